@@ -0,0 +1,209 @@
+//! Differential testing of the execution engines: a program is run by the
+//! AST interpreter, the Koopa IR interpreter and the RISC-V emulator on the
+//! same input, and the first one which does not behave like the first
+//! engine is reported, with the code it ran. A divergence of the Koopa IR
+//! interpreter points at the lowering in `traits.rs`, one of the emulator
+//! at the backend, which includes the backend panicking. An engine which
+//! panics diverges too, while one which runs out of steps or of stack does
+//! not decide anything.
+
+use crate::driver::{build, check, koopa_text, parse, riscv_text, verify, Diagnostics, Options};
+use crate::parser::asm::emu;
+use crate::parser::ast::interp as ast_interp;
+use crate::parser::interp;
+use crate::parser::runtime::{Outcome, RunError};
+use koopa::ir::Program;
+use std::fmt::{self, Display};
+use std::panic::{self, AssertUnwindSafe};
+
+/// An engine which runs programs.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Engine {
+    /// the AST interpreter
+    Ast,
+    /// the Koopa IR interpreter
+    Koopa,
+    /// the RISC-V emulator
+    Riscv,
+}
+
+impl Display for Engine {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Engine::Ast => write!(f, "AST interpreter"),
+            Engine::Koopa => write!(f, "Koopa IR interpreter"),
+            Engine::Riscv => write!(f, "RISC-V emulator"),
+        }
+    }
+}
+
+/// How an engine ran the program.
+#[derive(Debug)]
+pub struct Run {
+    pub engine: Engine,
+    pub result: Result<Outcome, RunError>,
+}
+
+/// The first engine which does not behave like the first one.
+#[derive(Debug)]
+pub struct Divergence {
+    pub engine: Engine,
+    /// the engine it is compared with, which ran first
+    pub reference: Engine,
+    /// what differs
+    pub reason: String,
+    /// Koopa IR or assembly the engine ran, `None` for the AST interpreter,
+    /// the Koopa IR if the backend panicked on it
+    pub code: Option<String>,
+}
+
+/// Results of every engine, in the order they ran.
+#[derive(Debug)]
+pub struct Report {
+    pub runs: Vec<Run>,
+    pub divergence: Option<Divergence>,
+}
+
+impl Report {
+    /// Whether every engine ran the program to the end, without running out
+    /// of steps or of stack.
+    pub fn conclusive(&self) -> bool {
+        self.runs.iter().all(|run| !matches!(run.result, Err(ref err) if exhausted(err)))
+    }
+}
+
+/// Runs the SysY source with every engine, for at most `step_limit` steps
+/// each. Errors of the source are returned as diagnostics, like errors of
+/// the IR verifier if `options.verify` is set.
+pub fn diff(source: &str, input: &[u8], step_limit: u64, options: &Options) -> Result<Report, Diagnostics> {
+    let unit = check(parse(source)?)?;
+    let ast = Run {
+        engine: Engine::Ast,
+        result: contain(|| ast_interp::run(&unit, input, step_limit)),
+    };
+    let program = build(unit)?;
+    if options.verify {
+        verify(&program)?;
+    }
+    Ok(diff_from(Some(ast), &program, input, step_limit))
+}
+
+/// Runs Koopa IR with the engines after the frontend.
+pub fn diff_koopa(program: &Program, input: &[u8], step_limit: u64) -> Report {
+    diff_from(None, program, input, step_limit)
+}
+
+fn diff_from(ast: Option<Run>, program: &Program, input: &[u8], step_limit: u64) -> Report {
+    let asm = backend(program);
+    let mut runs: Vec<Run> = ast.into_iter().collect();
+    runs.push(Run {
+        engine: Engine::Koopa,
+        result: contain(|| interp::run(program, input, step_limit)),
+    });
+    runs.push(Run {
+        engine: Engine::Riscv,
+        result: match &asm {
+            Ok(asm) => contain(|| emu::run(asm, input, step_limit)),
+            Err(message) => Err(RunError::Invalid(message.clone())),
+        },
+    });
+
+    let code = |engine| match engine {
+        Engine::Ast => None,
+        Engine::Koopa => Some(koopa_text(program)),
+        Engine::Riscv => Some(asm.clone().unwrap_or_else(|_| koopa_text(program))),
+    };
+    // the first engine is blamed for its own panic, not the ones compared
+    // with it
+    let divergence = match &runs[0].result {
+        Err(err @ RunError::Panic(_)) => Some(Divergence {
+            engine: runs[0].engine,
+            reference: runs[1].engine,
+            reason: format!("fails with \"{}\"", err),
+            code: code(runs[0].engine),
+        }),
+        _ => runs[1..].iter().find_map(|run| {
+            let reason = compare(&runs[0].result, &run.result)?;
+            Some(Divergence {
+                engine: run.engine,
+                reference: runs[0].engine,
+                reason,
+                code: code(run.engine),
+            })
+        }),
+    };
+    Report { runs, divergence }
+}
+
+/// Generates the assembly, a panic of the backend is returned as an error
+/// so that it is reported like any other divergence of the emulator.
+fn backend(program: &Program) -> Result<String, String> {
+    catch(|| riscv_text(program)).map_err(|message| format!("backend panicked: {}", message))
+}
+
+/// Runs an engine, its panic is returned as an error of the run.
+fn contain(run: impl FnOnce() -> Result<Outcome, RunError>) -> Result<Outcome, RunError> {
+    catch(run).unwrap_or_else(|message| Err(RunError::Panic(message)))
+}
+
+/// Calls `f`, with the message of its panic as the error.
+fn catch<T>(f: impl FnOnce() -> T) -> Result<T, String> {
+    panic::catch_unwind(AssertUnwindSafe(f)).map_err(|payload| {
+        match (payload.downcast_ref::<&str>(), payload.downcast_ref::<String>()) {
+            (Some(message), _) => message.to_string(),
+            (_, Some(message)) => message.clone(),
+            _ => "unknown panic".to_string(),
+        }
+    })
+}
+
+/// Whether the engine gave up on the program, rather than the program
+/// failing: steps or stack of one engine are not those of another.
+fn exhausted(err: &RunError) -> bool {
+    matches!(err, RunError::StepLimit(_) | RunError::StackOverflow(_))
+}
+
+/// Describes how `actual` differs from `expected`, `None` if the stdout and
+/// the exit code agree. Errors only agree with errors of the same kind, as
+/// their messages name functions differently in every engine, and panics
+/// agree with nothing. Running out of steps or of stack agrees with
+/// anything, as a step is a statement, a Koopa IR instruction or a machine
+/// instruction depending on the engine, and the stack of a call differs as
+/// much, so one engine may finish within the limit when another does not.
+fn compare(expected: &Result<Outcome, RunError>, actual: &Result<Outcome, RunError>) -> Option<String> {
+    match (expected, actual) {
+        (Err(err), _) | (_, Err(err)) if exhausted(err) => None,
+        (Ok(expected), Ok(actual)) => {
+            let mut reasons = Vec::new();
+            if expected.output != actual.output {
+                let pos = expected
+                    .output
+                    .iter()
+                    .zip(actual.output.iter())
+                    .position(|(e, a)| e != a)
+                    .unwrap_or_else(|| expected.output.len().min(actual.output.len()));
+                reasons.push(format!("stdout differs from byte {}", pos));
+            }
+            if expected.exit_code != actual.exit_code {
+                reasons.push(format!("exit code is {} instead of {}", actual.exit_code, expected.exit_code));
+            }
+            match reasons.is_empty() {
+                true => None,
+                false => Some(reasons.join(", ")),
+            }
+        }
+        (Err(expected), Err(actual))
+            if std::mem::discriminant(expected) == std::mem::discriminant(actual)
+                && !matches!(actual, RunError::Panic(_)) =>
+        {
+            None
+        }
+        (Err(expected), Err(actual)) => Some(format!("fails with \"{}\" instead of \"{}\"", actual, expected)),
+        (Ok(expected), Err(actual)) => {
+            Some(format!("fails with \"{}\" instead of exiting with {}", actual, expected.exit_code))
+        }
+        (Err(expected), Ok(actual)) => {
+            Some(format!("exits with {} instead of failing with \"{}\"", actual.exit_code, expected))
+        }
+    }
+}
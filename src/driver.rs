@@ -0,0 +1,161 @@
+//! The whole pipeline behind one call, for embedding the compiler in other
+//! Rust programs:
+//!
+//! ```no_run
+//! use compiler::{compile, Output, Stage};
+//!
+//! let source = "int main() { return 0; }";
+//! match compile(source, Stage::Riscv) {
+//!     Ok(Output::Riscv(asm)) => print!("{}", asm),
+//!     Ok(_) => unreachable!(),
+//!     Err(diags) => {
+//!         for diag in diags.iter() {
+//!             eprintln!("{}", diag.render("input.c", source));
+//!         }
+//!     }
+//! }
+//! ```
+
+use crate::parser::asm::visitor::{self, Visitor};
+use crate::parser::ast::check::{self, CheckedUnit};
+use crate::parser::ast::structs::CompUnit;
+use crate::parser::diagnostic::Diagnostic;
+use crate::parser::verify;
+use crate::sysy;
+use koopa::back::KoopaGenerator;
+use koopa::front::span::Span;
+use koopa::front::Driver;
+use koopa::ir::Program;
+use std::convert::TryFrom;
+use std::io;
+
+/// How far `compile` runs the pipeline, each stage includes the previous ones.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Stage {
+    /// parsed and checked syntax tree
+    Ast,
+    /// in-memory Koopa IR
+    Koopa,
+    /// Koopa IR in text form
+    KoopaText,
+    /// RISC-V assembly
+    Riscv,
+}
+
+/// Result of `compile`, the variant matches the requested stage.
+pub enum Output {
+    Ast(CheckedUnit),
+    Koopa(Program),
+    KoopaText(String),
+    Riscv(String),
+}
+
+/// Errors in the source, in the order they were found.
+pub type Diagnostics = Vec<Diagnostic>;
+
+/// Options of `compile_with`.
+#[derive(Debug, Clone, Copy)]
+pub struct Options {
+    /// verify the generated Koopa IR before using it, which is the default
+    /// in debug builds only
+    pub verify: bool,
+}
+
+impl Default for Options {
+    fn default() -> Self {
+        Options {
+            verify: cfg!(debug_assertions),
+        }
+    }
+}
+
+/// Compiles the SysY source up to the stage, with the default options.
+pub fn compile(source: &str, stage: Stage) -> Result<Output, Diagnostics> {
+    compile_with(source, stage, &Options::default())
+}
+
+/// Compiles the SysY source up to the stage.
+pub fn compile_with(source: &str, stage: Stage, options: &Options) -> Result<Output, Diagnostics> {
+    let unit = check(parse(source)?)?;
+    if stage == Stage::Ast {
+        return Ok(Output::Ast(unit));
+    }
+    let program = build(unit)?;
+    if options.verify {
+        verify(&program)?;
+    }
+    Ok(match stage {
+        Stage::Ast => unreachable!(),
+        Stage::Koopa => Output::Koopa(program),
+        Stage::KoopaText => Output::KoopaText(koopa_text(&program)),
+        Stage::Riscv => Output::Riscv(riscv_text(&program)),
+    })
+}
+
+/// Parses the source, syntax errors are recovered from and all reported.
+pub fn parse(source: &str) -> Result<CompUnit, Diagnostics> {
+    let mut errors = Vec::new();
+    let result = sysy::CompUnitParser::new().parse(source, &mut errors, source);
+    let mut diags: Diagnostics = errors.into_iter().map(|e| e.error.into()).collect();
+    match result {
+        Ok(unit) if diags.is_empty() => Ok(unit),
+        Ok(_) => Err(diags),
+        Err(err) => {
+            diags.push(err.into());
+            Err(diags)
+        }
+    }
+}
+
+/// Checks names, types and constants of the syntax tree.
+pub fn check(unit: CompUnit) -> Result<CheckedUnit, Diagnostics> {
+    check::check(unit).map_err(|errors| errors.into_iter().map(Diagnostic::from).collect())
+}
+
+/// Generates Koopa IR for the checked syntax tree.
+pub fn build(unit: CheckedUnit) -> Result<Program, Diagnostics> {
+    Program::try_from(unit).map_err(|err| vec![err.into()])
+}
+
+/// Verifies the Koopa IR, errors in it are bugs of the compiler rather than
+/// of the source, so they have no location.
+pub fn verify(program: &Program) -> Result<(), Diagnostics> {
+    verify::verify(program).map_err(|errors| {
+        errors
+            .into_iter()
+            .map(|err| Diagnostic::without_span(err.to_string()))
+            .collect()
+    })
+}
+
+/// Reads Koopa IR text from the file, so that the backend can run on it
+/// alone. The Koopa parser prints every error with its location to stderr
+/// itself, so only the number of errors is returned. IR the backend does
+/// not support is reported the same way.
+pub fn load_koopa(path: &str) -> io::Result<Result<Program, usize>> {
+    let program = match Driver::from_path(path)?.generate_program() {
+        Ok(program) => program,
+        Err(_) => return Ok(Err(Span::error_num().max(1))),
+    };
+    match visitor::unsupported(&program) {
+        Some(message) => {
+            eprintln!("error: {}", message);
+            Ok(Err(1))
+        }
+        None => Ok(Ok(program)),
+    }
+}
+
+/// Converts the program to Koopa IR text.
+pub fn koopa_text(program: &Program) -> String {
+    let mut gen = KoopaGenerator::new(Vec::new());
+    gen.generate_on(program).expect("writing to a Vec never fails");
+    String::from_utf8(gen.writer()).unwrap()
+}
+
+/// Generates RISC-V assembly for the program.
+pub fn riscv_text(program: &Program) -> String {
+    let mut asm = Vec::new();
+    Visitor.visit(&mut asm, program).expect("writing to a Vec never fails");
+    String::from_utf8(asm).unwrap()
+}
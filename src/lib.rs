@@ -1 +1,11 @@
+use lalrpop_util::lalrpop_mod;
+
+pub mod diff;
+pub mod driver;
 pub mod parser;
+
+pub use driver::{compile, compile_with, Diagnostics, Options, Output, Stage};
+
+// 引用 lalrpop 生成的解析器
+// 因为我们刚刚创建了 sysy.lalrpop, 所以模块名是 sysy
+lalrpop_mod!(#[allow(clippy::all)] pub sysy);
@@ -1,54 +1,258 @@
-use compiler::parser::asm::visitor::Visitor;
-use koopa::back::KoopaGenerator;
-use lalrpop_util::lalrpop_mod;
+use compiler::diff::{self, Report};
+use compiler::parser::diagnostic::Diagnostic;
+use compiler::driver::{koopa_text, load_koopa, riscv_text, verify};
+use compiler::parser::asm::emu;
+use compiler::parser::ast::interp as ast_interp;
+use compiler::parser::interp;
+use compiler::parser::runtime::{Outcome, RunError, DEFAULT_STEP_LIMIT};
+use compiler::{compile_with, Options, Output, Stage};
 use std::env::args;
 use std::fs::{read_to_string, File};
-use std::io::{Result, Write};
-
-// 引用 lalrpop 生成的解析器
-// 因为我们刚刚创建了 sysy.lalrpop, 所以模块名是 sysy
-
-lalrpop_mod!(sysy);
+use std::io::{stdin, stdout, Read, Result, Write};
 
 fn main() -> Result<()> {
     // 解析命令行参数
-    let (mode, input, output) = parse_args();
-    // 读取输入文件
-    let input = read_to_string(input)?;
-    // 调用 lalrpop 生成的 parser 解析输入文件
-    let ast = sysy::CompUnitParser::new().parse(&dbg!(input)).unwrap();
-    let program = ast.into();
-    let mut file = File::create(output)?;
-    let text;
-    match mode.as_str() {
-        "-koopa" => {
-            // convert to text form
-            let mut gen = KoopaGenerator::new(Vec::new());
-            gen.generate_on(&program)?;
-            text = std::str::from_utf8(&gen.writer()).unwrap().to_string();
-            // println!("{}", text_form_ir);
-        }
-        "-riscv" => {
-            let mut asm_visitor = Visitor::default();
-            let mut riscv_code = Vec::new();
-            asm_visitor.visit(&mut riscv_code, &program)?;
-            text = String::from_utf8(riscv_code).unwrap();
-        }
-        _ => {
-            panic!("Not implement")
-        }
-    }
-    write!(file, "{}", text)?;
+    let args = match parse_args() {
+        Some(args) => args,
+        None => {
+            eprintln!("usage: compiler (-koopa | -riscv) <input> -o <output> [-max-errors <n>] [-verify]");
+            eprintln!("       compiler (-interp | -interp-ast | -emu | -diff) <input> [-steps <n>] [-max-errors <n>] [-verify]");
+            eprintln!("       an input ending with `.koopa` is Koopa IR and skips the frontend");
+            eprintln!("       an input ending with `.s` is RISC-V assembly, which only `-emu` runs");
+            std::process::exit(2);
+        }
+    };
+    if let Mode::InterpAst = args.mode {
+        // 直接解释执行语法树, 不生成 Koopa IR
+        let input = read_to_string(&args.input)?;
+        let unit = match compile_with(&input, Stage::Ast, &args.options) {
+            Ok(Output::Ast(unit)) => unit,
+            Ok(_) => unreachable!(),
+            Err(diags) => report(&args.input, &input, diags, args.max_errors),
+        };
+        let stdin = read_stdin()?;
+        return finish(ast_interp::run(&unit, &stdin, args.step_limit));
+    }
+    if let (Mode::Diff, false) = (args.mode, args.input.ends_with(".koopa")) {
+        // 依次用语法树解释器, Koopa IR 解释器和 RISC-V 模拟器运行
+        let input = read_to_string(&args.input)?;
+        let stdin = read_stdin()?;
+        match diff::diff(&input, &stdin, args.step_limit, &args.options) {
+            Ok(report) => print_report(&report),
+            Err(diags) => report(&args.input, &input, diags, args.max_errors),
+        }
+    }
+    if args.input.ends_with(".s") {
+        // 汇编输入直接在模拟器上运行
+        let asm = read_to_string(&args.input)?;
+        let stdin = read_stdin()?;
+        return finish(emu::run(&asm, &stdin, args.step_limit).map(report_instructions));
+    }
+
+    let program = if args.input.ends_with(".koopa") {
+        // Koopa IR 输入只经过后端, 错误由 Koopa 的解析器直接打印
+        let program = match load_koopa(&args.input)? {
+            Ok(program) => program,
+            Err(errors) => abort(errors),
+        };
+        if args.options.verify {
+            if let Err(diags) = verify(&program) {
+                report(&args.input, "", diags, args.max_errors);
+            }
+        }
+        program
+    } else {
+        // 读取输入文件
+        let input = read_to_string(&args.input)?;
+        // 解析, 检查并生成 Koopa IR, 出错时报告所有错误
+        match compile_with(&input, Stage::Koopa, &args.options) {
+            Ok(Output::Koopa(program)) => program,
+            Ok(_) => unreachable!(),
+            Err(diags) => report(&args.input, &input, diags, args.max_errors),
+        }
+    };
+
+    match args.mode {
+        Mode::Koopa | Mode::Riscv => {
+            let text = match args.mode {
+                Mode::Riscv => riscv_text(&program),
+                _ => koopa_text(&program),
+            };
+            let mut file = File::create(args.output.unwrap())?;
+            write!(file, "{}", text)?;
+        }
+        Mode::Interp => {
+            let input = read_stdin()?;
+            finish(interp::run(&program, &input, args.step_limit))?;
+        }
+        Mode::Emulate => {
+            let input = read_stdin()?;
+            finish(emu::run(&riscv_text(&program), &input, args.step_limit).map(report_instructions))?;
+        }
+        Mode::Diff => {
+            let input = read_stdin()?;
+            print_report(&diff::diff_koopa(&program, &input, args.step_limit));
+        }
+        Mode::InterpAst => unreachable!(),
+    }
 
     Ok(())
 }
 
-fn parse_args() -> (String, String, String) {
+fn read_stdin() -> Result<Vec<u8>> {
+    let mut input = Vec::new();
+    stdin().read_to_end(&mut input)?;
+    Ok(input)
+}
+
+/// Prints the dynamic instruction count of an emulated program to stderr.
+fn report_instructions(outcome: Outcome) -> Outcome {
+    eprintln!("instructions: {}", outcome.steps);
+    outcome
+}
+
+/// Prints how every engine ran the program, and the code of the first one
+/// which diverges. Exits with a non-zero status if one diverges.
+fn print_report(report: &Report) -> ! {
+    for run in report.runs.iter() {
+        match &run.result {
+            Ok(outcome) => println!(
+                "{}: exit code {}, {} bytes of stdout, {} steps",
+                run.engine,
+                outcome.exit_code,
+                outcome.output.len(),
+                outcome.steps
+            ),
+            Err(err) => println!("{}: error: {}", run.engine, err),
+        }
+    }
+    match &report.divergence {
+        Some(divergence) => {
+            println!(
+                "{} diverges from {}: {}",
+                divergence.engine, divergence.reference, divergence.reason
+            );
+            if let Some(code) = &divergence.code {
+                print!("\n{}", code);
+            }
+            std::process::exit(1);
+        }
+        None => {
+            match report.conclusive() {
+                true => println!("all engines agree"),
+                false => println!("no divergence found, but not every engine finished"),
+            }
+            std::process::exit(0);
+        }
+    }
+}
+
+/// Writes the output of a program which was run and exits with its exit
+/// code, the program's input and output are stdin and stdout.
+fn finish(result: std::result::Result<Outcome, RunError>) -> Result<()> {
+    match result {
+        Ok(outcome) => {
+            stdout().write_all(&outcome.output)?;
+            stdout().flush()?;
+            std::process::exit(outcome.exit_code);
+        }
+        Err(err) => {
+            eprintln!("error: {}", err);
+            std::process::exit(1);
+        }
+    }
+}
+
+/// Number of errors reported by default, `-max-errors` changes it.
+const MAX_ERRORS: usize = 20;
+
+/// What to do with the input.
+#[derive(Clone, Copy)]
+enum Mode {
+    /// write Koopa IR text
+    Koopa,
+    /// write RISC-V assembly
+    Riscv,
+    /// run it with the Koopa IR interpreter
+    Interp,
+    /// run it with the AST interpreter, without generating Koopa IR
+    InterpAst,
+    /// run the generated RISC-V assembly with the emulator
+    Emulate,
+    /// run it with every engine and compare them
+    Diff,
+}
+
+struct Args {
+    mode: Mode,
+    input: String,
+    /// output file, required unless the program is run
+    output: Option<String>,
+    max_errors: usize,
+    options: Options,
+    /// number of steps the program is run for at most
+    step_limit: u64,
+}
+
+/// Parses `mode input` followed by the flags, `-verify` turns on the Koopa
+/// IR verifier in release builds.
+fn parse_args() -> Option<Args> {
     let mut args = args();
     args.next();
-    let mode = args.next().unwrap();
-    let input = args.next().unwrap();
-    args.next();
-    let output = args.next().unwrap();
-    (mode, input, output)
+    let mode = match args.next()?.as_str() {
+        "-koopa" => Mode::Koopa,
+        "-riscv" => Mode::Riscv,
+        "-interp" => Mode::Interp,
+        "-interp-ast" => Mode::InterpAst,
+        "-emu" => Mode::Emulate,
+        "-diff" => Mode::Diff,
+        _ => return None,
+    };
+    let mut parsed = Args {
+        mode,
+        input: args.next()?,
+        output: None,
+        max_errors: MAX_ERRORS,
+        options: Options::default(),
+        step_limit: DEFAULT_STEP_LIMIT,
+    };
+    while let Some(flag) = args.next() {
+        match flag.as_str() {
+            "-o" => parsed.output = Some(args.next()?),
+            "-max-errors" => parsed.max_errors = args.next()?.parse().ok()?,
+            "-verify" => parsed.options.verify = true,
+            "-steps" => parsed.step_limit = args.next()?.parse().ok()?,
+            _ => return None,
+        }
+    }
+    match (mode, &parsed.output) {
+        (Mode::Koopa, None) | (Mode::Riscv, None) => None,
+        // Koopa IR 没有语法树
+        (Mode::InterpAst, _) if parsed.input.ends_with(".koopa") => None,
+        (Mode::Emulate, _) => Some(parsed),
+        _ if parsed.input.ends_with(".s") => None,
+        _ => Some(parsed),
+    }
+}
+
+/// Prints at most `max_errors` diagnostics in source order, the ones
+/// without a location last, and exits with a non-zero status.
+fn report(path: &str, src: &str, mut diags: Vec<Diagnostic>, max_errors: usize) -> ! {
+    diags.sort_by_key(|diag| diag.span.map_or(usize::MAX, |span| span.start));
+    for diag in diags.iter().take(max_errors) {
+        eprintln!("{}", diag.render(path, src));
+    }
+    if diags.len() > max_errors {
+        eprintln!("error: too many errors, only the first {} are shown", max_errors);
+    }
+    abort(diags.len())
+}
+
+/// Exits with a non-zero status after the errors have been printed.
+fn abort(errors: usize) -> ! {
+    match errors {
+        1 => eprintln!("error: aborting due to previous error"),
+        n => eprintln!("error: aborting due to {} previous errors", n),
+    }
+    std::process::exit(1);
 }
@@ -0,0 +1,789 @@
+//! Emulator for the RISC-V assembly produced by `Visitor`, it assembles the
+//! text and runs `main` on an RV32IM hart, with the SysY runtime library
+//! provided by the host, so the backend can be tested without a toolchain.
+//!
+//! The assembler accepts RV32IM, the usual pseudo-instructions and the
+//! `.text`, `.data`, `.bss`, `.word`, `.zero` and `.align` directives.
+//! Pseudo-instructions expand to what an assembler emits for them (`li` of
+//! a large immediate is `lui` and `addi`, `la` is `lui` and `addi`), except
+//! that `call` is a single `jal` as after linker relaxation. A call of a
+//! function of the runtime library is one instruction too, which sets `ra`
+//! and leaves garbage in the other caller-saved registers, as the compiled
+//! library may do, so that values kept in them across a call are caught.
+//!
+//! Memory is the data and bss of the program and a stack below
+//! `STACK_TOP`, every access outside them is an error.
+
+use crate::parser::fold;
+use crate::parser::runtime::{Outcome, RunError, Runtime};
+use koopa::ir::BinaryOp;
+use std::collections::HashMap;
+
+/// Address of the first instruction.
+const TEXT_BASE: u32 = 0x0001_0000;
+/// Address of the data, the bss follows it.
+const DATA_BASE: u32 = 0x1000_0000;
+/// Initial `sp`, the stack grows down from it.
+const STACK_TOP: u32 = 0x8000_0000;
+const STACK_SIZE: u32 = 64 << 20;
+/// Return address of `main`, returning to it ends the program.
+const EXIT_ADDR: u32 = 0;
+/// `t0` to `t6` and `a1` to `a7`, which a call of the runtime library may
+/// overwrite, `a0` too unless it holds the result.
+const CALLER_SAVED: [Reg; 14] = [5, 6, 7, 28, 29, 30, 31, 11, 12, 13, 14, 15, 16, 17];
+/// Garbage left in the caller-saved registers by the runtime library, the
+/// number of the register is xored in so that no two are equal.
+const GARBAGE: i32 = 0xdead_be00_u32 as i32;
+
+/// Assembles the text and runs `main` on the input, for at most
+/// `step_limit` instructions. `steps` of the outcome is the number of
+/// instructions executed.
+pub fn run(asm: &str, input: &[u8], step_limit: u64) -> Result<Outcome, RunError> {
+    let image = assemble(asm)?;
+    let mut hart = Hart::new(&image, input, step_limit);
+    let exit_code = hart.run()?;
+    Ok(Outcome {
+        exit_code,
+        output: hart.runtime.into_output(),
+        steps: hart.steps,
+    })
+}
+
+/// Operations of the ALU, for both register and immediate operands.
+#[derive(Debug, Clone, Copy)]
+enum AluOp {
+    Add,
+    Sub,
+    Sll,
+    Slt,
+    Sltu,
+    Xor,
+    Srl,
+    Sra,
+    Or,
+    And,
+    Mul,
+    Mulh,
+    Mulhsu,
+    Mulhu,
+    Div,
+    Divu,
+    Rem,
+    Remu,
+}
+
+#[derive(Debug, Clone, Copy)]
+enum Cond {
+    Eq,
+    Ne,
+    Lt,
+    Ge,
+    Ltu,
+    Geu,
+}
+
+/// Width of a memory access in bytes, and whether a load sign-extends.
+#[derive(Debug, Clone, Copy)]
+struct Width {
+    bytes: u32,
+    signed: bool,
+}
+
+/// Functions of the SysY runtime library.
+#[derive(Debug, Clone, Copy)]
+enum HostFn {
+    GetInt,
+    GetCh,
+    GetArray,
+    PutInt,
+    PutCh,
+    PutArray,
+    StartTime,
+    StopTime,
+}
+
+impl HostFn {
+    fn from_name(name: &str) -> Option<HostFn> {
+        Some(match name {
+            "getint" => HostFn::GetInt,
+            "getch" => HostFn::GetCh,
+            "getarray" => HostFn::GetArray,
+            "putint" => HostFn::PutInt,
+            "putch" => HostFn::PutCh,
+            "putarray" => HostFn::PutArray,
+            "_sysy_starttime" => HostFn::StartTime,
+            "_sysy_stoptime" => HostFn::StopTime,
+            _ => return None,
+        })
+    }
+}
+
+type Reg = usize;
+
+/// An assembled instruction, branch and jump targets are addresses.
+#[derive(Debug, Clone, Copy)]
+enum Inst {
+    Op(AluOp, Reg, Reg, Reg),
+    OpImm(AluOp, Reg, Reg, i32),
+    Lui(Reg, i32),
+    Auipc(Reg, i32),
+    Load(Width, Reg, Reg, i32),
+    Store(Width, Reg, Reg, i32),
+    Branch(Cond, Reg, Reg, u32),
+    Jal(Reg, u32),
+    Jalr(Reg, Reg, i32),
+    /// call of the runtime library, which returns right away
+    Host(HostFn),
+}
+
+/// Part of an instruction which refers to a symbol.
+#[derive(Debug, Clone, Copy)]
+enum Fixup {
+    /// branch or jump target
+    Target,
+    /// upper 20 bits of the address, for `lui`
+    Hi,
+    /// lower 12 bits of the address, for `addi`
+    Lo,
+}
+
+/// An assembled program.
+struct Image {
+    text: Vec<Inst>,
+    /// initial data and zeroed bss, at `DATA_BASE`
+    data: Vec<u8>,
+    entry: u32,
+}
+
+#[derive(PartialEq, Eq)]
+enum Section {
+    Text,
+    Data,
+    Bss,
+}
+
+fn error<T>(line: usize, message: String) -> Result<T, RunError> {
+    Err(RunError::Invalid(format!("line {}: {}", line, message)))
+}
+
+/// Splits an address into the `lui` and `addi` immediates which build it.
+fn hi_lo(value: i32) -> (i32, i32) {
+    let lo = (value << 20) >> 20;
+    (value.wrapping_sub(lo) >> 12, lo)
+}
+
+fn is_imm12(i: i32) -> bool {
+    (-2048..=2047).contains(&i)
+}
+
+fn reg(name: &str) -> Option<Reg> {
+    const ABI: [&str; 32] = [
+        "zero", "ra", "sp", "gp", "tp", "t0", "t1", "t2", "s0", "s1", "a0", "a1", "a2", "a3", "a4", "a5", "a6",
+        "a7", "s2", "s3", "s4", "s5", "s6", "s7", "s8", "s9", "s10", "s11", "t3", "t4", "t5", "t6",
+    ];
+    if name == "fp" {
+        return Some(8);
+    }
+    if let Some(r) = ABI.iter().position(|&abi| abi == name) {
+        return Some(r);
+    }
+    match name.strip_prefix('x')?.parse() {
+        Ok(r) if r < 32 => Some(r),
+        _ => None,
+    }
+}
+
+fn imm(text: &str) -> Option<i32> {
+    let (negative, digits) = match text.strip_prefix('-') {
+        Some(digits) => (true, digits),
+        None => (false, text),
+    };
+    let value = match digits.strip_prefix("0x").or_else(|| digits.strip_prefix("0X")) {
+        Some(hex) => i64::from_str_radix(hex, 16).ok()?,
+        None => digits.parse::<i64>().ok()?,
+    };
+    let value = if negative { -value } else { value };
+    // accept both signed and unsigned 32-bit values, like assemblers do
+    if (i32::MIN as i64..=u32::MAX as i64).contains(&value) {
+        Some(value as i32)
+    } else {
+        None
+    }
+}
+
+/// Assembler state, instructions which refer to symbols are fixed up once
+/// every label is known.
+struct Assembler {
+    text: Vec<Inst>,
+    data: Vec<u8>,
+    bss: u32,
+    section: Section,
+    /// labels in the text, as instruction indices
+    text_labels: HashMap<String, usize>,
+    /// labels in the data and bss, as offsets in their section
+    data_labels: HashMap<String, (Section, u32)>,
+    fixups: Vec<(usize, Fixup, String, usize)>,
+    line: usize,
+}
+
+fn assemble(asm: &str) -> Result<Image, RunError> {
+    let mut assembler = Assembler {
+        text: Vec::new(),
+        data: Vec::new(),
+        bss: 0,
+        section: Section::Text,
+        text_labels: HashMap::new(),
+        data_labels: HashMap::new(),
+        fixups: Vec::new(),
+        line: 0,
+    };
+    for (i, line) in asm.lines().enumerate() {
+        assembler.line = i + 1;
+        let line = line.split('#').next().unwrap().trim();
+        assembler.statement(line)?;
+    }
+    assembler.finish()
+}
+
+impl Assembler {
+    fn error<T>(&self, message: String) -> Result<T, RunError> {
+        error(self.line, message)
+    }
+
+    fn statement(&mut self, mut line: &str) -> Result<(), RunError> {
+        // labels, possibly several before a statement
+        while let Some(colon) = line.find(':') {
+            let label = line[..colon].trim();
+            if label.is_empty() || !label.chars().all(|c| c.is_ascii_alphanumeric() || "_.$".contains(c)) {
+                break;
+            }
+            self.label(label)?;
+            line = line[colon + 1..].trim();
+        }
+        if line.is_empty() {
+            return Ok(());
+        }
+        let (op, rest) = match line.find(char::is_whitespace) {
+            Some(pos) => (&line[..pos], line[pos..].trim()),
+            None => (line, ""),
+        };
+        let args: Vec<&str> = match rest {
+            "" => Vec::new(),
+            _ => rest.split(',').map(str::trim).collect(),
+        };
+        if op.starts_with('.') {
+            self.directive(op, &args)
+        } else if self.section == Section::Text {
+            self.inst(op, &args)
+        } else {
+            self.error(format!("instruction `{}` outside of `.text`", op))
+        }
+    }
+
+    fn label(&mut self, label: &str) -> Result<(), RunError> {
+        if self.text_labels.contains_key(label) || self.data_labels.contains_key(label) {
+            return self.error(format!("label `{}` defined twice", label));
+        }
+        match self.section {
+            Section::Text => {
+                self.text_labels.insert(label.to_string(), self.text.len());
+            }
+            Section::Data => {
+                self.data_labels.insert(label.to_string(), (Section::Data, self.data.len() as u32));
+            }
+            Section::Bss => {
+                self.data_labels.insert(label.to_string(), (Section::Bss, self.bss));
+            }
+        }
+        Ok(())
+    }
+
+    fn directive(&mut self, op: &str, args: &[&str]) -> Result<(), RunError> {
+        match (op, args) {
+            (".text", []) => self.section = Section::Text,
+            (".data", []) | (".rodata", []) => self.section = Section::Data,
+            (".bss", []) => self.section = Section::Bss,
+            (".section", [name, ..]) => {
+                self.section = match *name {
+                    ".text" => Section::Text,
+                    ".bss" | ".sbss" => Section::Bss,
+                    _ => Section::Data,
+                }
+            }
+            (".global", _) | (".globl", _) | (".type", _) | (".size", _) | (".file", _) => (),
+            (".word", values) if self.section == Section::Data => {
+                for value in values.iter() {
+                    match imm(value) {
+                        Some(value) => self.data.extend(value.to_le_bytes()),
+                        None => return self.error(format!("invalid word `{}`", value)),
+                    }
+                }
+            }
+            (".zero", [size]) | (".space", [size]) => {
+                let size = match imm(size) {
+                    Some(size) if size >= 0 => size as u32,
+                    _ => return self.error(format!("invalid size `{}`", size)),
+                };
+                match self.section {
+                    Section::Data => self.data.resize(self.data.len() + size as usize, 0),
+                    Section::Bss => self.bss += size,
+                    Section::Text => return self.error("`.zero` in `.text`".into()),
+                }
+            }
+            (".align", [align]) | (".p2align", [align]) => {
+                let align = match imm(align) {
+                    Some(align) if (0..16).contains(&align) => 1u32 << align,
+                    _ => return self.error(format!("invalid alignment `{}`", align)),
+                };
+                match self.section {
+                    Section::Data => {
+                        let len = (self.data.len() as u32).next_multiple_of(align);
+                        self.data.resize(len as usize, 0);
+                    }
+                    Section::Bss => self.bss = self.bss.next_multiple_of(align),
+                    // instructions are always aligned
+                    Section::Text => (),
+                }
+            }
+            _ => return self.error(format!("unsupported directive `{}`", op)),
+        }
+        Ok(())
+    }
+
+    fn reg(&self, text: &str) -> Result<Reg, RunError> {
+        match reg(text) {
+            Some(r) => Ok(r),
+            None => self.error(format!("invalid register `{}`", text)),
+        }
+    }
+
+    fn imm(&self, text: &str) -> Result<i32, RunError> {
+        match imm(text) {
+            Some(i) => Ok(i),
+            None => self.error(format!("invalid immediate `{}`", text)),
+        }
+    }
+
+    fn imm12(&self, text: &str) -> Result<i32, RunError> {
+        match self.imm(text)? {
+            i if is_imm12(i) => Ok(i),
+            _ => self.error(format!("immediate `{}` out of range", text)),
+        }
+    }
+
+    /// Parses `offset(reg)`.
+    fn mem(&self, text: &str) -> Result<(i32, Reg), RunError> {
+        let (offset, base) = match text.strip_suffix(')').and_then(|text| text.split_once('(')) {
+            Some(parts) => parts,
+            None => return self.error(format!("invalid memory operand `{}`", text)),
+        };
+        let offset = match offset.trim() {
+            "" => 0,
+            offset => self.imm12(offset)?,
+        };
+        Ok((offset, self.reg(base.trim())?))
+    }
+
+    /// Emits an instruction which refers to the symbol.
+    fn emit_fixup(&mut self, inst: Inst, fixup: Fixup, symbol: &str) {
+        self.fixups.push((self.text.len(), fixup, symbol.to_string(), self.line));
+        self.text.push(inst);
+    }
+
+    fn inst(&mut self, op: &str, args: &[&str]) -> Result<(), RunError> {
+        let alu = |op: &str| -> Option<AluOp> {
+            Some(match op {
+                "add" => AluOp::Add,
+                "sub" => AluOp::Sub,
+                "sll" => AluOp::Sll,
+                "slt" => AluOp::Slt,
+                "sltu" => AluOp::Sltu,
+                "xor" => AluOp::Xor,
+                "srl" => AluOp::Srl,
+                "sra" => AluOp::Sra,
+                "or" => AluOp::Or,
+                "and" => AluOp::And,
+                "mul" => AluOp::Mul,
+                "mulh" => AluOp::Mulh,
+                "mulhsu" => AluOp::Mulhsu,
+                "mulhu" => AluOp::Mulhu,
+                "div" => AluOp::Div,
+                "divu" => AluOp::Divu,
+                "rem" => AluOp::Rem,
+                "remu" => AluOp::Remu,
+                _ => return None,
+            })
+        };
+        let alu_imm = |op: &str| -> Option<AluOp> {
+            Some(match op {
+                "addi" => AluOp::Add,
+                "slti" => AluOp::Slt,
+                "sltiu" => AluOp::Sltu,
+                "xori" => AluOp::Xor,
+                "ori" => AluOp::Or,
+                "andi" => AluOp::And,
+                "slli" => AluOp::Sll,
+                "srli" => AluOp::Srl,
+                "srai" => AluOp::Sra,
+                _ => return None,
+            })
+        };
+        let width = |op: &str| -> Option<Width> {
+            let (bytes, signed) = match op {
+                "lb" | "sb" => (1, true),
+                "lh" | "sh" => (2, true),
+                "lw" | "sw" => (4, true),
+                "lbu" => (1, false),
+                "lhu" => (2, false),
+                _ => return None,
+            };
+            Some(Width { bytes, signed })
+        };
+        let cond = |op: &str| -> Option<(Cond, bool)> {
+            // the condition, and whether the operands are swapped
+            Some(match op {
+                "beq" => (Cond::Eq, false),
+                "bne" => (Cond::Ne, false),
+                "blt" => (Cond::Lt, false),
+                "bge" => (Cond::Ge, false),
+                "bltu" => (Cond::Ltu, false),
+                "bgeu" => (Cond::Geu, false),
+                "bgt" => (Cond::Lt, true),
+                "ble" => (Cond::Ge, true),
+                "bgtu" => (Cond::Ltu, true),
+                "bleu" => (Cond::Geu, true),
+                _ => return None,
+            })
+        };
+        let cond_zero = |op: &str| -> Option<(Cond, bool)> {
+            Some(match op {
+                "beqz" => (Cond::Eq, false),
+                "bnez" => (Cond::Ne, false),
+                "bltz" => (Cond::Lt, false),
+                "bgez" => (Cond::Ge, false),
+                "bgtz" => (Cond::Lt, true),
+                "blez" => (Cond::Ge, true),
+                _ => return None,
+            })
+        };
+
+        let inst = match (op, args) {
+            (op, [rd, rs1, rs2]) if alu(op).is_some() => {
+                Inst::Op(alu(op).unwrap(), self.reg(rd)?, self.reg(rs1)?, self.reg(rs2)?)
+            }
+            (op, [rd, rs1, i]) if alu_imm(op).is_some() => {
+                let op = alu_imm(op).unwrap();
+                let i = match op {
+                    AluOp::Sll | AluOp::Srl | AluOp::Sra => match self.imm(i)? {
+                        shamt @ 0..=31 => shamt,
+                        _ => return self.error(format!("shift amount `{}` out of range", i)),
+                    },
+                    _ => self.imm12(i)?,
+                };
+                Inst::OpImm(op, self.reg(rd)?, self.reg(rs1)?, i)
+            }
+            ("lui", [rd, i]) => Inst::Lui(self.reg(rd)?, self.imm(i)? & 0xfffff),
+            ("auipc", [rd, i]) => Inst::Auipc(self.reg(rd)?, self.imm(i)? & 0xfffff),
+            (op, [rd, mem]) if op.starts_with('l') && width(op).is_some() => {
+                let (offset, base) = self.mem(mem)?;
+                Inst::Load(width(op).unwrap(), self.reg(rd)?, base, offset)
+            }
+            (op, [rs, mem]) if op.starts_with('s') && width(op).is_some() => {
+                let (offset, base) = self.mem(mem)?;
+                Inst::Store(width(op).unwrap(), self.reg(rs)?, base, offset)
+            }
+            (op, [rs1, rs2, target]) if cond(op).is_some() => {
+                let (cond, swap) = cond(op).unwrap();
+                let (rs1, rs2) = (self.reg(rs1)?, self.reg(rs2)?);
+                let (rs1, rs2) = if swap { (rs2, rs1) } else { (rs1, rs2) };
+                return self.branch(Inst::Branch(cond, rs1, rs2, 0), target);
+            }
+            (op, [rs, target]) if cond_zero(op).is_some() => {
+                let (cond, swap) = cond_zero(op).unwrap();
+                let rs = self.reg(rs)?;
+                let (rs1, rs2) = if swap { (0, rs) } else { (rs, 0) };
+                return self.branch(Inst::Branch(cond, rs1, rs2, 0), target);
+            }
+            ("jal", [target]) => return self.branch(Inst::Jal(1, 0), target),
+            ("jal", [rd, target]) => {
+                let rd = self.reg(rd)?;
+                return self.branch(Inst::Jal(rd, 0), target);
+            }
+            ("jalr", [rs]) => Inst::Jalr(1, self.reg(rs)?, 0),
+            ("jalr", [rd, mem]) if mem.ends_with(')') => {
+                let (offset, base) = self.mem(mem)?;
+                Inst::Jalr(self.reg(rd)?, base, offset)
+            }
+            ("jalr", [rd, rs, i]) => Inst::Jalr(self.reg(rd)?, self.reg(rs)?, self.imm12(i)?),
+            ("j", [target]) => return self.branch(Inst::Jal(0, 0), target),
+            ("jr", [rs]) => Inst::Jalr(0, self.reg(rs)?, 0),
+            ("ret", []) => Inst::Jalr(0, 1, 0),
+            // the callee is a host function if it is not defined
+            ("call", [target]) => return self.branch(Inst::Jal(1, 0), target),
+            ("tail", [target]) => return self.branch(Inst::Jal(0, 0), target),
+            ("nop", []) => Inst::OpImm(AluOp::Add, 0, 0, 0),
+            ("mv", [rd, rs]) => Inst::OpImm(AluOp::Add, self.reg(rd)?, self.reg(rs)?, 0),
+            ("not", [rd, rs]) => Inst::OpImm(AluOp::Xor, self.reg(rd)?, self.reg(rs)?, -1),
+            ("neg", [rd, rs]) => Inst::Op(AluOp::Sub, self.reg(rd)?, 0, self.reg(rs)?),
+            ("seqz", [rd, rs]) => Inst::OpImm(AluOp::Sltu, self.reg(rd)?, self.reg(rs)?, 1),
+            ("snez", [rd, rs]) => Inst::Op(AluOp::Sltu, self.reg(rd)?, 0, self.reg(rs)?),
+            ("sltz", [rd, rs]) => Inst::Op(AluOp::Slt, self.reg(rd)?, self.reg(rs)?, 0),
+            ("sgtz", [rd, rs]) => Inst::Op(AluOp::Slt, self.reg(rd)?, 0, self.reg(rs)?),
+            ("li", [rd, i]) => {
+                let (rd, i) = (self.reg(rd)?, self.imm(i)?);
+                if is_imm12(i) {
+                    Inst::OpImm(AluOp::Add, rd, 0, i)
+                } else {
+                    let (hi, lo) = hi_lo(i);
+                    self.text.push(Inst::Lui(rd, hi & 0xfffff));
+                    if lo == 0 {
+                        return Ok(());
+                    }
+                    Inst::OpImm(AluOp::Add, rd, rd, lo)
+                }
+            }
+            ("la", [rd, symbol]) => {
+                let rd = self.reg(rd)?;
+                self.emit_fixup(Inst::Lui(rd, 0), Fixup::Hi, symbol);
+                self.emit_fixup(Inst::OpImm(AluOp::Add, rd, rd, 0), Fixup::Lo, symbol);
+                return Ok(());
+            }
+            _ => return self.error(format!("invalid instruction `{} {}`", op, args.join(", "))),
+        };
+        self.text.push(inst);
+        Ok(())
+    }
+
+    fn branch(&mut self, inst: Inst, target: &str) -> Result<(), RunError> {
+        self.emit_fixup(inst, Fixup::Target, target);
+        Ok(())
+    }
+
+    /// Address of the symbol.
+    fn symbol(&self, name: &str) -> Option<u32> {
+        if let Some(&index) = self.text_labels.get(name) {
+            return Some(TEXT_BASE + 4 * index as u32);
+        }
+        self.data_labels.get(name).map(|(section, offset)| match section {
+            Section::Bss => DATA_BASE + self.data.len().next_multiple_of(4) as u32 + offset,
+            _ => DATA_BASE + offset,
+        })
+    }
+
+    /// Resolves the symbols, and lays out the bss after the data.
+    fn finish(mut self) -> Result<Image, RunError> {
+        for (index, fixup, name, line) in std::mem::take(&mut self.fixups) {
+            let addr = match self.symbol(&name) {
+                Some(addr) => addr,
+                None => match (self.text[index], HostFn::from_name(&name)) {
+                    (Inst::Jal(1, _), Some(func)) => {
+                        self.text[index] = Inst::Host(func);
+                        continue;
+                    }
+                    _ => return error(line, format!("undefined symbol `{}`", name)),
+                },
+            };
+            let (hi, lo) = hi_lo(addr as i32);
+            self.text[index] = match (self.text[index], fixup) {
+                (Inst::Branch(cond, rs1, rs2, _), Fixup::Target) => Inst::Branch(cond, rs1, rs2, addr),
+                (Inst::Jal(rd, _), Fixup::Target) => Inst::Jal(rd, addr),
+                (Inst::Lui(rd, _), Fixup::Hi) => Inst::Lui(rd, hi & 0xfffff),
+                (Inst::OpImm(op, rd, rs, _), Fixup::Lo) => Inst::OpImm(op, rd, rs, lo),
+                _ => unreachable!(),
+            };
+        }
+        let entry = match self.text_labels.get("main") {
+            Some(&index) => TEXT_BASE + 4 * index as u32,
+            None => return Err(RunError::UndefinedFunction("main".into())),
+        };
+        let len = self.data.len().next_multiple_of(4) + self.bss as usize;
+        self.data.resize(len, 0);
+        Ok(Image {
+            text: self.text,
+            data: self.data,
+            entry,
+        })
+    }
+}
+
+/// Computes `lhs op rhs` like RV32IM does.
+fn alu(op: AluOp, lhs: i32, rhs: i32) -> i32 {
+    let (ulhs, urhs) = (lhs as u32, rhs as u32);
+    match op {
+        AluOp::Add => fold::binary(BinaryOp::Add, lhs, rhs),
+        AluOp::Sub => fold::binary(BinaryOp::Sub, lhs, rhs),
+        AluOp::Sll => fold::binary(BinaryOp::Shl, lhs, rhs),
+        AluOp::Slt => fold::binary(BinaryOp::Lt, lhs, rhs),
+        AluOp::Sltu => (ulhs < urhs) as i32,
+        AluOp::Xor => fold::binary(BinaryOp::Xor, lhs, rhs),
+        AluOp::Srl => fold::binary(BinaryOp::Shr, lhs, rhs),
+        AluOp::Sra => fold::binary(BinaryOp::Sar, lhs, rhs),
+        AluOp::Or => fold::binary(BinaryOp::Or, lhs, rhs),
+        AluOp::And => fold::binary(BinaryOp::And, lhs, rhs),
+        AluOp::Mul => fold::binary(BinaryOp::Mul, lhs, rhs),
+        AluOp::Mulh => ((lhs as i64 * rhs as i64) >> 32) as i32,
+        AluOp::Mulhsu => ((lhs as i64 * urhs as i64) >> 32) as i32,
+        AluOp::Mulhu => ((ulhs as u64 * urhs as u64) >> 32) as i32,
+        AluOp::Div => fold::binary(BinaryOp::Div, lhs, rhs),
+        AluOp::Divu => ulhs.checked_div(urhs).unwrap_or(u32::MAX) as i32,
+        AluOp::Rem => fold::binary(BinaryOp::Mod, lhs, rhs),
+        AluOp::Remu => ulhs.checked_rem(urhs).unwrap_or(ulhs) as i32,
+    }
+}
+
+/// The running program.
+struct Hart<'a> {
+    text: &'a [Inst],
+    data: Vec<u8>,
+    /// the stack, it ends at `STACK_TOP`
+    stack: Vec<u8>,
+    regs: [i32; 32],
+    pc: u32,
+    runtime: Runtime,
+    step_limit: u64,
+    steps: u64,
+}
+
+impl<'a> Hart<'a> {
+    fn new(image: &'a Image, input: &[u8], step_limit: u64) -> Self {
+        let mut regs = [0; 32];
+        regs[1] = EXIT_ADDR as i32;
+        regs[2] = STACK_TOP as i32;
+        Hart {
+            text: &image.text,
+            data: image.data.clone(),
+            stack: vec![0; STACK_SIZE as usize],
+            regs,
+            pc: image.entry,
+            runtime: Runtime::new(input),
+            step_limit,
+            steps: 0,
+        }
+    }
+
+    /// Runs until `main` returns, gives its return value.
+    fn run(&mut self) -> Result<i32, RunError> {
+        while self.pc != EXIT_ADDR {
+            self.steps += 1;
+            if self.steps > self.step_limit {
+                return Err(RunError::StepLimit(self.step_limit));
+            }
+            self.step()?;
+            self.regs[0] = 0;
+        }
+        Ok(self.regs[10])
+    }
+
+    fn fetch(&self) -> Result<Inst, RunError> {
+        let index = self.pc.wrapping_sub(TEXT_BASE) / 4;
+        match self.text.get(index as usize) {
+            Some(&inst) if self.pc.is_multiple_of(4) && self.pc >= TEXT_BASE => Ok(inst),
+            _ => Err(RunError::Invalid(format!("jump to {:#x}, which is not an instruction", self.pc))),
+        }
+    }
+
+    /// Bytes of memory at the address.
+    fn bytes(&mut self, addr: u32, len: u32) -> Result<&mut [u8], RunError> {
+        let (addr, end) = (addr as u64, addr as u64 + len as u64);
+        let data = (DATA_BASE as u64, DATA_BASE as u64 + self.data.len() as u64);
+        let stack = ((STACK_TOP - STACK_SIZE) as u64, STACK_TOP as u64);
+        if addr >= data.0 && end <= data.1 {
+            Ok(&mut self.data[(addr - data.0) as usize..(end - data.0) as usize])
+        } else if addr >= stack.0 && end <= stack.1 {
+            Ok(&mut self.stack[(addr - stack.0) as usize..(end - stack.0) as usize])
+        } else {
+            Err(RunError::OutOfBounds(format!("at {:#x}: address {:#x}", self.pc, addr)))
+        }
+    }
+
+    fn load(&mut self, width: Width, addr: u32) -> Result<i32, RunError> {
+        let bytes = self.bytes(addr, width.bytes)?;
+        Ok(match (bytes.len(), width.signed) {
+            (1, true) => bytes[0] as i8 as i32,
+            (1, false) => bytes[0] as i32,
+            (2, true) => i16::from_le_bytes([bytes[0], bytes[1]]) as i32,
+            (2, false) => u16::from_le_bytes([bytes[0], bytes[1]]) as i32,
+            _ => i32::from_le_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]),
+        })
+    }
+
+    fn store(&mut self, width: Width, addr: u32, value: i32) -> Result<(), RunError> {
+        let bytes = self.bytes(addr, width.bytes)?;
+        let len = bytes.len();
+        bytes.copy_from_slice(&value.to_le_bytes()[..len]);
+        Ok(())
+    }
+
+    /// Executes the instruction at `pc`.
+    fn step(&mut self) -> Result<(), RunError> {
+        let word = Width { bytes: 4, signed: true };
+        let mut next = self.pc.wrapping_add(4);
+        match self.fetch()? {
+            Inst::Op(op, rd, rs1, rs2) => self.regs[rd] = alu(op, self.regs[rs1], self.regs[rs2]),
+            Inst::OpImm(op, rd, rs1, i) => self.regs[rd] = alu(op, self.regs[rs1], i),
+            Inst::Lui(rd, i) => self.regs[rd] = i << 12,
+            Inst::Auipc(rd, i) => self.regs[rd] = self.pc.wrapping_add((i << 12) as u32) as i32,
+            Inst::Load(width, rd, rs1, offset) => {
+                self.regs[rd] = self.load(width, self.regs[rs1].wrapping_add(offset) as u32)?
+            }
+            Inst::Store(width, rs2, rs1, offset) => {
+                self.store(width, self.regs[rs1].wrapping_add(offset) as u32, self.regs[rs2])?
+            }
+            Inst::Branch(cond, rs1, rs2, target) => {
+                let (lhs, rhs) = (self.regs[rs1], self.regs[rs2]);
+                let taken = match cond {
+                    Cond::Eq => lhs == rhs,
+                    Cond::Ne => lhs != rhs,
+                    Cond::Lt => lhs < rhs,
+                    Cond::Ge => lhs >= rhs,
+                    Cond::Ltu => (lhs as u32) < rhs as u32,
+                    Cond::Geu => lhs as u32 >= rhs as u32,
+                };
+                if taken {
+                    next = target;
+                }
+            }
+            Inst::Jal(rd, target) => {
+                self.regs[rd] = next as i32;
+                next = target;
+            }
+            Inst::Jalr(rd, rs1, offset) => {
+                let target = self.regs[rs1].wrapping_add(offset) as u32 & !1;
+                self.regs[rd] = next as i32;
+                next = target;
+            }
+            Inst::Host(func) => {
+                let (a0, a1) = (self.regs[10], self.regs[11]);
+                match func {
+                    HostFn::GetInt => self.regs[10] = self.runtime.getint(),
+                    HostFn::GetCh => self.regs[10] = self.runtime.getch(),
+                    HostFn::GetArray => {
+                        let n = self.runtime.getint();
+                        for i in 0..n {
+                            let value = self.runtime.getint();
+                            self.store(word, (a0 as u32).wrapping_add(4 * i as u32), value)?;
+                        }
+                        self.regs[10] = n;
+                    }
+                    HostFn::PutInt => self.runtime.putint(a0),
+                    HostFn::PutCh => self.runtime.putch(a0),
+                    HostFn::PutArray => {
+                        let mut values = Vec::new();
+                        for i in 0..a0 {
+                            values.push(self.load(word, (a1 as u32).wrapping_add(4 * i as u32))?);
+                        }
+                        self.runtime.putarray(&values);
+                    }
+                    HostFn::StartTime | HostFn::StopTime => (),
+                }
+                let is_void = !matches!(func, HostFn::GetInt | HostFn::GetCh | HostFn::GetArray);
+                for &reg in CALLER_SAVED.iter().chain(is_void.then_some(&10)) {
+                    self.regs[reg] = GARBAGE ^ reg as i32;
+                }
+                self.regs[1] = next as i32;
+            }
+        }
+        self.pc = next;
+        Ok(())
+    }
+}
@@ -1,67 +1,84 @@
-use koopa::ir::Value;
-use std::collections::HashMap;
+use koopa::ir::{Type, TypeKind, Value};
+use std::collections::{BTreeMap, HashMap};
 
 #[derive(Copy, Clone, Debug)]
 pub enum ValueStore {
     Const(i32),
     Reg(Reg),
+    /// value spilled to the stack frame, at `offset(sp)`
+    Stack(i32),
+    /// memory allocated by `alloc`, starting at `offset(sp)`
+    Frame(i32),
 }
 
+/// Register number in the riscv ABI, e.g. 10 for `a0`.
 pub type Reg = u8;
 
+/// The return address register.
+pub const RA: Reg = 1;
+/// The first argument register, `a0` to `a7` are numbered consecutively.
+pub const A0: Reg = 10;
+/// Number of arguments passed in registers.
+pub const ARG_REGS: usize = 8;
+
 pub struct RegNode {
-    pub value: Option<Value>,
+    pub used: bool,
     pub name: &'static str,
 }
 
 pub struct ValueManager {
-    regs: HashMap<Reg, RegNode>,
+    /// ordered by number, so registers are allocated deterministically
+    regs: BTreeMap<Reg, RegNode>,
     values: HashMap<Value, ValueStore>,
+    /// size of the current stack frame, 16-byte aligned
+    frame_size: i32,
+    /// next free offset in the current stack frame
+    frame_top: i32,
+}
+
+impl Default for ValueManager {
+    fn default() -> Self {
+        Self::new()
+    }
 }
 
 impl ValueManager {
     pub fn new() -> ValueManager {
-        let mut regs = HashMap::new();
-        regs.insert(
-            0,
-            RegNode {
-                value: None,
-                name: "x0",
-            },
-        );
+        let mut regs = BTreeMap::new();
+        // registers with a fixed role are never allocated
+        let fixed_regs = [("x0", 0), ("ra", RA), ("sp", 2)];
+        for &(name, num) in &fixed_regs {
+            regs.insert(num, RegNode { used: true, name });
+        }
 
+        // only caller-saved temporaries are allocated, every value is
+        // spilled right after it is computed, so nothing lives in a
+        // register across a call
         let temp_regs = [
-            ("t0", 1),
-            ("t1", 2),
-            ("t2", 3),
-            ("t3", 4),
-            ("t4", 5),
-            ("t5", 6),
-            ("t6", 7),
+            ("t0", 5),
+            ("t1", 6),
+            ("t2", 7),
+            ("t3", 28),
+            ("t4", 29),
+            ("t5", 30),
+            ("t6", 31),
         ];
 
         for &(name, num) in &temp_regs {
-            regs.insert(num, RegNode { value: None, name });
+            regs.insert(num, RegNode { used: false, name });
         }
 
-        let arg_regs = [
-            ("a7", 8),
-            ("a1", 9),
-            ("a2", 10),
-            ("a3", 11),
-            ("a4", 12),
-            ("a5", 13),
-            ("a6", 14),
-            ("a0", 15), // avoid use first
-        ];
-
-        for &(name, num) in &arg_regs {
-            regs.insert(num, RegNode { value: None, name });
+        // argument registers are only used for calls and returns
+        let arg_regs = ["a0", "a1", "a2", "a3", "a4", "a5", "a6", "a7"];
+        for (i, &name) in arg_regs.iter().enumerate() {
+            regs.insert(A0 + i as Reg, RegNode { used: true, name });
         }
 
         ValueManager {
             regs,
             values: HashMap::new(),
+            frame_size: 0,
+            frame_top: 0,
         }
     }
 
@@ -76,34 +93,24 @@ impl ValueManager {
         self.regs.get_mut(&reg)
     }
 
+    /// Allocates the free register with the lowest number and marks it as used.
     pub fn alloc_reg(&mut self) -> Reg {
-        for (reg, reg_node) in self.regs.iter() {
-            if reg_node.value.is_none() {
+        for (reg, reg_node) in self.regs.iter_mut() {
+            if !reg_node.used {
+                reg_node.used = true;
                 return *reg;
             }
         }
         panic!("Out of registers!");
     }
 
-    pub fn reset_reg(&mut self, reg: Reg) -> Option<String> {
-        let reg_node = self.regs.get_mut(&reg).unwrap();
-        let v = reg_node.value.take();
-        let name = reg_node.name;
-        match v {
-            Some(v) => {
-                for (r, rn) in self.regs.iter_mut() {
-                    if rn.value.is_none() {
-                        self.values.insert(v, ValueStore::Reg(*r));
-                        rn.value = Some(v);
-                        return Some(format!("mv {}, {}", rn.name, name));
-                    }
-                }
-                unimplemented!()
-            }
-            None => None,
+    /// Marks the register as free again.
+    pub fn free_reg(&mut self, reg: Reg) {
+        if reg != 0 {
+            self.regs.get_mut(&reg).unwrap().used = false;
         }
-        //
     }
+
     pub fn get_value(&self, value: Value) -> Option<&ValueStore> {
         self.values.get(&value)
     }
@@ -116,18 +123,38 @@ impl ValueManager {
         match store {
             ValueStore::Const(i) => i.to_string(),
             ValueStore::Reg(r) => self.get_reg_name(r).to_string(),
+            ValueStore::Stack(off) | ValueStore::Frame(off) => format!("{}(sp)", off),
         }
     }
 
-    pub fn load_reg(&mut self, value: Value) -> Option<String> {
-        let store = *self.get_value(value).unwrap();
-        match store {
-            ValueStore::Const(i) => {
-                let reg = self.alloc_reg();
-                self.set_value(value, ValueStore::Reg(reg));
-                Some(format!("li {}, {}", self.get_reg_name(reg), i))
-            }
-            ValueStore::Reg(_) => None,
-        }
+    /// Resets the stack frame for a new function, `size` is the number of
+    /// bytes needed by the whole frame, the first `args_size` bytes are
+    /// kept for the arguments passed on the stack to callees.
+    pub fn new_frame(&mut self, size: i32, args_size: i32) {
+        self.values.clear();
+        self.frame_size = (size + 15) / 16 * 16;
+        self.frame_top = args_size;
+    }
+
+    pub fn frame_size(&self) -> i32 {
+        self.frame_size
+    }
+
+    /// Reserves `size` bytes in the current stack frame, returns the offset.
+    pub fn alloc_stack(&mut self, size: i32) -> i32 {
+        let offset = self.frame_top;
+        self.frame_top += size;
+        assert!(self.frame_top <= self.frame_size, "stack frame overflow");
+        offset
+    }
+}
+
+/// Size of the given type on riscv32, in bytes.
+pub fn type_size(ty: &Type) -> i32 {
+    match ty.kind() {
+        TypeKind::Int32 => 4,
+        TypeKind::Unit => 0,
+        TypeKind::Array(base, len) => type_size(base) * *len as i32,
+        TypeKind::Pointer(_) | TypeKind::Function(..) => 4,
     }
 }
@@ -1,2 +1,3 @@
+pub mod emu;
 pub mod gen;
 pub mod visitor;
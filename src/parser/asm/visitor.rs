@@ -1,8 +1,10 @@
 use crate::parser::asm::gen::*;
-use koopa::ir::entities::FunctionData;
+use crate::parser::fold;
+use koopa::ir::entities::{FunctionData, ValueData};
 use koopa::ir::layout::BasicBlockNode;
 use koopa::ir::values::*;
-use koopa::ir::{BasicBlock, Program, Value, ValueKind};
+use koopa::ir::{BasicBlock, Program, Type, TypeKind, Value, ValueKind};
+use std::collections::HashMap;
 use std::io::{Result, Write};
 
 /// Visitor for generating the in-memory form Koopa IR program into the riscv
@@ -20,45 +22,214 @@ impl Visitor {
             program,
             func: None,
             vm: ValueManager::new(),
+            labels: HashMap::new(),
+            edges: 0,
+            args_buffer: 0,
+            save_ra: false,
         };
         visitor.visit()
     }
 }
 
+/// Describes the first thing in the program the backend cannot generate code
+/// for, `None` if there is none. Arrays are only supported in memory, so no
+/// value may have an array type, which the frontend never generates.
+pub fn unsupported(program: &Program) -> Option<String> {
+    for func in program.func_layout().iter() {
+        let func = program.func(*func);
+        // parameters, results of instructions and their local operands
+        let mut values = func.params().to_vec();
+        for (bb, node) in func.layout().bbs() {
+            values.extend(func.dfg().bb(*bb).params());
+            for inst in node.insts().keys() {
+                values.push(*inst);
+                let uses = func.dfg().value(*inst).kind().value_uses();
+                values.extend(uses.filter(|value| !value.is_global()));
+            }
+        }
+        for value in values {
+            let ty = func.dfg().value(value).ty();
+            if let TypeKind::Array(..) = ty.kind() {
+                return Some(format!(
+                    "`{}` has a value of type `{}`, arrays are only supported in memory",
+                    func.name(),
+                    ty
+                ));
+            }
+        }
+    }
+    None
+}
+
 /// The implementation of riscv Koopa IR generator.
 struct VisitorImpl<'a, W: Write> {
     w: &'a mut W,
     program: &'a Program,
     func: Option<&'a FunctionData>,
     vm: ValueManager,
+    /// labels of the basic blocks in the current function
+    labels: HashMap<BasicBlock, String>,
+    /// number of labels of branch edges in the current function
+    edges: usize,
+    /// offset of the buffer for the arguments of jumps in the frame
+    args_buffer: i32,
+    /// whether the current function calls others, so `ra` is saved
+    save_ra: bool,
+}
+
+/// Immediates of riscv I-type / S-type instructions are 12-bit signed.
+fn is_imm12(i: i32) -> bool {
+    (-2048..=2047).contains(&i)
 }
 
 impl<W: Write> VisitorImpl<'_, W> {
     /// Visits the program
     fn visit(&mut self) -> Result<()> {
-        writeln!(self.w, "  .text")?;
-        writeln!(self.w, "  .global main")?;
+        for value in self.program.inst_layout() {
+            self.visit_global_alloc(*value)?;
+        }
 
+        writeln!(self.w, "  .text")?;
         for func in self.program.func_layout().iter() {
             let func = self.program.func(*func);
+            // declarations of the runtime library have no body
+            if func.layout().entry_bb().is_none() {
+                continue;
+            }
             self.func = Some(func);
             self.visit_func(func)?;
         }
         Ok(())
     }
 
+    /// Generates the given global allocation, zero initialized ones go to
+    /// `.bss` and the others to `.data`.
+    fn visit_global_alloc(&mut self, value: Value) -> Result<()> {
+        let data = self.program.borrow_value(value);
+        let init = match data.kind() {
+            ValueKind::GlobalAlloc(alloc) => alloc.init(),
+            _ => unreachable!("global value must be an alloc"),
+        };
+        let name = &data.name().as_ref().expect("global alloc must be named")[1..];
+        let init = self.program.borrow_value(init);
+        match init.kind() {
+            ValueKind::ZeroInit(_) => writeln!(self.w, "  .bss")?,
+            _ => writeln!(self.w, "  .data")?,
+        }
+        writeln!(self.w, "  .global {}", name)?;
+        writeln!(self.w, "{}:", name)?;
+        self.visit_global_init(&init)?;
+        writeln!(self.w)?;
+        Ok(())
+    }
+
+    /// Generates the data of a global initializer, aggregates recursively.
+    fn visit_global_init(&mut self, init: &ValueData) -> Result<()> {
+        match init.kind() {
+            ValueKind::Integer(i) => writeln!(self.w, "  .word {}", i.value())?,
+            ValueKind::ZeroInit(_) | ValueKind::Undef(_) => writeln!(self.w, "  .zero {}", type_size(init.ty()))?,
+            ValueKind::Aggregate(agg) => {
+                for elem in agg.elems() {
+                    let elem = self.program.borrow_value(*elem);
+                    self.visit_global_init(&elem)?;
+                }
+            }
+            _ => unreachable!("global initializer must be a constant"),
+        }
+        Ok(())
+    }
+
     /// Generates the given function
     fn visit_func(&mut self, func: &FunctionData) -> Result<()> {
+        writeln!(self.w, "  .global {}", &func.name()[1..])?;
         writeln!(self.w, "{}:", &func.name()[1..])?;
 
+        // every alloc gets its memory in the frame, and every instruction
+        // with a result gets a 4-byte slot to spill the result into
+        let mut size = 0;
+        let mut max_args = None;
+        let mut max_bb_params = 0;
+        for (bb, node) in func.layout().bbs() {
+            // a slot for each parameter of the block
+            let bb_params = func.dfg().bb(*bb).params().len() as i32;
+            size += bb_params * 4;
+            max_bb_params = max_bb_params.max(bb_params);
+            for inst in node.insts().keys() {
+                let data = func.dfg().value(*inst);
+                match data.kind() {
+                    ValueKind::Alloc(_) => match data.ty().kind() {
+                        TypeKind::Pointer(base) => size += type_size(base),
+                        _ => unreachable!("alloc must be a pointer"),
+                    },
+                    _ => size += type_size(data.ty()),
+                }
+                if let ValueKind::Call(call) = data.kind() {
+                    max_args = max_args.max(Some(call.args().len()));
+                }
+            }
+        }
+        // the frame from the bottom up: arguments passed on the stack to
+        // callees, allocs and spilled values (including the register
+        // arguments of this function and the parameters of blocks), the
+        // buffer for the arguments of jumps, then `ra`
+        self.save_ra = max_args.is_some();
+        let args_size = max_args.unwrap_or(0).saturating_sub(ARG_REGS) as i32 * 4;
+        let params_size = func.params().len().min(ARG_REGS) as i32 * 4;
+        let buffer_size = max_bb_params * 4;
+        let ra_size = if self.save_ra { 4 } else { 0 };
+        self.vm.new_frame(args_size + size + params_size + buffer_size + ra_size, args_size);
+
+        // prologue
+        let frame_size = self.vm.frame_size();
+        self.adjust_sp(-frame_size)?;
+        if self.save_ra {
+            self.access_stack("sw", RA, frame_size - 4)?;
+        }
+        // arguments in registers are saved to the frame, the others are in
+        // the caller's frame right above ours
+        for (i, param) in func.params().iter().enumerate() {
+            if i < ARG_REGS {
+                let offset = self.vm.alloc_stack(4);
+                self.access_stack("sw", A0 + i as Reg, offset)?;
+                self.vm.set_value(*param, ValueStore::Stack(offset));
+            } else {
+                let offset = frame_size + (i - ARG_REGS) as i32 * 4;
+                self.vm.set_value(*param, ValueStore::Stack(offset));
+            }
+        }
+        // the parameters of blocks are set by the jumps to them
+        for bb in func.layout().bbs().keys() {
+            for param in func.dfg().bb(*bb).params() {
+                let offset = self.vm.alloc_stack(4);
+                self.vm.set_value(*param, ValueStore::Stack(offset));
+            }
+        }
+        self.args_buffer = self.vm.alloc_stack(buffer_size);
+
+        // labels are prefixed with the function name, since basic block
+        // names are only unique inside a function
+        self.labels.clear();
+        for (i, bb) in func.layout().bbs().keys().enumerate() {
+            let label = match func.dfg().bb(*bb).name() {
+                Some(name) => format!(".L{}.{}", &func.name()[1..], &name[1..]),
+                None => format!(".L{}.{}", &func.name()[1..], i),
+            };
+            self.labels.insert(*bb, label);
+        }
+        self.edges = 0;
+
         for (i, (bb, node)) in func.layout().bbs().iter().enumerate() {
-            self.visit_bb(*bb, node)?;
+            // the entry block follows the prologue directly
+            self.visit_bb(*bb, node, i != 0)?;
         }
         Ok(())
     }
 
     /// Generates the given basic block.
-    fn visit_bb(&mut self, bb: BasicBlock, node: &BasicBlockNode) -> Result<()> {
+    fn visit_bb(&mut self, bb: BasicBlock, node: &BasicBlockNode, label: bool) -> Result<()> {
+        if label {
+            writeln!(self.w, "{}:", self.labels[&bb])?;
+        }
         for inst in node.insts().keys() {
             self.visit_local_inst(inst)?;
         }
@@ -69,50 +240,193 @@ impl<W: Write> VisitorImpl<'_, W> {
     fn visit_local_inst(&mut self, inst: &Value) -> Result<()> {
         let value_data = self.func.unwrap().dfg().value(*inst);
         match value_data.kind() {
+            ValueKind::Alloc(_) => self.visit_alloc(inst)?,
+            ValueKind::Load(l) => self.visit_load(inst, l)?,
+            ValueKind::Store(s) => self.visit_store(s)?,
             ValueKind::Binary(b) => {
                 self.visit_binary(inst, b)?;
             }
+            ValueKind::Branch(b) => self.visit_branch(b)?,
+            ValueKind::Jump(j) => self.visit_jump(j.target(), j.args())?,
             ValueKind::Return(v) => self.visit_return(v)?,
-            _ => unimplemented!(),
+            ValueKind::Call(c) => self.visit_call(inst, c)?,
+            ValueKind::GetElemPtr(g) => {
+                // the pointer to array steps over its elements
+                let stride = match self.value_ty(g.src()).kind() {
+                    TypeKind::Pointer(base) => match base.kind() {
+                        TypeKind::Array(elem, _) => type_size(elem),
+                        _ => unreachable!("getelemptr on non-array pointer"),
+                    },
+                    _ => unreachable!("getelemptr on non-pointer"),
+                };
+                self.visit_offset(inst, g.src(), g.index(), stride)?;
+            }
+            ValueKind::GetPtr(g) => {
+                // the pointer steps over its pointee
+                let stride = match self.value_ty(g.src()).kind() {
+                    TypeKind::Pointer(base) => type_size(base),
+                    _ => unreachable!("getptr on non-pointer"),
+                };
+                self.visit_offset(inst, g.src(), g.index(), stride)?;
+            }
+            _ => unreachable!("{:?} is not a local instruction", value_data.kind()),
         };
         Ok(())
     }
 
+    /// Reserves the memory of an alloc in the stack frame.
+    fn visit_alloc(&mut self, value: &Value) -> Result<()> {
+        let size = match self.func.unwrap().dfg().value(*value).ty().kind() {
+            TypeKind::Pointer(base) => type_size(base),
+            _ => unreachable!("alloc must be a pointer"),
+        };
+        let offset = self.vm.alloc_stack(size);
+        self.vm.set_value(*value, ValueStore::Frame(offset));
+        Ok(())
+    }
+
+    /// Generates memory load.
+    fn visit_load(&mut self, value: &Value, l: &Load) -> Result<()> {
+        self.visit_value(l.src())?;
+        let rd = self.vm.alloc_reg();
+        match self.vm.get_value(l.src()) {
+            Some(&ValueStore::Frame(off)) => self.access_stack("lw", rd, off)?,
+            _ => {
+                let ptr = self.load_value(l.src())?;
+                writeln!(
+                    self.w,
+                    "  lw {}, 0({})",
+                    self.vm.get_reg_name(rd),
+                    self.vm.get_reg_name(ptr)
+                )?;
+                self.vm.free_reg(ptr);
+            }
+        }
+        self.spill(*value, rd)
+    }
+
+    /// Generates memory store.
+    fn visit_store(&mut self, s: &Store) -> Result<()> {
+        self.visit_value(s.value())?;
+        self.visit_value(s.dest())?;
+        let rs = self.load_value(s.value())?;
+        match self.vm.get_value(s.dest()) {
+            Some(&ValueStore::Frame(off)) => self.access_stack("sw", rs, off)?,
+            _ => {
+                let ptr = self.load_value(s.dest())?;
+                writeln!(
+                    self.w,
+                    "  sw {}, 0({})",
+                    self.vm.get_reg_name(rs),
+                    self.vm.get_reg_name(ptr)
+                )?;
+                self.vm.free_reg(ptr);
+            }
+        }
+        self.vm.free_reg(rs);
+        Ok(())
+    }
+
+    /// Generates conditional branch. The arguments of the true target are
+    /// copied behind a label of their own after the jump to the false one,
+    /// the label is the one of the target with a number appended, which no
+    /// name of a basic block can give.
+    fn visit_branch(&mut self, b: &Branch) -> Result<()> {
+        self.visit_value(b.cond())?;
+        let cond = self.load_value(b.cond())?;
+        let true_label = if b.true_args().is_empty() {
+            self.labels[&b.true_bb()].clone()
+        } else {
+            self.edges += 1;
+            format!("{}.{}", self.labels[&b.true_bb()], self.edges)
+        };
+        writeln!(self.w, "  bnez {}, {}", self.vm.get_reg_name(cond), true_label)?;
+        self.vm.free_reg(cond);
+        self.visit_jump(b.false_bb(), b.false_args())?;
+        if !b.true_args().is_empty() {
+            writeln!(self.w, "{}:", true_label)?;
+            self.visit_jump(b.true_bb(), b.true_args())?;
+        }
+        Ok(())
+    }
+
+    /// Generates unconditional jump, after copying the arguments to the
+    /// slots of the parameters of the target. If an argument is itself a
+    /// parameter of the target, all arguments go through the buffer first,
+    /// so that none is overwritten before it is read.
+    fn visit_jump(&mut self, target: BasicBlock, args: &[Value]) -> Result<()> {
+        let params = self.func.unwrap().dfg().bb(target).params();
+        let buffered = args.iter().any(|arg| params.contains(arg));
+        for (i, (arg, param)) in args.iter().zip(params).enumerate() {
+            self.visit_value(*arg)?;
+            let r = self.load_value(*arg)?;
+            let offset = match buffered {
+                true => self.args_buffer + i as i32 * 4,
+                false => self.param_slot(*param),
+            };
+            self.access_stack("sw", r, offset)?;
+            self.vm.free_reg(r);
+        }
+        if buffered {
+            for (i, param) in params.iter().enumerate() {
+                let r = self.vm.alloc_reg();
+                self.access_stack("lw", r, self.args_buffer + i as i32 * 4)?;
+                self.access_stack("sw", r, self.param_slot(*param))?;
+                self.vm.free_reg(r);
+            }
+        }
+        writeln!(self.w, "  j {}", self.labels[&target])
+    }
+
+    /// Offset of the slot of the parameter of a basic block in the frame.
+    fn param_slot(&self, param: Value) -> i32 {
+        match self.vm.get_value(param) {
+            Some(&ValueStore::Stack(offset)) => offset,
+            _ => unreachable!("block parameter without a slot"),
+        }
+    }
+
     /// Generates function return.
     fn visit_return(&mut self, ret: &Return) -> Result<()> {
         if let Some(val) = ret.value() {
             self.visit_value(val)?;
-            let val = self
-                .vm
-                .get_value(val)
-                .expect(&format!("value {:#?} not found", val));
-
-            match *val {
-                ValueStore::Const(v) => {
-                    if let Some(inst) = self.vm.reset_reg(15) {
-                        writeln!(self.w, "  {}", inst)?;
-                    }
-                    writeln!(self.w, "  li {}, {}", self.vm.get_reg_name(15), v)?;
-                }
-                ValueStore::Reg(r) => {
-                    if r != 15 {
-                        if let Some(inst) = self.vm.reset_reg(15) {
-                            writeln!(self.w, "  {}", inst)?;
-                        }
-                        write!(
-                            self.w,
-                            "  mv {}, {}",
-                            self.vm.get_reg_name(15),
-                            self.vm.get_reg_name(r)
-                        )?;
-                    }
-                }
-            }
+            let r = self.load_value(val)?;
+            writeln!(self.w, "  mv a0, {}", self.vm.get_reg_name(r))?;
+            self.vm.free_reg(r);
+        }
+        // epilogue
+        if self.save_ra {
+            self.access_stack("lw", RA, self.vm.frame_size() - 4)?;
         }
+        self.adjust_sp(self.vm.frame_size())?;
         writeln!(self.w, "  ret")?;
         Ok(())
     }
 
+    /// Generates function call, the first arguments are passed in `a0` to
+    /// `a7` and the others on the stack, the result is returned in `a0`.
+    fn visit_call(&mut self, value: &Value, c: &Call) -> Result<()> {
+        for (i, arg) in c.args().iter().enumerate() {
+            self.visit_value(*arg)?;
+            let r = self.load_value(*arg)?;
+            if i < ARG_REGS {
+                let a = self.vm.get_reg_name(A0 + i as Reg);
+                writeln!(self.w, "  mv {}, {}", a, self.vm.get_reg_name(r))?;
+            } else {
+                self.access_stack("sw", r, (i - ARG_REGS) as i32 * 4)?;
+            }
+            self.vm.free_reg(r);
+        }
+        let callee = self.program.func(c.callee());
+        writeln!(self.w, "  call {}", &callee.name()[1..])?;
+        if !self.func.unwrap().dfg().value(*value).ty().is_unit() {
+            let rd = self.vm.alloc_reg();
+            writeln!(self.w, "  mv {}, a0", self.vm.get_reg_name(rd))?;
+            self.spill(*value, rd)?;
+        }
+        Ok(())
+    }
+
     /// Generates the given binary operation._
     fn visit_binary(&mut self, value: &Value, b: &Binary) -> Result<()> {
         self.visit_value(b.lhs())?;
@@ -122,45 +436,19 @@ impl<W: Write> VisitorImpl<'_, W> {
             *self.vm.get_value(b.lhs()).unwrap(),
             *self.vm.get_value(b.rhs()).unwrap(),
         );
-        // let (lvs, rvs) = (self.vm.get_store_name(lvs), self.vm.get_store_name(rvs));
         // deal const val
         if let (ValueStore::Const(lv), ValueStore::Const(rv)) = (lvs, rvs) {
-            dbg!("const: ", lv, rv);
-            let bv = ValueStore::Const(match b.op() {
-                BinaryOp::Eq => (lv == rv) as i32,
-                BinaryOp::NotEq => (lv != rv) as i32,
-                BinaryOp::Lt => (lv < rv) as i32,
-                BinaryOp::Gt => (lv > rv) as i32,
-                BinaryOp::Le => (lv <= rv) as i32,
-                BinaryOp::Ge => (lv >= rv) as i32,
-                BinaryOp::And => lv & rv,
-                BinaryOp::Or => lv | rv,
-                BinaryOp::Add => lv + rv,
-                BinaryOp::Sub => lv - rv,
-                BinaryOp::Mul => lv * rv,
-                BinaryOp::Div => lv / rv,
-                BinaryOp::Mod => lv % rv,
-                _ => unimplemented!("not implemented"),
-            });
+            let bv = ValueStore::Const(fold::binary(b.op(), lv, rv));
             self.vm.set_value(*value, bv);
             return Ok(());
         }
 
-        // deal reg, for now load all const to reg
+        // load both operands to registers, then spill the result
+        let lr = self.load_value(b.lhs())?;
+        let rr = self.load_value(b.rhs())?;
         let rd = self.vm.alloc_reg();
         let rd_name = self.vm.get_reg_name(rd);
-
-        if let Some(inst) = self.vm.load_reg(b.lhs()) {
-            writeln!(self.w, "  {}", inst)?;
-        }
-        if let Some(inst) = self.vm.load_reg(b.rhs()) {
-            writeln!(self.w, "  {}", inst)?;
-        }
-        let (lvs, rvs) = (
-            *self.vm.get_value(b.lhs()).unwrap(),
-            *self.vm.get_value(b.rhs()).unwrap(),
-        );
-        let (lvs, rvs) = (self.vm.get_store_name(lvs), self.vm.get_store_name(rvs));
+        let (lvs, rvs) = (self.vm.get_reg_name(lr), self.vm.get_reg_name(rr));
 
         match b.op() {
             BinaryOp::Eq => {
@@ -185,6 +473,9 @@ impl<W: Write> VisitorImpl<'_, W> {
                 writeln!(self.w, "  slt {}, {}, {}", rd_name, lvs, rvs)?;
                 writeln!(self.w, "  seqz {}, {}", rd_name, rd_name)?;
             }
+            BinaryOp::And => {
+                writeln!(self.w, "  and {}, {}, {}", rd_name, lvs, rvs)?;
+            }
             BinaryOp::Or => {
                 writeln!(self.w, "  or {}, {}, {}", rd_name, lvs, rvs)?;
             }
@@ -203,26 +494,170 @@ impl<W: Write> VisitorImpl<'_, W> {
             BinaryOp::Mod => {
                 writeln!(self.w, "  rem {}, {}, {}", rd_name, lvs, rvs)?;
             }
-            _ => unimplemented!("not implemented"),
+            BinaryOp::Xor => {
+                writeln!(self.w, "  xor {}, {}, {}", rd_name, lvs, rvs)?;
+            }
+            BinaryOp::Shl => {
+                writeln!(self.w, "  sll {}, {}, {}", rd_name, lvs, rvs)?;
+            }
+            BinaryOp::Shr => {
+                writeln!(self.w, "  srl {}, {}, {}", rd_name, lvs, rvs)?;
+            }
+            BinaryOp::Sar => {
+                writeln!(self.w, "  sra {}, {}, {}", rd_name, lvs, rvs)?;
+            }
         }
+        self.vm.free_reg(lr);
+        self.vm.free_reg(rr);
 
-        self.vm.set_value(*value, ValueStore::Reg(rd));
-        Ok(())
+        self.spill(*value, rd)
+    }
+
+    /// Generates `value = src + index * stride` for pointer arithmetic.
+    fn visit_offset(&mut self, value: &Value, src: Value, index: Value, stride: i32) -> Result<()> {
+        self.visit_value(src)?;
+        self.visit_value(index)?;
+        let rd = self.load_value(src)?;
+        let rd_name = self.vm.get_reg_name(rd);
+        match *self.vm.get_value(index).unwrap() {
+            ValueStore::Const(i) => {
+                let offset = i.wrapping_mul(stride);
+                if is_imm12(offset) {
+                    if offset != 0 {
+                        writeln!(self.w, "  addi {}, {}, {}", rd_name, rd_name, offset)?;
+                    }
+                } else {
+                    let tmp = self.vm.alloc_reg();
+                    let tmp_name = self.vm.get_reg_name(tmp);
+                    writeln!(self.w, "  li {}, {}", tmp_name, offset)?;
+                    writeln!(self.w, "  add {}, {}, {}", rd_name, rd_name, tmp_name)?;
+                    self.vm.free_reg(tmp);
+                }
+            }
+            _ => {
+                let ri = self.load_value(index)?;
+                let rs = self.vm.alloc_reg();
+                let (ri_name, rs_name) = (self.vm.get_reg_name(ri), self.vm.get_reg_name(rs));
+                writeln!(self.w, "  li {}, {}", rs_name, stride)?;
+                writeln!(self.w, "  mul {}, {}, {}", ri_name, ri_name, rs_name)?;
+                writeln!(self.w, "  add {}, {}, {}", rd_name, rd_name, ri_name)?;
+                self.vm.free_reg(ri);
+                self.vm.free_reg(rs);
+            }
+        }
+        self.spill(*value, rd)
+    }
+
+    /// Type of the local or global value.
+    fn value_ty(&self, v: Value) -> Type {
+        if v.is_global() {
+            self.program.borrow_value(v).ty().clone()
+        } else {
+            self.func.unwrap().dfg().value(v).ty().clone()
+        }
     }
 
     /// check if const, add it to vm
     fn visit_value(&mut self, v: Value) -> Result<()> {
+        if v.is_global() {
+            // addressed by its symbol, see `load_value`
+            return Ok(());
+        }
         let data = self.func.unwrap().dfg().value(v);
         match data.kind() {
             ValueKind::Integer(i) => {
                 self.vm.set_value(v, ValueStore::Const(i.value()));
             }
-            ValueKind::Binary(_) => {
-                // do nothing
+            // any value will do, integers are the only values in registers
+            ValueKind::ZeroInit(_) | ValueKind::Undef(_) => {
+                self.vm.set_value(v, ValueStore::Const(0));
+            }
+            _ if data.kind().is_local_inst() => {
+                // already visited, the result is in vm
             }
-            _ => unimplemented!("not implemented"),
+            ValueKind::FuncArgRef(_) => {
+                // saved to the frame by the prologue
+            }
+            ValueKind::BlockArgRef(_) => {
+                // copied to its slot by the jumps to the block
+            }
+            _ => unreachable!("aggregate operand, rejected by `unsupported`"),
         }
 
         Ok(())
     }
+
+    /// Loads the value into a newly allocated register.
+    /// For an alloc or a global alloc, the address of its memory is loaded.
+    fn load_value(&mut self, v: Value) -> Result<Reg> {
+        if v.is_global() {
+            let reg = self.vm.alloc_reg();
+            let data = self.program.borrow_value(v);
+            let name = &data.name().as_ref().unwrap()[1..];
+            writeln!(self.w, "  la {}, {}", self.vm.get_reg_name(reg), name)?;
+            return Ok(reg);
+        }
+        let store = *self
+            .vm
+            .get_value(v)
+            .unwrap_or_else(|| panic!("value {:#?} not found", v));
+        let reg = self.vm.alloc_reg();
+        let name = self.vm.get_reg_name(reg);
+        match store {
+            ValueStore::Const(i) => writeln!(self.w, "  li {}, {}", name, i)?,
+            ValueStore::Reg(r) => writeln!(self.w, "  mv {}, {}", name, self.vm.get_reg_name(r))?,
+            ValueStore::Stack(off) => self.access_stack("lw", reg, off)?,
+            ValueStore::Frame(off) => {
+                if is_imm12(off) {
+                    writeln!(self.w, "  addi {}, sp, {}", name, off)?;
+                } else {
+                    writeln!(self.w, "  li {}, {}", name, off)?;
+                    writeln!(self.w, "  add {}, sp, {}", name, name)?;
+                }
+            }
+        }
+        Ok(reg)
+    }
+
+    /// Stores the result in `reg` to a new stack slot, and frees `reg`.
+    fn spill(&mut self, v: Value, reg: Reg) -> Result<()> {
+        let offset = self.vm.alloc_stack(4);
+        self.access_stack("sw", reg, offset)?;
+        self.vm.set_value(v, ValueStore::Stack(offset));
+        self.vm.free_reg(reg);
+        Ok(())
+    }
+
+    /// Generates `op reg, offset(sp)`, where `op` is `lw` or `sw`.
+    fn access_stack(&mut self, op: &str, reg: Reg, offset: i32) -> Result<()> {
+        let name = self.vm.get_reg_name(reg);
+        if is_imm12(offset) {
+            writeln!(self.w, "  {} {}, {}(sp)", op, name, offset)
+        } else {
+            let tmp = self.vm.alloc_reg();
+            let tmp_name = self.vm.get_reg_name(tmp);
+            writeln!(self.w, "  li {}, {}", tmp_name, offset)?;
+            writeln!(self.w, "  add {}, sp, {}", tmp_name, tmp_name)?;
+            writeln!(self.w, "  {} {}, 0({})", op, name, tmp_name)?;
+            self.vm.free_reg(tmp);
+            Ok(())
+        }
+    }
+
+    /// Generates `sp = sp + delta`.
+    fn adjust_sp(&mut self, delta: i32) -> Result<()> {
+        if delta == 0 {
+            return Ok(());
+        }
+        if is_imm12(delta) {
+            writeln!(self.w, "  addi sp, sp, {}", delta)
+        } else {
+            let tmp = self.vm.alloc_reg();
+            let tmp_name = self.vm.get_reg_name(tmp);
+            writeln!(self.w, "  li {}, {}", tmp_name, delta)?;
+            writeln!(self.w, "  add sp, sp, {}", tmp_name)?;
+            self.vm.free_reg(tmp);
+            Ok(())
+        }
+    }
 }
@@ -0,0 +1,536 @@
+use std::collections::HashMap;
+
+use crate::parser::ast::error::{BuildError, ErrorKind};
+use crate::parser::ast::eval::{ConstValue, Consts};
+use crate::parser::ast::structs::*;
+use crate::parser::ast::traits::{eval_dims, RUNTIME};
+use crate::parser::diagnostic::Span;
+
+/// A compilation unit which passed `check`, only it can be built into IR.
+pub struct CheckedUnit(CompUnit);
+
+impl CheckedUnit {
+    pub fn unit(&self) -> &CompUnit {
+        &self.0
+    }
+}
+
+/// Checks the names, types and constants of the whole unit, returns all
+/// errors found.
+pub fn check(unit: CompUnit) -> Result<CheckedUnit, Vec<BuildError>> {
+    let mut checker = Checker::new();
+    checker.unit(&unit);
+    if checker.errors.is_empty() {
+        Ok(CheckedUnit(unit))
+    } else {
+        Err(checker.errors)
+    }
+}
+
+enum Symbol {
+    Const(i32),
+    /// dimensions and flattened values of a constant array
+    ConstArray(Vec<usize>, Vec<i32>),
+    /// dimensions of a variable, empty for `int`, the first one is 0 for an
+    /// array parameter
+    Var(Vec<usize>),
+}
+
+/// Parameter types and whether the function returns `void`.
+struct Signature {
+    params: Vec<Ty>,
+    is_void: bool,
+}
+
+/// Type of an expression.
+#[derive(PartialEq)]
+enum Ty {
+    Int,
+    Void,
+    /// pointer to an array with the dimensions, `*i32` if empty
+    Ptr(Vec<usize>),
+}
+
+struct Checker {
+    /// innermost scope last, the first one is the global scope
+    scopes: Vec<HashMap<String, Symbol>>,
+    /// index of the item of the unit which declares each global
+    globals: HashMap<String, usize>,
+    funcs: HashMap<String, Signature>,
+    /// index of the item of the unit being checked
+    item: usize,
+    errors: Vec<BuildError>,
+    /// the current function returns `void`
+    is_void: bool,
+    /// number of enclosing loops
+    loops: usize,
+}
+
+impl Consts for Checker {
+    fn lookup_const(&self, name: &str) -> Option<ConstValue<'_>> {
+        match self.get(name)? {
+            Symbol::Const(v) => Some(ConstValue::Int(*v)),
+            Symbol::ConstArray(dims, values) => Some(ConstValue::Array(dims, values)),
+            Symbol::Var(_) => None,
+        }
+    }
+}
+
+impl Checker {
+    fn new() -> Checker {
+        let mut funcs = HashMap::new();
+        for (name, params, func_type) in RUNTIME {
+            let params = params
+                .iter()
+                .map(|&is_array| if is_array { Ty::Ptr(Vec::new()) } else { Ty::Int })
+                .collect();
+            funcs.insert(name.to_string(), Signature { params, is_void: matches!(func_type, FuncType::Void) });
+        }
+        // `starttime()` and `stoptime()` get the line number as argument
+        for name in ["starttime", "stoptime"] {
+            funcs.insert(name.to_string(), Signature { params: Vec::new(), is_void: true });
+        }
+        Checker {
+            scopes: vec![HashMap::new()],
+            globals: HashMap::new(),
+            funcs,
+            item: 0,
+            errors: Vec::new(),
+            is_void: false,
+            loops: 0,
+        }
+    }
+
+    fn error(&mut self, kind: ErrorKind, span: Span) {
+        self.errors.push(kind.at(span));
+    }
+
+    /// Looks up the name from the innermost scope outwards, globals declared
+    /// after the current item are not visible yet.
+    fn get(&self, name: &str) -> Option<&Symbol> {
+        let (global, locals) = self.scopes.split_first().unwrap();
+        locals.iter().rev().find_map(|scope| scope.get(name)).or_else(|| match self.globals.get(name) {
+            Some(&item) if item > self.item => None,
+            _ => global.get(name),
+        })
+    }
+
+    /// Declares the name in the current scope, the first declaration wins.
+    fn insert(&mut self, name: &str, symbol: Symbol, span: Span) {
+        let is_global = self.scopes.len() == 1;
+        let scope = self.scopes.last_mut().unwrap();
+        if scope.contains_key(name) || (is_global && self.funcs.contains_key(name)) {
+            self.error(ErrorKind::Redeclared(name.to_string()), span);
+        } else {
+            scope.insert(name.to_string(), symbol);
+            if is_global {
+                self.globals.insert(name.to_string(), self.item);
+            }
+        }
+    }
+
+    /// Evaluates the constant expression, after resolving its names.
+    fn calc(&mut self, exp: &Exp) -> Option<i32> {
+        let ty = self.exp(exp);
+        self.int(ty, exp.span())?;
+        match exp.calc(self) {
+            Ok(v) => Some(v),
+            Err(err) => {
+                self.errors.push(err);
+                None
+            }
+        }
+    }
+
+    /// Evaluates the array dimensions.
+    fn dims(&mut self, dims: &[ConstExp]) -> Option<Vec<usize>> {
+        for dim in dims.iter() {
+            self.calc(dim)?;
+        }
+        match eval_dims(dims, self) {
+            Ok(dims) => Some(dims),
+            Err(err) => {
+                self.errors.push(err);
+                None
+            }
+        }
+    }
+
+    /// Requires an integer, reports void values and arrays.
+    fn int(&mut self, ty: Option<Ty>, span: Span) -> Option<()> {
+        match ty? {
+            Ty::Int => Some(()),
+            Ty::Void => {
+                self.error(ErrorKind::VoidValue, span);
+                None
+            }
+            Ty::Ptr(_) => {
+                self.error(ErrorKind::ArrayAsValue, span);
+                None
+            }
+        }
+    }
+
+    fn unit(&mut self, unit: &CompUnit) {
+        // globals and function signatures first, in order, so that
+        // functions can be called before their definitions; a global can
+        // still only be used after it is declared
+        for (i, item) in unit.items.iter().enumerate() {
+            self.item = i;
+            match item {
+                GlobalItem::Decl(decl) => self.decl(decl, true),
+                GlobalItem::FuncDef(func_def) => self.signature(func_def),
+            }
+        }
+        for (i, item) in unit.items.iter().enumerate() {
+            if let GlobalItem::FuncDef(func_def) = item {
+                self.item = i;
+                self.func_def(func_def);
+            }
+        }
+
+        match unit.items.iter().find_map(|item| match item {
+            GlobalItem::FuncDef(func_def) if func_def.ident == "main" => Some(func_def),
+            _ => None,
+        }) {
+            Some(main) if !matches!(main.func_type, FuncType::Int) || !main.params.is_empty() => {
+                self.error(ErrorKind::InvalidMain, main.span)
+            }
+            Some(_) => {}
+            None => self.error(ErrorKind::MissingMain, Span::new(unit.span.end, unit.span.end)),
+        }
+    }
+
+    /// Type of the parameter, `None` if its dimensions are invalid.
+    fn param_ty(&mut self, param: &FuncFParam) -> Option<Ty> {
+        match &param.dims {
+            None => Some(Ty::Int),
+            Some(dims) => Some(Ty::Ptr(self.dims(dims)?)),
+        }
+    }
+
+    fn signature(&mut self, func_def: &FuncDef) {
+        let mut params = Vec::new();
+        for param in func_def.params.iter() {
+            // keep the arity even if the parameter is invalid
+            params.push(self.param_ty(param).unwrap_or(Ty::Int));
+        }
+        let name = &func_def.ident;
+        if self.scopes[0].contains_key(name) || self.funcs.contains_key(name) {
+            self.error(ErrorKind::Redeclared(name.clone()), func_def.span);
+            return;
+        }
+        let is_void = matches!(func_def.func_type, FuncType::Void);
+        self.funcs.insert(name.clone(), Signature { params, is_void });
+    }
+
+    fn func_def(&mut self, func_def: &FuncDef) {
+        self.is_void = matches!(func_def.func_type, FuncType::Void);
+        // parameters share the scope with the outermost block of the body
+        self.scopes.push(HashMap::new());
+        for param in func_def.params.iter() {
+            let dims = match self.param_ty(param) {
+                Some(Ty::Ptr(dims)) => [vec![0], dims].concat(),
+                _ => Vec::new(),
+            };
+            self.insert(&param.ident, Symbol::Var(dims), param.span);
+        }
+        for item in func_def.block.items.iter() {
+            self.block_item(item);
+        }
+        self.scopes.pop();
+    }
+
+    fn block(&mut self, block: &Block) {
+        self.scopes.push(HashMap::new());
+        for item in block.items.iter() {
+            self.block_item(item);
+        }
+        self.scopes.pop();
+    }
+
+    fn block_item(&mut self, item: &BlockItem) {
+        match item {
+            BlockItem::Decl(decl) => self.decl(decl, false),
+            BlockItem::Stmt(stmt) => self.stmt(stmt),
+        }
+    }
+
+    fn decl(&mut self, decl: &Decl, is_global: bool) {
+        match decl {
+            Decl::Const(decl) => {
+                for def in decl.defs.iter() {
+                    self.const_def(def);
+                }
+            }
+            Decl::Var(decl) => {
+                for def in decl.defs.iter() {
+                    self.var_def(def, is_global);
+                }
+            }
+        }
+    }
+
+    /// Elements of the initializer of an array with `dims`.
+    fn flatten<'a>(&mut self, init: &'a InitVal, dims: &[usize]) -> Option<Vec<Option<&'a Exp>>> {
+        let elems = if dims.is_empty() {
+            init.exp().map(|exp| vec![Some(exp)])
+        } else {
+            init.flatten(dims)
+        };
+        match elems {
+            Ok(elems) => Some(elems),
+            Err(err) => {
+                self.errors.push(err);
+                None
+            }
+        }
+    }
+
+    fn const_def(&mut self, def: &ConstDef) {
+        let symbol = self.dims(&def.dims).and_then(|dims| {
+            let mut values = Vec::new();
+            for exp in self.flatten(&def.value, &dims)? {
+                values.push(match exp {
+                    Some(exp) => self.calc(exp)?,
+                    None => 0,
+                });
+            }
+            Some(match dims.is_empty() {
+                true => Symbol::Const(values[0]),
+                false => Symbol::ConstArray(dims, values),
+            })
+        });
+        // still declared after an error, to avoid follow-up errors
+        let symbol = symbol.unwrap_or(Symbol::Var(Vec::new()));
+        self.insert(&def.ident, symbol, def.span);
+    }
+
+    fn var_def(&mut self, def: &VarDef, is_global: bool) {
+        let dims = self.dims(&def.dims);
+        if let (Some(init), Some(dims)) = (&def.init, &dims) {
+            for exp in self.flatten(init, dims).into_iter().flatten().flatten() {
+                if is_global {
+                    self.calc(exp);
+                } else {
+                    let ty = self.exp(exp);
+                    self.int(ty, exp.span());
+                }
+            }
+        }
+        self.insert(&def.ident, Symbol::Var(dims.unwrap_or_default()), def.span);
+    }
+
+    fn stmt(&mut self, stmt: &Stmt) {
+        match stmt {
+            Stmt::LVal(lval, exp, _) => {
+                let ty = self.exp(exp);
+                self.int(ty, exp.span());
+                match self.get(&lval.ident) {
+                    Some(Symbol::Var(_)) => {
+                        let ty = self.lval(lval);
+                        if matches!(ty, Some(Ty::Ptr(_))) {
+                            self.error(ErrorKind::IndexMismatch(lval.ident.clone()), lval.span);
+                        }
+                    }
+                    Some(_) => self.error(ErrorKind::AssignToConst(lval.ident.clone()), lval.span),
+                    None => self.error(ErrorKind::UndeclaredVariable(lval.ident.clone()), lval.span),
+                }
+            }
+            Stmt::Ret(exp, span) => {
+                if let Some(exp) = exp {
+                    let ty = self.exp(exp);
+                    self.int(ty, exp.span());
+                }
+                if exp.is_some() == self.is_void {
+                    self.error(ErrorKind::ReturnMismatch, *span);
+                }
+            }
+            Stmt::If(cond, then, els, _) => {
+                let ty = self.exp(cond);
+                self.int(ty, cond.span());
+                self.stmt(then);
+                if let Some(els) = els {
+                    self.stmt(els);
+                }
+            }
+            Stmt::While(cond, body, _) => {
+                let ty = self.exp(cond);
+                self.int(ty, cond.span());
+                self.loops += 1;
+                self.stmt(body);
+                self.loops -= 1;
+            }
+            Stmt::Block(block) => self.block(block),
+            Stmt::Exp(exp, _) => {
+                if let Some(exp) = exp {
+                    self.exp(exp);
+                }
+            }
+            Stmt::Break(span) => {
+                if self.loops == 0 {
+                    self.error(ErrorKind::BreakOutsideLoop, *span);
+                }
+            }
+            Stmt::Continue(span) => {
+                if self.loops == 0 {
+                    self.error(ErrorKind::ContinueOutsideLoop, *span);
+                }
+            }
+        }
+    }
+
+    /// Type of the variable or constant after indexing, arrays which are
+    /// not fully indexed decay to pointers.
+    fn lval(&mut self, lval: &LVal) -> Option<Ty> {
+        for index in lval.indices.iter() {
+            let ty = self.exp(index);
+            self.int(ty, index.span());
+        }
+        let dims = match self.get(&lval.ident) {
+            Some(Symbol::Const(_)) => Vec::new(),
+            Some(Symbol::ConstArray(dims, _)) | Some(Symbol::Var(dims)) => dims.clone(),
+            None => {
+                self.error(ErrorKind::UndeclaredVariable(lval.ident.clone()), lval.span);
+                return None;
+            }
+        };
+        match lval.indices.len() {
+            n if n == dims.len() => Some(Ty::Int),
+            n if n < dims.len() => Some(Ty::Ptr(dims[n + 1..].to_vec())),
+            _ => {
+                self.error(ErrorKind::IndexMismatch(lval.ident.clone()), lval.span);
+                None
+            }
+        }
+    }
+
+    /// Type of the expression, `None` if it has errors.
+    fn exp(&mut self, exp: &Exp) -> Option<Ty> {
+        match exp {
+            Exp::Exp(exp) => self.lor(exp),
+        }
+    }
+
+    /// Both operands of a binary operator are integers, so is the result.
+    fn binary(&mut self, lhs: (Option<Ty>, Span), rhs: (Option<Ty>, Span)) -> Option<Ty> {
+        let lhs = self.int(lhs.0, lhs.1);
+        let rhs = self.int(rhs.0, rhs.1);
+        lhs.and(rhs).map(|_| Ty::Int)
+    }
+
+    fn lor(&mut self, exp: &LOrExp) -> Option<Ty> {
+        match exp {
+            LOrExp::LAndExp(exp) => self.land(exp),
+            LOrExp::LOrExp(lhs, rhs, _) => {
+                let l = (self.lor(lhs), lhs.span());
+                let r = (self.land(rhs), rhs.span());
+                self.binary(l, r)
+            }
+        }
+    }
+
+    fn land(&mut self, exp: &LAndExp) -> Option<Ty> {
+        match exp {
+            LAndExp::EqExp(exp) => self.eq(exp),
+            LAndExp::LAndExp(lhs, rhs, _) => {
+                let l = (self.land(lhs), lhs.span());
+                let r = (self.eq(rhs), rhs.span());
+                self.binary(l, r)
+            }
+        }
+    }
+
+    fn eq(&mut self, exp: &EqExp) -> Option<Ty> {
+        match exp {
+            EqExp::RelExp(exp) => self.rel(exp),
+            EqExp::EqExp(lhs, _, rhs, _) => {
+                let l = (self.eq(lhs), lhs.span());
+                let r = (self.rel(rhs), rhs.span());
+                self.binary(l, r)
+            }
+        }
+    }
+
+    fn rel(&mut self, exp: &RelExp) -> Option<Ty> {
+        match exp {
+            RelExp::AddExp(exp) => self.add(exp),
+            RelExp::RelExp(lhs, _, rhs, _) => {
+                let l = (self.rel(lhs), lhs.span());
+                let r = (self.add(rhs), rhs.span());
+                self.binary(l, r)
+            }
+        }
+    }
+
+    fn add(&mut self, exp: &AddExp) -> Option<Ty> {
+        match exp {
+            AddExp::MulExp(exp) => self.mul(exp),
+            AddExp::AddExp(lhs, _, rhs, _) => {
+                let l = (self.add(lhs), lhs.span());
+                let r = (self.mul(rhs), rhs.span());
+                self.binary(l, r)
+            }
+        }
+    }
+
+    fn mul(&mut self, exp: &MulExp) -> Option<Ty> {
+        match exp {
+            MulExp::UnaryExp(exp) => self.unary(exp),
+            MulExp::MulExp(lhs, _, rhs, _) => {
+                let l = (self.mul(lhs), lhs.span());
+                let r = (self.unary(rhs), rhs.span());
+                self.binary(l, r)
+            }
+        }
+    }
+
+    fn unary(&mut self, exp: &UnaryExp) -> Option<Ty> {
+        match exp {
+            UnaryExp::PrimaryExp(exp) => self.primary(exp),
+            UnaryExp::UnaryOp(_, exp, _) => {
+                let ty = self.unary(exp);
+                self.int(ty, exp.span()).map(|_| Ty::Int)
+            }
+            UnaryExp::Call(ident, args, _, span) => self.call(ident, args, *span),
+        }
+    }
+
+    fn call(&mut self, ident: &str, args: &[Exp], span: Span) -> Option<Ty> {
+        let arg_tys: Vec<_> = args.iter().map(|arg| self.exp(arg)).collect();
+        let sig = match self.funcs.get(ident) {
+            Some(sig) => sig,
+            None => {
+                self.error(ErrorKind::UndeclaredFunction(ident.to_string()), span);
+                return None;
+            }
+        };
+        let ret = if sig.is_void { Ty::Void } else { Ty::Int };
+        if sig.params.len() != args.len() {
+            let kind = ErrorKind::ArgCountMismatch(ident.to_string(), sig.params.len(), args.len());
+            self.error(kind, span);
+            return Some(ret);
+        }
+
+        let mut errors = Vec::new();
+        for (i, (arg_ty, param_ty)) in arg_tys.into_iter().zip(&sig.params).enumerate() {
+            match arg_ty {
+                Some(Ty::Void) => errors.push(ErrorKind::VoidValue.at(args[i].span())),
+                Some(arg_ty) if arg_ty != *param_ty => {
+                    errors.push(ErrorKind::ArgTypeMismatch(ident.to_string(), i + 1).at(args[i].span()))
+                }
+                _ => {}
+            }
+        }
+        self.errors.extend(errors);
+        Some(ret)
+    }
+
+    fn primary(&mut self, exp: &PrimaryExp) -> Option<Ty> {
+        match exp {
+            PrimaryExp::Exp(exp, _) => self.exp(exp),
+            PrimaryExp::Number(..) => Some(Ty::Int),
+            PrimaryExp::LVal(lval) => self.lval(lval),
+        }
+    }
+}
@@ -0,0 +1,105 @@
+use std::fmt;
+
+use crate::parser::diagnostic::Span;
+
+/// Error found while building Koopa IR from the AST, at the node it is
+/// reported for.
+#[derive(Debug)]
+pub struct BuildError {
+    pub kind: ErrorKind,
+    pub span: Span,
+}
+
+#[derive(Debug)]
+pub enum ErrorKind {
+    /// `break` outside of a loop
+    BreakOutsideLoop,
+    /// `continue` outside of a loop
+    ContinueOutsideLoop,
+    /// name declared twice in the same scope
+    Redeclared(String),
+    /// call to a function that is not defined
+    UndeclaredFunction(String),
+    /// use of a variable or constant that is not declared
+    UndeclaredVariable(String),
+    /// assignment to a constant
+    AssignToConst(String),
+    /// expression is required to be constant, but is not
+    NotConstant,
+    /// division or remainder by zero in a constant expression
+    DivisionByZero,
+    /// function, number of parameters and number of arguments of a call
+    ArgCountMismatch(String, usize, usize),
+    /// function and 1-based position of an argument of the wrong type
+    ArgTypeMismatch(String, usize),
+    /// `main` is not `int main()`
+    InvalidMain,
+    /// no `main` function
+    MissingMain,
+    /// result of a void function used as a value
+    VoidValue,
+    /// `return;` in an int function, or `return exp;` in a void function
+    ReturnMismatch,
+    /// array dimension is not a positive constant
+    InvalidArraySize,
+    /// initializer does not match the shape of the variable
+    InvalidInitializer,
+    /// wrong number of indices for the variable
+    IndexMismatch(String),
+    /// array used where an integer is expected
+    ArrayAsValue,
+}
+
+impl ErrorKind {
+    /// Reports the error at the given node.
+    pub fn at(self, span: Span) -> BuildError {
+        BuildError { kind: self, span }
+    }
+}
+
+impl fmt::Display for ErrorKind {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ErrorKind::BreakOutsideLoop => write!(f, "`break` statement not within a loop"),
+            ErrorKind::ContinueOutsideLoop => {
+                write!(f, "`continue` statement not within a loop")
+            }
+            ErrorKind::Redeclared(name) => write!(f, "redeclaration of `{}`", name),
+            ErrorKind::UndeclaredFunction(name) => {
+                write!(f, "call to undeclared function `{}`", name)
+            }
+            ErrorKind::UndeclaredVariable(name) => write!(f, "use of undeclared identifier `{}`", name),
+            ErrorKind::AssignToConst(name) => write!(f, "cannot assign to constant `{}`", name),
+            ErrorKind::NotConstant => write!(f, "expression is not a constant"),
+            ErrorKind::DivisionByZero => write!(f, "division by zero in a constant expression"),
+            ErrorKind::ArgCountMismatch(name, expected, found) => write!(
+                f,
+                "function `{}` takes {} argument(s) but {} were supplied",
+                name, expected, found
+            ),
+            ErrorKind::ArgTypeMismatch(name, pos) => {
+                write!(f, "mismatched type for argument {} of `{}`", pos, name)
+            }
+            ErrorKind::InvalidMain => write!(f, "`main` must be declared as `int main()`"),
+            ErrorKind::MissingMain => write!(f, "`main` function not found"),
+            ErrorKind::VoidValue => write!(f, "void value not ignored as it ought to be"),
+            ErrorKind::ReturnMismatch => {
+                write!(f, "return statement does not match the function return type")
+            }
+            ErrorKind::InvalidArraySize => write!(f, "array size must be a positive constant"),
+            ErrorKind::InvalidInitializer => write!(f, "invalid initializer"),
+            ErrorKind::IndexMismatch(name) => {
+                write!(f, "wrong number of indices for `{}`", name)
+            }
+            ErrorKind::ArrayAsValue => write!(f, "array used where an integer is expected"),
+        }
+    }
+}
+
+impl fmt::Display for BuildError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        self.kind.fmt(f)
+    }
+}
+
+impl std::error::Error for BuildError {}
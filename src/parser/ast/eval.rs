@@ -0,0 +1,147 @@
+use crate::parser::ast::error::{BuildError, ErrorKind};
+use crate::parser::ast::structs::*;
+use crate::parser::fold;
+use koopa::ir::BinaryOp;
+
+/// Value of a constant visible to a constant expression.
+pub enum ConstValue<'a> {
+    Int(i32),
+    /// dimensions and flattened values of a constant array
+    Array(&'a [usize], &'a [i32]),
+}
+
+/// Symbol table which constant expressions are evaluated against.
+pub trait Consts {
+    /// Looks up the constant, `None` if the name is not a constant.
+    fn lookup_const(&self, name: &str) -> Option<ConstValue<'_>>;
+}
+
+impl Exp {
+    /// Evaluates the constant expression.
+    pub fn calc(&self, consts: &impl Consts) -> Result<i32, BuildError> {
+        match self {
+            Exp::Exp(exp) => exp.calc(consts),
+        }
+    }
+}
+
+impl LOrExp {
+    fn calc(&self, consts: &impl Consts) -> Result<i32, BuildError> {
+        match self {
+            LOrExp::LAndExp(exp) => exp.calc(consts),
+            LOrExp::LOrExp(lor_exp, land_exp, _) =>
+                Ok((lor_exp.calc(consts)? != 0 || land_exp.calc(consts)? != 0).into())
+        }
+    }
+}
+
+impl LAndExp {
+    fn calc(&self, consts: &impl Consts) -> Result<i32, BuildError> {
+        match self {
+            LAndExp::EqExp(exp) => exp.calc(consts),
+            LAndExp::LAndExp(land_exp, eq_exp, _) =>
+               Ok((land_exp.calc(consts)? != 0 && eq_exp.calc(consts)? != 0).into())
+        }
+    }
+}
+
+impl EqExp {
+    fn calc(&self, consts: &impl Consts) -> Result<i32, BuildError> {
+        match self {
+            EqExp::RelExp(exp) => exp.calc(consts),
+            EqExp::EqExp(eq_exp, eq_op, rel_exp, _) => {
+                let op = match eq_op {
+                    EqOp::Eq => BinaryOp::Eq,
+                    EqOp::Ne => BinaryOp::NotEq,
+                };
+                Ok(fold::binary(op, eq_exp.calc(consts)?, rel_exp.calc(consts)?))
+            }
+        }
+    }
+}
+
+impl RelExp {
+    fn calc(&self, consts: &impl Consts) -> Result<i32, BuildError> {
+        match self {
+            RelExp::AddExp(exp) => exp.calc(consts),
+            RelExp::RelExp(rel_exp, rel_op, add_exp, _) => {
+                let op = match rel_op {
+                    RelOp::Lt => BinaryOp::Lt,
+                    RelOp::Le => BinaryOp::Le,
+                    RelOp::Gt => BinaryOp::Gt,
+                    RelOp::Ge => BinaryOp::Ge,
+                };
+                Ok(fold::binary(op, rel_exp.calc(consts)?, add_exp.calc(consts)?))
+            }
+        }
+    }
+}
+
+impl AddExp {
+    fn calc(&self, consts: &impl Consts) -> Result<i32, BuildError> {
+        match self {
+            AddExp::MulExp(exp) => exp.calc(consts),
+            AddExp::AddExp(add_exp, op, mul_exp, _) => {
+                let op = match op {
+                    AddOp::Add => BinaryOp::Add,
+                    AddOp::Sub => BinaryOp::Sub,
+                };
+                Ok(fold::binary(op, add_exp.calc(consts)?, mul_exp.calc(consts)?))
+            }
+        }
+    }
+}
+
+impl MulExp {
+    fn calc(&self, consts: &impl Consts) -> Result<i32, BuildError> {
+        match self {
+            MulExp::UnaryExp(exp) => exp.calc(consts),
+            MulExp::MulExp(mul_exp, op, unary_exp, span) => {
+                let op = match op {
+                    MulOp::Mul => BinaryOp::Mul,
+                    MulOp::Div => BinaryOp::Div,
+                    MulOp::Mod => BinaryOp::Mod,
+                };
+                fold::checked_binary(op, mul_exp.calc(consts)?, unary_exp.calc(consts)?)
+                    .ok_or_else(|| ErrorKind::DivisionByZero.at(*span))
+            }
+        }
+    }
+}
+
+impl UnaryExp {
+    fn calc(&self, consts: &impl Consts) -> Result<i32, BuildError> {
+        match self {
+            UnaryExp::PrimaryExp(exp) => exp.calc(consts),
+            UnaryExp::UnaryOp(op, exp, _) => Ok(match op {
+                UnaryOp::Plus => exp.calc(consts)?,
+                UnaryOp::Minus => fold::neg(exp.calc(consts)?),
+                UnaryOp::Not => fold::not(exp.calc(consts)?),
+            }),
+            UnaryExp::Call(.., span) => Err(ErrorKind::NotConstant.at(*span)),
+        }
+    }
+}
+
+impl PrimaryExp {
+    fn calc(&self, consts: &impl Consts) -> Result<i32, BuildError> {
+        match self {
+            PrimaryExp::Exp(exp, _) => exp.calc(consts),
+            PrimaryExp::Number(num, _) => Ok(*num),
+            PrimaryExp::LVal(lval) => match consts.lookup_const(&lval.ident) {
+                Some(ConstValue::Int(v)) if lval.indices.is_empty() => Ok(v),
+                Some(ConstValue::Array(dims, values)) if lval.indices.len() == dims.len() => {
+                    let mut index = 0;
+                    for (exp, len) in lval.indices.iter().zip(dims) {
+                        match exp.calc(consts)? {
+                            i if i >= 0 && (i as usize) < *len => index = index * len + i as usize,
+                            _ => return Err(ErrorKind::NotConstant.at(exp.span())),
+                        }
+                    }
+                    Ok(values[index])
+                }
+                _ => Err(ErrorKind::NotConstant.at(lval.span)),
+            },
+        }
+    }
+}
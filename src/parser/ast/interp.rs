@@ -0,0 +1,611 @@
+//! Tree-walking interpreter for checked SysY programs, it runs the AST
+//! directly with the SysY runtime library, as the semantic reference for
+//! the lowering to Koopa IR.
+//!
+//! Memory is laid out like in the Koopa IR interpreter: every variable,
+//! constant and array is an allocation of words, and array parameters are
+//! a pointer into the allocation of the argument, so out-of-bounds accesses
+//! are caught the same way. Allocations of a block are freed when it ends.
+
+use crate::parser::ast::check::CheckedUnit;
+use crate::parser::ast::structs::*;
+use crate::parser::fold;
+use crate::parser::runtime::{Outcome, RunError, Runtime};
+use koopa::ir::BinaryOp;
+use std::collections::HashMap;
+
+/// Stack size of the thread the program runs on, every SysY call nests a
+/// few Rust calls per expression level, so deep recursion needs plenty.
+const STACK_SIZE: usize = 1 << 30;
+/// Stack kept free for the statements and expressions of the innermost
+/// call, recursion deeper than that stops with an error instead of
+/// overflowing the stack. It is more than the whole stack of the main
+/// thread, which the checker walked the same expressions on.
+const STACK_RESERVE: usize = 16 << 20;
+
+/// Runs `main` of the program on the input, for at most `step_limit`
+/// statements.
+pub fn run(unit: &CheckedUnit, input: &[u8], step_limit: u64) -> Result<Outcome, RunError> {
+    std::thread::scope(|scope| {
+        std::thread::Builder::new()
+            .stack_size(STACK_SIZE)
+            .spawn_scoped(scope, || {
+                let mut interp = Interpreter::new(unit.unit(), input, step_limit);
+                let exit_code = interp.run()?;
+                Ok(Outcome {
+                    exit_code,
+                    output: interp.runtime.into_output(),
+                    steps: interp.steps,
+                })
+            })
+            .expect("failed to spawn the interpreter thread")
+            .join()
+            .unwrap_or_else(|panic| std::panic::resume_unwind(panic))
+    })
+}
+
+/// Address of a local of the caller, the stack grows by the difference of
+/// two such addresses between two calls.
+fn stack_address() -> usize {
+    let local = 0u8;
+    std::hint::black_box(&local) as *const u8 as usize
+}
+
+/// A value computed by an expression, arrays indexed partially are
+/// pointers to their first element.
+#[derive(Debug, Clone, Copy)]
+enum Val {
+    Int(i32),
+    Ptr(Ptr),
+}
+
+/// Address of a word in memory.
+#[derive(Debug, Clone, Copy)]
+struct Ptr {
+    /// index of the allocation
+    alloc: usize,
+    /// offset in words, may be out of the allocation until it is accessed
+    offset: i64,
+}
+
+/// A variable or constant in scope.
+struct Binding {
+    ptr: Ptr,
+    /// dimensions, empty for `int`, the first one is 0 for an array parameter
+    dims: Vec<usize>,
+}
+
+/// How a statement ends.
+enum Flow {
+    Next,
+    Break,
+    Continue,
+    Return(Option<i32>),
+}
+
+struct Interpreter<'a> {
+    funcs: HashMap<&'a str, &'a FuncDef>,
+    globals: &'a [GlobalItem],
+    runtime: Runtime,
+    step_limit: u64,
+    steps: u64,
+    memory: Vec<Vec<i32>>,
+    /// scopes of the current function, the global scope is the first one
+    /// while `main` has not been called
+    scopes: Vec<HashMap<&'a str, Binding>>,
+    global_scope: HashMap<&'a str, Binding>,
+    /// name of the current function
+    func: &'a str,
+    /// number of calls of functions of the program in progress
+    depth: u64,
+    /// address on the stack where `run` started, to measure how much of the
+    /// stack the calls use
+    stack_base: usize,
+}
+
+impl<'a> Interpreter<'a> {
+    fn new(unit: &'a CompUnit, input: &[u8], step_limit: u64) -> Self {
+        let funcs = unit
+            .items
+            .iter()
+            .filter_map(|item| match item {
+                GlobalItem::FuncDef(func) => Some((func.ident.as_str(), func)),
+                GlobalItem::Decl(_) => None,
+            })
+            .collect();
+        Interpreter {
+            funcs,
+            globals: &unit.items,
+            runtime: Runtime::new(input),
+            step_limit,
+            steps: 0,
+            memory: Vec::new(),
+            scopes: vec![HashMap::new()],
+            global_scope: HashMap::new(),
+            func: "",
+            depth: 0,
+            stack_base: 0,
+        }
+    }
+
+    /// Initializes the globals, then runs `main` until it returns.
+    fn run(&mut self) -> Result<i32, RunError> {
+        self.stack_base = stack_address();
+        for item in self.globals.iter() {
+            if let GlobalItem::Decl(decl) = item {
+                self.decl(decl)?;
+            }
+        }
+        self.global_scope = self.scopes.pop().unwrap();
+        if !self.funcs.contains_key("main") {
+            return Err(RunError::UndefinedFunction("main".into()));
+        }
+        Ok(self.call("main", Vec::new())?.unwrap_or(0))
+    }
+
+    fn step(&mut self) -> Result<(), RunError> {
+        self.steps += 1;
+        if self.steps > self.step_limit {
+            return Err(RunError::StepLimit(self.step_limit));
+        }
+        Ok(())
+    }
+
+    fn lookup(&self, name: &str) -> Result<&Binding, RunError> {
+        self.scopes
+            .iter()
+            .rev()
+            .chain(std::iter::once(&self.global_scope))
+            .find_map(|scope| scope.get(name))
+            .ok_or_else(|| RunError::Invalid(format!("use of undeclared `{}` in `{}`", name, self.func)))
+    }
+
+    /// Allocates the words, in the innermost scope.
+    fn alloc(&mut self, words: Vec<i32>) -> Ptr {
+        self.memory.push(words);
+        Ptr {
+            alloc: self.memory.len() - 1,
+            offset: 0,
+        }
+    }
+
+    /// Index of the word the pointer points to, if it is in its allocation.
+    fn cell(&self, ptr: Ptr) -> Result<(usize, usize), RunError> {
+        let len = self.memory.get(ptr.alloc).map_or(0, |words| words.len());
+        if ptr.offset < 0 || ptr.offset as usize >= len {
+            return Err(RunError::OutOfBounds(format!(
+                "in `{}`: word {} of an allocation of {} words",
+                self.func, ptr.offset, len
+            )));
+        }
+        Ok((ptr.alloc, ptr.offset as usize))
+    }
+
+    fn load(&self, ptr: Ptr) -> Result<i32, RunError> {
+        let (alloc, offset) = self.cell(ptr)?;
+        Ok(self.memory[alloc][offset])
+    }
+
+    fn store(&mut self, ptr: Ptr, value: i32) -> Result<(), RunError> {
+        let (alloc, offset) = self.cell(ptr)?;
+        self.memory[alloc][offset] = value;
+        Ok(())
+    }
+
+    fn invalid<T>(&self, reason: &str) -> Result<T, RunError> {
+        Err(RunError::Invalid(format!("{} in `{}`", reason, self.func)))
+    }
+
+    /// Calls a function of the program or of the runtime library.
+    fn call(&mut self, name: &str, args: Vec<Val>) -> Result<Option<i32>, RunError> {
+        self.step()?;
+        let func = match self.funcs.get(name) {
+            Some(&func) => func,
+            None => return self.call_runtime(name, &args),
+        };
+        if func.params.len() != args.len() {
+            return self.invalid(&format!("wrong number of arguments for `{}`", name));
+        }
+        if self.stack_base.abs_diff(stack_address()) > STACK_SIZE - STACK_RESERVE {
+            return Err(RunError::StackOverflow(self.depth));
+        }
+        self.depth += 1;
+        let scopes = std::mem::replace(&mut self.scopes, vec![HashMap::new()]);
+        let caller = std::mem::replace(&mut self.func, &func.ident);
+        let allocs = self.memory.len();
+
+        let mut result = Ok(Flow::Next);
+        for (param, arg) in func.params.iter().zip(args) {
+            let binding = match (&param.dims, arg) {
+                (None, Val::Int(value)) => Binding {
+                    ptr: self.alloc(vec![value]),
+                    dims: Vec::new(),
+                },
+                (Some(dims), Val::Ptr(ptr)) => {
+                    let mut dims = match self.dims(dims) {
+                        Ok(dims) => dims,
+                        Err(err) => {
+                            result = Err(err);
+                            break;
+                        }
+                    };
+                    dims.insert(0, 0);
+                    Binding { ptr, dims }
+                }
+                _ => {
+                    result = self.invalid(&format!("argument of the wrong type for `{}`", param.ident));
+                    break;
+                }
+            };
+            self.scopes[0].insert(&param.ident, binding);
+        }
+        if result.is_ok() {
+            result = self.block(&func.block);
+        }
+
+        self.memory.truncate(allocs);
+        self.func = caller;
+        self.scopes = scopes;
+        self.depth -= 1;
+        match (result?, &func.func_type) {
+            (Flow::Return(value), FuncType::Int) => Ok(Some(value.unwrap_or(0))),
+            // `int` functions which end without `return` give 0, as the
+            // generated code does
+            (_, FuncType::Int) => Ok(Some(0)),
+            (_, FuncType::Void) => Ok(None),
+        }
+    }
+
+    /// Runs a function of the SysY runtime library.
+    fn call_runtime(&mut self, name: &str, args: &[Val]) -> Result<Option<i32>, RunError> {
+        let int = |i: usize| match args.get(i) {
+            Some(Val::Int(int)) => Ok(*int),
+            _ => Err(RunError::Invalid(format!("bad arguments for `{}`", name))),
+        };
+        let ptr = |i: usize| match args.get(i) {
+            Some(Val::Ptr(ptr)) => Ok(*ptr),
+            _ => Err(RunError::Invalid(format!("bad arguments for `{}`", name))),
+        };
+        Ok(match name {
+            "getint" => Some(self.runtime.getint()),
+            "getch" => Some(self.runtime.getch()),
+            "getarray" => {
+                let mut array = ptr(0)?;
+                let n = self.runtime.getint();
+                for _ in 0..n {
+                    let value = self.runtime.getint();
+                    self.store(array, value)?;
+                    array.offset += 1;
+                }
+                Some(n)
+            }
+            "putint" => {
+                self.runtime.putint(int(0)?);
+                None
+            }
+            "putch" => {
+                self.runtime.putch(int(0)?);
+                None
+            }
+            "putarray" => {
+                let mut array = ptr(1)?;
+                let mut values = Vec::new();
+                for _ in 0..int(0)? {
+                    values.push(self.load(array)?);
+                    array.offset += 1;
+                }
+                self.runtime.putarray(&values);
+                None
+            }
+            "starttime" | "stoptime" | "_sysy_starttime" | "_sysy_stoptime" => None,
+            _ => return Err(RunError::UndefinedFunction(name.to_string())),
+        })
+    }
+
+    fn decl(&mut self, decl: &'a Decl) -> Result<(), RunError> {
+        match decl {
+            Decl::Const(decl) => {
+                for def in decl.defs.iter() {
+                    self.def(&def.ident, &def.dims, Some(&def.value))?;
+                }
+            }
+            Decl::Var(decl) => {
+                for def in decl.defs.iter() {
+                    self.def(&def.ident, &def.dims, def.init.as_ref())?;
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Allocates a constant or variable, which is zero without initializer.
+    fn def(&mut self, ident: &'a str, dims: &[ConstExp], init: Option<&InitVal>) -> Result<(), RunError> {
+        let dims = self.dims(dims)?;
+        let words = match init {
+            None => vec![0; dims.iter().product()],
+            Some(InitVal::Exp(exp)) if dims.is_empty() => vec![self.int(exp)?],
+            Some(init) => {
+                let elems = match init.flatten(&dims) {
+                    Ok(elems) => elems,
+                    Err(_) => return self.invalid(&format!("invalid initializer of `{}`", ident)),
+                };
+                let mut words = Vec::with_capacity(elems.len());
+                for elem in elems {
+                    words.push(match elem {
+                        Some(exp) => self.int(exp)?,
+                        None => 0,
+                    });
+                }
+                words
+            }
+        };
+        let ptr = self.alloc(words);
+        self.scopes.last_mut().unwrap().insert(ident, Binding { ptr, dims });
+        Ok(())
+    }
+
+    /// Evaluates array dimensions, which are constant in a checked program.
+    fn dims(&mut self, dims: &[ConstExp]) -> Result<Vec<usize>, RunError> {
+        let mut lens = Vec::new();
+        for dim in dims.iter() {
+            match self.int(dim)? {
+                len if len > 0 => lens.push(len as usize),
+                _ => return self.invalid("array dimension which is not positive"),
+            }
+        }
+        Ok(lens)
+    }
+
+    /// Runs the block in a new scope.
+    fn block(&mut self, block: &'a Block) -> Result<Flow, RunError> {
+        let allocs = self.memory.len();
+        self.scopes.push(HashMap::new());
+        let mut result = Ok(Flow::Next);
+        for item in block.items.iter() {
+            result = match item {
+                BlockItem::Decl(decl) => self.decl(decl).map(|_| Flow::Next),
+                BlockItem::Stmt(stmt) => self.stmt(stmt),
+            };
+            if !matches!(result, Ok(Flow::Next)) {
+                break;
+            }
+        }
+        self.scopes.pop();
+        self.memory.truncate(allocs);
+        result
+    }
+
+    fn stmt(&mut self, stmt: &'a Stmt) -> Result<Flow, RunError> {
+        self.step()?;
+        Ok(match stmt {
+            Stmt::LVal(lval, exp, _) => {
+                let value = self.int(exp)?;
+                let ptr = self.lval_ptr(lval)?;
+                self.store(ptr, value)?;
+                Flow::Next
+            }
+            Stmt::Ret(exp, _) => Flow::Return(match exp {
+                Some(exp) => Some(self.int(exp)?),
+                None => None,
+            }),
+            Stmt::If(cond, then, els, _) => {
+                if self.int(cond)? != 0 {
+                    self.stmt(then)?
+                } else if let Some(els) = els {
+                    self.stmt(els)?
+                } else {
+                    Flow::Next
+                }
+            }
+            Stmt::While(cond, body, _) => {
+                while self.int(cond)? != 0 {
+                    match self.stmt(body)? {
+                        Flow::Break => break,
+                        Flow::Next | Flow::Continue => (),
+                        ret @ Flow::Return(_) => return Ok(ret),
+                    }
+                }
+                Flow::Next
+            }
+            Stmt::Block(block) => self.block(block)?,
+            Stmt::Exp(exp, _) => {
+                if let Some(exp) = exp {
+                    self.exp(exp)?;
+                }
+                Flow::Next
+            }
+            Stmt::Break(_) => Flow::Break,
+            Stmt::Continue(_) => Flow::Continue,
+        })
+    }
+
+    /// Address of the element an lvalue designates, with the dimensions
+    /// left after its indices.
+    fn lval(&mut self, lval: &LVal) -> Result<(Ptr, Vec<usize>), RunError> {
+        let mut indices = Vec::new();
+        for index in lval.indices.iter() {
+            indices.push(self.int(index)?);
+        }
+        let binding = self.lookup(&lval.ident)?;
+        let (mut ptr, dims) = (binding.ptr, binding.dims.clone());
+        if indices.len() > dims.len() {
+            return self.invalid(&format!("too many indices for `{}`", lval.ident));
+        }
+        for (k, index) in indices.iter().enumerate() {
+            let stride: usize = dims[k + 1..].iter().product();
+            ptr.offset += *index as i64 * stride as i64;
+        }
+        Ok((ptr, dims[indices.len()..].to_vec()))
+    }
+
+    /// Address of the `int` an lvalue designates.
+    fn lval_ptr(&mut self, lval: &LVal) -> Result<Ptr, RunError> {
+        match self.lval(lval)? {
+            (ptr, dims) if dims.is_empty() => Ok(ptr),
+            _ => self.invalid(&format!("assignment to the array `{}`", lval.ident)),
+        }
+    }
+
+    fn int(&mut self, exp: &Exp) -> Result<i32, RunError> {
+        match self.exp(exp)? {
+            Some(Val::Int(value)) => Ok(value),
+            Some(Val::Ptr(_)) => self.invalid("array used as an integer"),
+            None => self.invalid("value of a `void` call used"),
+        }
+    }
+
+    /// Evaluates the expression, `None` for a call of a `void` function.
+    fn exp(&mut self, exp: &Exp) -> Result<Option<Val>, RunError> {
+        match exp {
+            Exp::Exp(exp) => self.lor(exp),
+        }
+    }
+
+    fn lor(&mut self, exp: &LOrExp) -> Result<Option<Val>, RunError> {
+        match exp {
+            LOrExp::LAndExp(exp) => self.land(exp),
+            LOrExp::LOrExp(lhs, rhs, _) => {
+                let lhs = self.lor(lhs)?;
+                let value = self.bool(lhs)? || {
+                    let rhs = self.land(rhs)?;
+                    self.bool(rhs)?
+                };
+                Ok(Some(Val::Int(value as i32)))
+            }
+        }
+    }
+
+    fn land(&mut self, exp: &LAndExp) -> Result<Option<Val>, RunError> {
+        match exp {
+            LAndExp::EqExp(exp) => self.eq(exp),
+            LAndExp::LAndExp(lhs, rhs, _) => {
+                let lhs = self.land(lhs)?;
+                let value = self.bool(lhs)? && {
+                    let rhs = self.eq(rhs)?;
+                    self.bool(rhs)?
+                };
+                Ok(Some(Val::Int(value as i32)))
+            }
+        }
+    }
+
+    fn bool(&self, value: Option<Val>) -> Result<bool, RunError> {
+        match value {
+            Some(Val::Int(value)) => Ok(value != 0),
+            _ => self.invalid("condition which is not an integer"),
+        }
+    }
+
+    fn eq(&mut self, exp: &EqExp) -> Result<Option<Val>, RunError> {
+        match exp {
+            EqExp::RelExp(exp) => self.rel(exp),
+            EqExp::EqExp(lhs, op, rhs, _) => {
+                let op = match op {
+                    EqOp::Eq => BinaryOp::Eq,
+                    EqOp::Ne => BinaryOp::NotEq,
+                };
+                let (lhs, rhs) = (self.eq(lhs)?, self.rel(rhs)?);
+                self.binary(op, lhs, rhs)
+            }
+        }
+    }
+
+    fn rel(&mut self, exp: &RelExp) -> Result<Option<Val>, RunError> {
+        match exp {
+            RelExp::AddExp(exp) => self.add(exp),
+            RelExp::RelExp(lhs, op, rhs, _) => {
+                let op = match op {
+                    RelOp::Lt => BinaryOp::Lt,
+                    RelOp::Le => BinaryOp::Le,
+                    RelOp::Gt => BinaryOp::Gt,
+                    RelOp::Ge => BinaryOp::Ge,
+                };
+                let (lhs, rhs) = (self.rel(lhs)?, self.add(rhs)?);
+                self.binary(op, lhs, rhs)
+            }
+        }
+    }
+
+    fn add(&mut self, exp: &AddExp) -> Result<Option<Val>, RunError> {
+        match exp {
+            AddExp::MulExp(exp) => self.mul(exp),
+            AddExp::AddExp(lhs, op, rhs, _) => {
+                let op = match op {
+                    AddOp::Add => BinaryOp::Add,
+                    AddOp::Sub => BinaryOp::Sub,
+                };
+                let (lhs, rhs) = (self.add(lhs)?, self.mul(rhs)?);
+                self.binary(op, lhs, rhs)
+            }
+        }
+    }
+
+    fn mul(&mut self, exp: &MulExp) -> Result<Option<Val>, RunError> {
+        match exp {
+            MulExp::UnaryExp(exp) => self.unary(exp),
+            MulExp::MulExp(lhs, op, rhs, _) => {
+                let op = match op {
+                    MulOp::Mul => BinaryOp::Mul,
+                    MulOp::Div => BinaryOp::Div,
+                    MulOp::Mod => BinaryOp::Mod,
+                };
+                let (lhs, rhs) = (self.mul(lhs)?, self.unary(rhs)?);
+                self.binary(op, lhs, rhs)
+            }
+        }
+    }
+
+    /// Computes `lhs op rhs` on integers, with the wrapping arithmetic of
+    /// the generated code.
+    fn binary(&self, op: BinaryOp, lhs: Option<Val>, rhs: Option<Val>) -> Result<Option<Val>, RunError> {
+        match (lhs, rhs) {
+            (Some(Val::Int(lhs)), Some(Val::Int(rhs))) => match fold::checked_binary(op, lhs, rhs) {
+                Some(value) => Ok(Some(Val::Int(value))),
+                None => Err(RunError::DivisionByZero(self.func.to_string())),
+            },
+            _ => self.invalid("operand which is not an integer"),
+        }
+    }
+
+    fn unary(&mut self, exp: &UnaryExp) -> Result<Option<Val>, RunError> {
+        match exp {
+            UnaryExp::PrimaryExp(exp) => self.primary(exp),
+            UnaryExp::UnaryOp(op, exp, _) => {
+                let value = match self.unary(exp)? {
+                    Some(Val::Int(value)) => value,
+                    _ => return self.invalid("operand which is not an integer"),
+                };
+                Ok(Some(Val::Int(match op {
+                    UnaryOp::Plus => value,
+                    UnaryOp::Minus => fold::neg(value),
+                    UnaryOp::Not => fold::not(value),
+                })))
+            }
+            UnaryExp::Call(ident, args, _, _) => {
+                let mut values = Vec::new();
+                for arg in args.iter() {
+                    match self.exp(arg)? {
+                        Some(value) => values.push(value),
+                        None => return self.invalid("value of a `void` call used"),
+                    }
+                }
+                Ok(self.call(ident, values)?.map(Val::Int))
+            }
+        }
+    }
+
+    fn primary(&mut self, exp: &PrimaryExp) -> Result<Option<Val>, RunError> {
+        match exp {
+            PrimaryExp::Exp(exp, _) => self.exp(exp),
+            PrimaryExp::Number(num, _) => Ok(Some(Val::Int(*num))),
+            PrimaryExp::LVal(lval) => {
+                let (ptr, dims) = self.lval(lval)?;
+                if dims.is_empty() {
+                    Ok(Some(Val::Int(self.load(ptr)?)))
+                } else {
+                    Ok(Some(Val::Ptr(ptr)))
+                }
+            }
+        }
+    }
+}
@@ -1,3 +1,7 @@
+pub mod check;
+pub mod error;
+pub mod eval;
+pub mod interp;
 pub mod structs;
 pub mod traits;
 pub mod vm;
\ No newline at end of file
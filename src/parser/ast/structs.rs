@@ -1,6 +1,22 @@
+// the AST is built once and walked by reference, boxing every large
+// variant would only make the grammar noisier
+#![allow(clippy::large_enum_variant)]
+
+use crate::parser::diagnostic::Span;
+
+// Every node covers its source text with a span, variants which only wrap
+// another node share the span of the wrapped node.
+
 #[derive(Debug)]
 pub struct CompUnit {
-    pub func_def: FuncDef,
+    pub items: Vec<GlobalItem>,
+    pub span: Span,
+}
+
+#[derive(Debug)]
+pub enum GlobalItem {
+    Decl(Decl),
+    FuncDef(FuncDef),
 }
 
 #[derive(Debug)]
@@ -13,47 +29,72 @@ pub enum Decl {
 #[derive(Debug)]
 pub struct ConstDecl {
     pub defs: Vec<ConstDef>,
+    pub span: Span,
 }
 
 #[derive(Debug)]
 pub struct VarDecl {
     pub defs: Vec<VarDef>,
+    pub span: Span,
 }
 
 
 #[derive(Debug)]
 pub struct ConstDef {
     pub ident: String,
+    /// array dimensions, empty for a scalar
+    pub dims: Vec<ConstExp>,
     pub value: ConstInitVal,
+    pub span: Span,
 }
 
 #[derive(Debug)]
-pub enum VarDef {
-    Ident(Ident),
-    InitVal(Ident, InitVal)
+pub struct VarDef {
+    pub ident: Ident,
+    /// array dimensions, empty for a scalar
+    pub dims: Vec<ConstExp>,
+    pub init: Option<InitVal>,
+    pub span: Span,
 }
 
-pub type ConstInitVal = ConstExp;
+pub type ConstInitVal = InitVal;
 
 pub type ConstExp = Exp;
 
 pub type Ident = String;
 
-pub type InitVal = Exp;
+#[derive(Debug)]
+pub enum InitVal {
+    Exp(Exp),
+    /// {init, ...}
+    List(Vec<InitVal>, Span),
+}
 
 #[derive(Debug)]
 pub struct FuncDef {
     pub func_type: FuncType,
     pub ident: Ident,
+    pub params: Vec<FuncFParam>,
     pub block: Block,
+    pub span: Span,
+}
+
+#[derive(Debug)]
+pub struct FuncFParam {
+    pub ident: Ident,
+    /// `Some` for an array parameter, with the dimensions after the first `[]`
+    pub dims: Option<Vec<ConstExp>>,
+    pub span: Span,
 }
 #[derive(Debug)]
 pub enum FuncType {
     Int,
+    Void,
 }
 #[derive(Debug)]
 pub struct Block {
     pub items: Vec<BlockItem>,
+    pub span: Span,
 }
 
 #[derive(Debug)]
@@ -64,8 +105,17 @@ pub enum BlockItem {
 
 #[derive(Debug)]
 pub enum Stmt {
-    LVal(LVal, Exp),
-    Ret(Exp)
+    LVal(LVal, Exp, Span),
+    Ret(Option<Exp>, Span),
+    /// if (cond) then [else els]
+    If(Exp, Box<Stmt>, Option<Box<Stmt>>, Span),
+    /// while (cond) body
+    While(Exp, Box<Stmt>, Span),
+    Block(Block),
+    /// [exp];
+    Exp(Option<Exp>, Span),
+    Break(Span),
+    Continue(Span),
 }
 
 #[derive(Debug)]
@@ -73,21 +123,39 @@ pub enum Exp {
     Exp(LOrExp),
 }
 
-pub type LVal = Ident;
+#[derive(Debug)]
+pub struct LVal {
+    pub ident: Ident,
+    /// ident[index]...
+    pub indices: Vec<Exp>,
+    pub span: Span,
+}
 
 #[derive(Debug)]
 pub enum PrimaryExp {
-    Exp(Box<Exp>),
+    /// (exp)
+    Exp(Box<Exp>, Span),
     LVal(LVal),
-    Number(Number),
+    Number(Number, Span),
 }
 
 pub type Number = i32;
 
+/// Parses the digits of an integer literal as a 32-bit unsigned value and
+/// reinterprets it in two's complement, so `2147483648` is `i32::MIN` (the
+/// operand of `-2147483648`) and `0xFFFFFFFF` is `-1`. Returns `None` if the
+/// value does not fit in 32 bits.
+pub fn parse_number(digits: &str, radix: u32) -> Option<Number> {
+    u32::from_str_radix(digits, radix).ok().map(|n| n as i32)
+}
+
 #[derive(Debug)]
 pub enum UnaryExp {
     PrimaryExp(PrimaryExp),
-    UnaryOp(UnaryOp, Box<UnaryExp>),
+    UnaryOp(UnaryOp, Box<UnaryExp>, Span),
+    /// ident(args), with the source line of the call for `starttime` and
+    /// `stoptime`, 0 for other functions
+    Call(Ident, Vec<Exp>, usize, Span),
 }
 
 #[derive(Debug)]
@@ -101,7 +169,7 @@ pub enum UnaryOp {
 #[derive(Debug)]
 pub enum MulExp {
     UnaryExp(UnaryExp),
-    MulExp(Box<MulExp>, MulOp, UnaryExp),
+    MulExp(Box<MulExp>, MulOp, UnaryExp, Span),
 }
 
 #[derive(Debug)]
@@ -114,7 +182,7 @@ pub enum MulOp {
 #[derive(Debug)]
 pub enum AddExp {
     MulExp(MulExp),
-    AddExp(Box<AddExp>, AddOp, MulExp),
+    AddExp(Box<AddExp>, AddOp, MulExp, Span),
 }
 
 #[derive(Debug)]
@@ -126,7 +194,7 @@ pub enum AddOp {
 #[derive(Debug)]
 pub enum RelExp {
     AddExp(AddExp),
-    RelExp(Box<RelExp>, RelOp, AddExp),
+    RelExp(Box<RelExp>, RelOp, AddExp, Span),
 }
 
 #[derive(Debug)]
@@ -140,7 +208,7 @@ pub enum RelOp {
 #[derive(Debug)]
 pub enum EqExp {
     RelExp(RelExp),
-    EqExp(Box<EqExp>, EqOp, RelExp),
+    EqExp(Box<EqExp>, EqOp, RelExp, Span),
 }
 
 #[derive(Debug)]
@@ -152,12 +220,115 @@ pub enum EqOp {
 #[derive(Debug)]
 pub enum LAndExp {
     EqExp(EqExp),
-    LAndExp(Box<LAndExp>, EqExp),
+    LAndExp(Box<LAndExp>, EqExp, Span),
 }
 
 #[derive(Debug)]
 pub enum LOrExp {
     LAndExp(LAndExp),
-    LOrExp(Box<LOrExp>, LAndExp),
+    LOrExp(Box<LOrExp>, LAndExp, Span),
+}
+
+impl InitVal {
+    pub fn span(&self) -> Span {
+        match self {
+            InitVal::Exp(exp) => exp.span(),
+            InitVal::List(_, span) => *span,
+        }
+    }
+}
+
+impl Stmt {
+    pub fn span(&self) -> Span {
+        match self {
+            Stmt::LVal(.., span)
+            | Stmt::Ret(_, span)
+            | Stmt::If(.., span)
+            | Stmt::While(.., span)
+            | Stmt::Exp(_, span)
+            | Stmt::Break(span)
+            | Stmt::Continue(span) => *span,
+            Stmt::Block(block) => block.span,
+        }
+    }
+}
+
+impl Exp {
+    pub fn span(&self) -> Span {
+        match self {
+            Exp::Exp(exp) => exp.span(),
+        }
+    }
+}
+
+impl PrimaryExp {
+    pub fn span(&self) -> Span {
+        match self {
+            PrimaryExp::Exp(_, span) | PrimaryExp::Number(_, span) => *span,
+            PrimaryExp::LVal(lval) => lval.span,
+        }
+    }
+}
+
+impl UnaryExp {
+    pub fn span(&self) -> Span {
+        match self {
+            UnaryExp::PrimaryExp(exp) => exp.span(),
+            UnaryExp::UnaryOp(.., span) | UnaryExp::Call(.., span) => *span,
+        }
+    }
+}
+
+impl MulExp {
+    pub fn span(&self) -> Span {
+        match self {
+            MulExp::UnaryExp(exp) => exp.span(),
+            MulExp::MulExp(.., span) => *span,
+        }
+    }
+}
+
+impl AddExp {
+    pub fn span(&self) -> Span {
+        match self {
+            AddExp::MulExp(exp) => exp.span(),
+            AddExp::AddExp(.., span) => *span,
+        }
+    }
+}
+
+impl RelExp {
+    pub fn span(&self) -> Span {
+        match self {
+            RelExp::AddExp(exp) => exp.span(),
+            RelExp::RelExp(.., span) => *span,
+        }
+    }
 }
 
+impl EqExp {
+    pub fn span(&self) -> Span {
+        match self {
+            EqExp::RelExp(exp) => exp.span(),
+            EqExp::EqExp(.., span) => *span,
+        }
+    }
+}
+
+impl LAndExp {
+    pub fn span(&self) -> Span {
+        match self {
+            LAndExp::EqExp(exp) => exp.span(),
+            LAndExp::LAndExp(.., span) => *span,
+        }
+    }
+}
+
+impl LOrExp {
+    pub fn span(&self) -> Span {
+        match self {
+            LOrExp::LAndExp(exp) => exp.span(),
+            LOrExp::LOrExp(.., span) => *span,
+        }
+    }
+}
@@ -1,16 +1,11 @@
 use crate::parser::ast::structs::*;
 use koopa::ir::{builder_traits::*, *};
-use core::panic;
-use std::sync::atomic::{AtomicUsize, Ordering};
+use std::convert::TryFrom;
+use crate::parser::ast::check::CheckedUnit;
+use crate::parser::ast::error::{BuildError, ErrorKind};
+use crate::parser::ast::eval::Consts;
 use crate::parser::ast::vm::{self, ValueManager};
-static CNT: AtomicUsize = AtomicUsize::new(0);
-
-macro_rules! next_bb_id {
-    ($prefix:expr) => {{
-        let cnt = CNT.fetch_add(1, Ordering::SeqCst);
-        Some(format!("{}_{}", $prefix, cnt))
-    }};
-}
+use crate::parser::diagnostic::Span;
 
 macro_rules! insert_op {
     ($program:expr, $params:expr, $op:expr, $l:expr, $r:expr) => {
@@ -31,84 +26,393 @@ macro_rules! insert_op {
     };
 }
 
-impl Into<Program> for CompUnit {
-    fn into(self) -> Program {
+impl TryFrom<CheckedUnit> for Program {
+    type Error = BuildError;
+
+    fn try_from(unit: CheckedUnit) -> Result<Program, BuildError> {
+        let unit = unit.unit();
         let mut program = Program::new();
+        let mut vm = ValueManager::new();
+        declare_runtime(&mut program, &mut vm);
+
+        // global variables are visible in all functions, their names must
+        // not be reused by local variables
+        for item in unit.items.iter() {
+            match item {
+                GlobalItem::Decl(Decl::Var(decl)) => {
+                    for def in decl.defs.iter() {
+                        vm.reserve_global(&format!("@{}", def.ident));
+                    }
+                }
+                GlobalItem::Decl(Decl::Const(decl)) => {
+                    for def in decl.defs.iter() {
+                        vm.reserve_global(&format!("@{}", def.ident));
+                    }
+                }
+                GlobalItem::FuncDef(_) => {}
+            }
+        }
+
+        // globals and function declarations first, in order, so that
+        // functions can be called before their definitions (recursion and
+        // mutual recursion), and array parameters can use global constants
+        let mut funcs = Vec::new();
+        for item in unit.items.iter() {
+            match item {
+                GlobalItem::FuncDef(func_def) => funcs.push(func_def.declare(&mut program, &mut vm)?),
+                GlobalItem::Decl(decl) => decl.build_global(&mut program, &mut vm)?,
+            }
+        }
+
+        let mut funcs = funcs.into_iter();
+        for item in unit.items.iter() {
+            if let GlobalItem::FuncDef(func_def) = item {
+                func_def.build(&mut program, &mut vm, funcs.next().unwrap())?;
+            }
+        }
+        Ok(program)
+    }
+}
+
+/// The SysY runtime library, which every program can call: the name,
+/// whether each parameter is an `int[]`, and the return type.
+///
+/// `starttime()` and `stoptime()` are macros of `sylib.h`, they call
+/// `_sysy_starttime` and `_sysy_stoptime` with the line number.
+pub(crate) const RUNTIME: &[(&str, &[bool], FuncType)] = &[
+    ("getint", &[], FuncType::Int),
+    ("getch", &[], FuncType::Int),
+    ("getarray", &[true], FuncType::Int),
+    ("putint", &[false], FuncType::Void),
+    ("putch", &[false], FuncType::Void),
+    ("putarray", &[false, true], FuncType::Void),
+    ("_sysy_starttime", &[false], FuncType::Void),
+    ("_sysy_stoptime", &[false], FuncType::Void),
+];
 
-        // create func
-        let main = program.new_func(FunctionData::new(
-            format!("@{}", self.func_def.ident),
-            Vec::new(),
-            self.func_def.func_type.into(),
+/// Declares the SysY runtime library.
+fn declare_runtime(program: &mut Program, vm: &mut ValueManager) {
+    for (name, params, func_type) in RUNTIME {
+        let params_ty = params
+            .iter()
+            .map(|&is_array| match is_array {
+                true => Type::get_pointer(Type::get_i32()),
+                false => Type::get_i32(),
+            })
+            .collect();
+        let func = program.new_func(FunctionData::new_decl(format!("@{}", name), params_ty, func_type.into()));
+        vm.insert_func(name, func).expect("runtime library declared twice");
+    }
+}
+
+impl FuncDef {
+    /// Creates the function in the program, without its body.
+    fn declare(&self, program: &mut Program, vm: &mut ValueManager) -> Result<Function, BuildError> {
+        vm.reset_names();
+        let mut params = Vec::new();
+        for param in self.params.iter() {
+            params.push((Some(vm.unique_name(&param.ident)), param.ty(vm)?));
+        }
+        let func = program.new_func(FunctionData::with_param_names(
+            format!("@{}", self.ident),
+            params,
+            (&self.func_type).into(),
         ));
+        vm.insert_func(&self.ident, func).map_err(|e| e.at(self.span))?;
+        Ok(func)
+    }
 
-        // fill func
-        let main_data = program.func_mut(main);
-        let bb = main_data
-            .dfg_mut()
-            .new_bb()
-            .basic_block(next_bb_id!("%main"));
-        main_data.layout_mut().bbs_mut().push_key_back(bb).unwrap();
+    /// Builds the body of the declared function.
+    fn build(&self, program: &mut Program, vm: &mut ValueManager, func: Function) -> Result<(), BuildError> {
+        vm.reset_names();
+        let mut params = BuildParams::new(program, func, vm);
 
-        let mut params = BuildParams {
-            func: main,
-            bb,
-            v: None,
-            vm: ValueManager::new(),
-        };
-        // parse exp
-        self.func_def.block.build(&mut program, &mut params);
-        program
+        // parameters share the scope with the outermost block of the body,
+        // each of them is spilled into an alloc so it can be assigned
+        params.vm.push_scope();
+        let args = program.func(func).params().to_vec();
+        for (param, arg) in self.params.iter().zip(args) {
+            let func_data = program.func_mut(func);
+            let ty = func_data.dfg().value(arg).ty().clone();
+            let alloc = func_data.dfg_mut().new_value().alloc(ty);
+            func_data
+                .dfg_mut()
+                .set_value_name(alloc, Some(format!("%{}", param.ident)));
+            let store = func_data.dfg_mut().new_value().store(arg, alloc);
+            params.push_alloc(program, alloc);
+            params.push_insts(program, &[store]);
+            if let Some(name) = program.func(func).dfg().value(arg).name() {
+                params.vm.reserve_name(name);
+            }
+            params.vm.insert_var(&param.ident, alloc).map_err(|e| e.at(param.span))?;
+        }
+        for item in self.block.items.iter() {
+            item.build(program, &mut params)?;
+        }
+        params.vm.pop_scope();
+
+        // basic blocks falling off the end of the function return implicitly,
+        // with 0 for functions returning int (required for `main`)
+        let func_data = program.func_mut(func);
+        let bbs: Vec<BasicBlock> = func_data.layout().bbs().keys().copied().collect();
+        for bb in bbs {
+            if !is_terminated(func_data, bb) {
+                let ret_v = match self.func_type {
+                    FuncType::Int => Some(func_data.dfg_mut().new_value().integer(0)),
+                    FuncType::Void => None,
+                };
+                let ret = func_data.dfg_mut().new_value().ret(ret_v);
+                func_data.layout_mut().bb_mut(bb).insts_mut().push_key_back(ret).unwrap();
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Whether the basic block ends with `br`, `jump` or `ret`.
+fn is_terminated(func_data: &FunctionData, bb: BasicBlock) -> bool {
+    let node = func_data.layout().bbs().node(&bb).unwrap();
+    match node.insts().back_key() {
+        Some(inst) => matches!(
+            func_data.dfg().value(*inst).kind(),
+            ValueKind::Branch(_) | ValueKind::Jump(_) | ValueKind::Return(_)
+        ),
+        None => false,
     }
 }
 
 /// Build params.
-struct BuildParams {
+struct BuildParams<'a> {
     func: Function,
     bb: BasicBlock,
+    /// entry block of the function, which holds every alloc
+    entry: BasicBlock,
+    /// last alloc in the entry block, the next one goes after it
+    last_alloc: Option<Value>,
     /// last value
     v: Option<Value>,
 
     /// variable manager
-    vm: ValueManager,
+    vm: &'a mut ValueManager,
+
+    /// (entry, exit) basic blocks of the enclosing loops, innermost last
+    loops: Vec<(BasicBlock, BasicBlock)>,
+
+    /// number of basic blocks created in the function, numbers their names
+    bb_cnt: usize,
 }
 
-impl Into<Type> for FuncType {
-    fn into(self) -> Type {
-        match self {
+impl<'a> BuildParams<'a> {
+    /// Starts building the body of the function in a new entry block.
+    fn new(program: &mut Program, func: Function, vm: &'a mut ValueManager) -> BuildParams<'a> {
+        let bb = program.func_mut(func).dfg_mut().new_bb().basic_block(None);
+        let mut params = BuildParams {
+            func,
+            bb,
+            entry: bb,
+            last_alloc: None,
+            v: None,
+            vm,
+            loops: Vec::new(),
+            bb_cnt: 0,
+        };
+        let name = params.bb_name("%entry");
+        program.func_mut(func).dfg_mut().bb_mut(bb).set_name(name);
+        params.enter_bb(program, bb);
+        params
+    }
+
+    /// Returns the next name for a basic block, unique in the function.
+    fn bb_name(&mut self, prefix: &str) -> Option<String> {
+        let name = format!("{}_{}", prefix, self.bb_cnt);
+        self.bb_cnt += 1;
+        Some(name)
+    }
+
+    /// Creates a new basic block, it is added to the layout by `enter_bb`.
+    fn new_bb(&mut self, program: &mut Program, prefix: &str) -> BasicBlock {
+        let name = self.bb_name(prefix);
+        program.func_mut(self.func).dfg_mut().new_bb().basic_block(name)
+    }
+
+    /// Appends the basic block to the layout and makes it the current one.
+    fn enter_bb(&mut self, program: &mut Program, bb: BasicBlock) {
+        program
+            .func_mut(self.func)
+            .layout_mut()
+            .bbs_mut()
+            .push_key_back(bb)
+            .unwrap();
+        self.bb = bb;
+    }
+
+    /// Appends the instructions to the current basic block.
+    fn push_insts(&self, program: &mut Program, insts: &[Value]) {
+        program
+            .func_mut(self.func)
+            .layout_mut()
+            .bb_mut(self.bb)
+            .insts_mut()
+            .extend(insts.iter().copied());
+    }
+
+    /// Puts the alloc after the previous ones at the start of the entry
+    /// block, so that it runs once per call even if it is declared in a
+    /// loop.
+    fn push_alloc(&mut self, program: &mut Program, alloc: Value) {
+        let insts = program.func_mut(self.func).layout_mut().bb_mut(self.entry).insts_mut();
+        match self.last_alloc {
+            Some(last) => insts.cursor_mut(last).insert_key_after(alloc).unwrap(),
+            None => insts.push_key_front(alloc).unwrap(),
+        }
+        self.last_alloc = Some(alloc);
+    }
+
+    /// Whether the current basic block already ends with `br`, `jump` or `ret`.
+    fn is_terminated(&self, program: &Program) -> bool {
+        is_terminated(program.func(self.func), self.bb)
+    }
+
+    /// Allocates a local variable of `ty` in the entry block.
+    fn new_alloc(&mut self, program: &mut Program, ident: &str, ty: Type) -> Value {
+        let func_data = program.func_mut(self.func);
+        let alloc = func_data.dfg_mut().new_value().alloc(ty);
+        let name = self.vm.unique_name(ident);
+        func_data.dfg_mut().set_value_name(alloc, Some(name));
+        self.push_alloc(program, alloc);
+        alloc
+    }
+
+    /// Pointer to the `index`-th element of the flattened array `base`.
+    fn elem_ptr(&mut self, program: &mut Program, base: Value, dims: &[usize], index: usize) -> Value {
+        let mut ptr = base;
+        let mut insts = Vec::new();
+        let mut stride: usize = dims.iter().product();
+        for len in dims.iter() {
+            stride /= len;
+            let func_data = program.func_mut(self.func);
+            let i = func_data.dfg_mut().new_value().integer((index / stride % len) as i32);
+            ptr = func_data.dfg_mut().new_value().get_elem_ptr(ptr, i);
+            insts.push(ptr);
+        }
+        self.push_insts(program, &insts);
+        ptr
+    }
+
+    /// Type of a local or global value.
+    fn value_ty(&self, program: &Program, v: Value) -> Type {
+        if v.is_global() {
+            program.borrow_value(v).ty().clone()
+        } else {
+            program.func(self.func).dfg().value(v).ty().clone()
+        }
+    }
+
+    /// Takes the last value, which must be an integer, `span` is the
+    /// expression it comes from.
+    fn take_value(&mut self, program: &Program, span: Span) -> Result<Value, BuildError> {
+        let v = self.take_arg(program, span)?;
+        if !program.func(self.func).dfg().value(v).ty().is_i32() {
+            return Err(ErrorKind::ArrayAsValue.at(span));
+        }
+        Ok(v)
+    }
+
+    /// Takes the last value as a call argument, which can also be a pointer,
+    /// but must not come from a void call.
+    fn take_arg(&mut self, program: &Program, span: Span) -> Result<Value, BuildError> {
+        let v = self.v.take().unwrap();
+        if program.func(self.func).dfg().value(v).ty().is_unit() {
+            return Err(ErrorKind::VoidValue.at(span));
+        }
+        Ok(v)
+    }
+
+    /// Return type of the current function.
+    fn ret_ty(&self, program: &Program) -> Type {
+        match program.func(self.func).ty().kind() {
+            TypeKind::Function(_, ret) => ret.clone(),
+            _ => unreachable!(),
+        }
+    }
+
+    /// Jumps to `target` if the current basic block is not terminated yet.
+    fn jump_to(&self, program: &mut Program, target: BasicBlock) {
+        if !self.is_terminated(program) {
+            let jump = program
+                .func_mut(self.func)
+                .dfg_mut()
+                .new_value()
+                .jump(target);
+            self.push_insts(program, &[jump]);
+        }
+    }
+}
+
+impl FuncFParam {
+    /// Koopa type of the parameter, `int a[][3]` is a pointer `*[i32, 3]`.
+    fn ty(&self, vm: &ValueManager) -> Result<Type, BuildError> {
+        match &self.dims {
+            None => Ok(Type::get_i32()),
+            Some(dims) => Ok(Type::get_pointer(array_type(&eval_dims(dims, vm)?))),
+        }
+    }
+}
+
+impl From<&FuncType> for Type {
+    fn from(func_type: &FuncType) -> Type {
+        match func_type {
             FuncType::Int => Type::get_i32(),
+            FuncType::Void => Type::get_unit(),
         }
     }
 }
 
 impl Block {
-    fn build(&self, program: &mut Program, params: &mut BuildParams) {
+    fn build(&self, program: &mut Program, params: &mut BuildParams) -> Result<(), BuildError> {
+        params.vm.push_scope();
         for item in self.items.iter() {
-            item.build(program, params);
+            item.build(program, params)?;
         }
+        params.vm.pop_scope();
+        Ok(())
     }
 }
 
 impl BlockItem {
-    fn build(&self, program: &mut Program, params: &mut BuildParams) {
+    fn build(&self, program: &mut Program, params: &mut BuildParams) -> Result<(), BuildError> {
+        // code after a terminator is unreachable, but still needs a basic block
+        if params.is_terminated(program) {
+            let bb = params.new_bb(program, "%unreachable");
+            params.enter_bb(program, bb);
+        }
         match self {
-            BlockItem::Stmt(stmt) => stmt.build(program, params),
-            BlockItem::Decl(decl) => decl.build(program, params),
+            BlockItem::Stmt(stmt) => stmt.build(program, params)?,
+            BlockItem::Decl(decl) => decl.build(program, params)?,
         }
+        Ok(())
     }
 }
 
 
 impl Stmt {
-    fn build(&self, program: &mut Program, params: &mut BuildParams) {
+    fn build(&self, program: &mut Program, params: &mut BuildParams) -> Result<(), BuildError> {
         match self {
-            Stmt::Ret(exp) => {
-                exp.build(program, params);
+            Stmt::Ret(exp, span) => {
+                let v = match exp {
+                    Some(exp) => {
+                        exp.build(program, params)?;
+                        Some(params.take_value(program, exp.span())?)
+                    }
+                    None => None,
+                };
+                if v.is_some() == params.ret_ty(program).is_unit() {
+                    return Err(ErrorKind::ReturnMismatch.at(*span));
+                }
 
-                // create a new basic block for ret
                 let func_data = program.func_mut(params.func);
             
-                let ret = func_data.dfg_mut().new_value().ret(params.v);
+                let ret = func_data.dfg_mut().new_value().ret(v);
                 func_data
                     .layout_mut()
                     .bb_mut(params.bb)
@@ -116,89 +420,389 @@ impl Stmt {
                     .extend([ret]);
                 params.v = None; // clear
             }
-            Stmt::LVal(lval, exp) => {
-                exp.build(program, params);
-                let v = params.v.take().unwrap();
-                let func_data = program.func_mut(params.func);
-                let lv = params.vm.get(&lval).unwrap();
-                match *lv {
-                    vm::Decl::Var(lv) => {
-                        let s = func_data.dfg_mut().new_value().store(v, lv);
-                        func_data.layout_mut().bb_mut(params.bb).insts_mut().extend([s]);
+            Stmt::LVal(lval, exp, _) => {
+                exp.build(program, params)?;
+                let v = params.take_value(program, exp.span())?;
+                match lval.decl(params.vm)? {
+                    vm::Decl::Var(_) => {
+                        let ptr = lval.build_ptr(program, params)?;
+                        if !pointee(&params.value_ty(program, ptr)).is_i32() {
+                            return Err(ErrorKind::IndexMismatch(lval.ident.clone()).at(lval.span));
+                        }
+                        let s = program.func_mut(params.func).dfg_mut().new_value().store(v, ptr);
+                        params.push_insts(program, &[s]);
                     }
-                    _ => panic!()
+                    _ => return Err(ErrorKind::AssignToConst(lval.ident.clone()).at(lval.span)),
                 }
             }
+            Stmt::If(cond, then, els, _) => {
+                cond.build(program, params)?;
+                let cond_v = params.take_value(program, cond.span())?;
+
+                let then_bb = params.new_bb(program, "%then");
+                let else_bb = els.as_ref().map(|_| params.new_bb(program, "%else"));
+                let end_bb = params.new_bb(program, "%end");
+
+                let br = program.func_mut(params.func).dfg_mut().new_value().branch(
+                    cond_v,
+                    then_bb,
+                    else_bb.unwrap_or(end_bb),
+                );
+                params.push_insts(program, &[br]);
+
+                params.enter_bb(program, then_bb);
+                then.build(program, params)?;
+                params.jump_to(program, end_bb);
+
+                if let (Some(els), Some(else_bb)) = (els, else_bb) {
+                    params.enter_bb(program, else_bb);
+                    els.build(program, params)?;
+                    params.jump_to(program, end_bb);
+                }
+
+                params.enter_bb(program, end_bb);
+            }
+            Stmt::While(cond, body, _) => {
+                let entry_bb = params.new_bb(program, "%while_entry");
+                let body_bb = params.new_bb(program, "%while_body");
+                let end_bb = params.new_bb(program, "%while_end");
+
+                params.jump_to(program, entry_bb);
+                params.enter_bb(program, entry_bb);
+                cond.build(program, params)?;
+                let cond_v = params.take_value(program, cond.span())?;
+                let br = program
+                    .func_mut(params.func)
+                    .dfg_mut()
+                    .new_value()
+                    .branch(cond_v, body_bb, end_bb);
+                params.push_insts(program, &[br]);
+
+                params.enter_bb(program, body_bb);
+                params.loops.push((entry_bb, end_bb));
+                body.build(program, params)?;
+                params.loops.pop();
+                params.jump_to(program, entry_bb);
+
+                params.enter_bb(program, end_bb);
+            }
+            Stmt::Block(block) => block.build(program, params)?,
+            Stmt::Exp(exp, _) => {
+                if let Some(exp) = exp {
+                    exp.build(program, params)?;
+                    params.v = None;
+                }
+            }
+            Stmt::Break(span) => {
+                let &(_, exit) = params
+                    .loops
+                    .last()
+                    .ok_or_else(|| ErrorKind::BreakOutsideLoop.at(*span))?;
+                params.jump_to(program, exit);
+            }
+            Stmt::Continue(span) => {
+                let &(entry, _) = params
+                    .loops
+                    .last()
+                    .ok_or_else(|| ErrorKind::ContinueOutsideLoop.at(*span))?;
+                params.jump_to(program, entry);
+            }
         }
+        Ok(())
     }
 }
 
 impl Decl {
-    fn build(&self, program: &mut Program, params: &mut BuildParams) {
+    fn build(&self, program: &mut Program, params: &mut BuildParams) -> Result<(), BuildError> {
         match self {
-            Decl::Const(decl) => decl.build(program, params),
-            Decl::Var(decl) => decl.build(program, params),
+            Decl::Const(decl) => decl.build(program, params)?,
+            Decl::Var(decl) => decl.build(program, params)?,
+        }
+        Ok(())
+    }
+
+    /// Builds the declaration in the global scope, initializers must be
+    /// constant expressions.
+    fn build_global(&self, program: &mut Program, vm: &mut ValueManager) -> Result<(), BuildError> {
+        match self {
+            Decl::Const(decl) => {
+                for def in decl.defs.iter() {
+                    def.build_global(program, vm)?;
+                }
+            }
+            Decl::Var(decl) => {
+                for def in decl.defs.iter() {
+                    def.build_global(program, vm)?;
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Evaluates the array dimensions, which must be positive constants.
+pub(crate) fn eval_dims(dims: &[ConstExp], consts: &impl Consts) -> Result<Vec<usize>, BuildError> {
+    dims.iter()
+        .map(|dim| match dim.calc(consts)? {
+            len if len > 0 => Ok(len as usize),
+            _ => Err(ErrorKind::InvalidArraySize.at(dim.span())),
+        })
+        .collect()
+}
+
+/// Koopa type of `int` with the dimensions, e.g. `[[i32, 3], 2]` for `[2][3]`.
+fn array_type(dims: &[usize]) -> Type {
+    dims.iter()
+        .rev()
+        .fold(Type::get_i32(), |ty, &len| Type::get_array(ty, len))
+}
+
+/// Builds the initializer of a global array from the flattened values.
+fn global_init(program: &mut Program, dims: &[usize], values: &[i32]) -> Value {
+    if !dims.is_empty() && values.iter().all(|v| *v == 0) {
+        return program.new_value().zero_init(array_type(dims));
+    }
+    match dims.split_first() {
+        None => program.new_value().integer(values[0]),
+        Some((len, sub_dims)) => {
+            let elems = values
+                .chunks(values.len() / len)
+                .map(|chunk| global_init(program, sub_dims, chunk))
+                .collect();
+            program.new_value().aggregate(elems)
+        }
+    }
+}
+
+/// Evaluates the initializer of a constant array, flattened.
+fn const_values(init: &InitVal, dims: &[usize], vm: &ValueManager) -> Result<Vec<i32>, BuildError> {
+    init.flatten(dims)?
+        .into_iter()
+        .map(|exp| exp.map_or(Ok(0), |exp| exp.calc(vm)))
+        .collect()
+}
+
+impl InitVal {
+    /// The expression of a scalar initializer.
+    pub(crate) fn exp(&self) -> Result<&Exp, BuildError> {
+        match self {
+            InitVal::Exp(exp) => Ok(exp),
+            InitVal::List(_, span) => Err(ErrorKind::InvalidInitializer.at(*span)),
+        }
+    }
+
+    /// Flattens the initializer of an array with `dims`, missing elements
+    /// are `None` and must be zero.
+    pub(crate) fn flatten(&self, dims: &[usize]) -> Result<Vec<Option<&Exp>>, BuildError> {
+        match self {
+            InitVal::Exp(exp) => Err(ErrorKind::InvalidInitializer.at(exp.span())),
+            InitVal::List(list, _) => {
+                let mut elems = Vec::new();
+                flatten_list(list, dims, &mut elems)?;
+                Ok(elems)
+            }
         }
     }
 }
 
+/// Appends the elements of the initializer list for an array with `dims`.
+///
+/// As in SysY, a nested list initializes the largest sub-array (but not the
+/// whole array) which is aligned at the current position, and the rest of
+/// the array is filled with zeros.
+fn flatten_list<'a>(
+    list: &'a [InitVal],
+    dims: &[usize],
+    elems: &mut Vec<Option<&'a Exp>>,
+) -> Result<(), BuildError> {
+    let start = elems.len();
+    let total: usize = dims.iter().product();
+    for init in list.iter() {
+        let pos = elems.len() - start;
+        if pos >= total {
+            return Err(ErrorKind::InvalidInitializer.at(init.span()));
+        }
+        match init {
+            InitVal::Exp(exp) => elems.push(Some(exp)),
+            InitVal::List(sub_list, span) => {
+                let k = (1..dims.len())
+                    .find(|&k| pos.is_multiple_of(dims[k..].iter().product::<usize>()))
+                    .ok_or_else(|| ErrorKind::InvalidInitializer.at(*span))?;
+                flatten_list(sub_list, &dims[k..], elems)?;
+            }
+        }
+    }
+    elems.resize(start + total, None);
+    Ok(())
+}
+
 impl ConstDecl {
-    fn build(&self, program: &mut Program, params: &mut BuildParams) {
+    fn build(&self, program: &mut Program, params: &mut BuildParams) -> Result<(), BuildError> {
         for def in self.defs.iter() {
-            def.build(program, params);
+            def.build(program, params)?;
         }
+        Ok(())
     }
 }
 
 impl VarDecl {
-    fn build(&self, program: &mut Program, params: &mut BuildParams) {
+    fn build(&self, program: &mut Program, params: &mut BuildParams) -> Result<(), BuildError> {
         for def in self.defs.iter() {
-            def.build(program, params);
+            def.build(program, params)?;
         }
+        Ok(())
     }
 }
 
 impl ConstDef {
-    fn build(&self, program: &mut Program, params: &mut BuildParams) {
-        let v = self.value.calc(params);
-        params.vm.insert_const(self.ident.as_str(), v);
+    fn build(&self, program: &mut Program, params: &mut BuildParams) -> Result<(), BuildError> {
+        let dims = eval_dims(&self.dims, params.vm)?;
+        if dims.is_empty() {
+            let v = self.value.exp()?.calc(params.vm)?;
+            params.vm.insert_const(self.ident.as_str(), v).map_err(|e| e.at(self.span))?;
+            return Ok(());
+        }
+
+        // constant arrays can be indexed by variables, so they need memory
+        let values = const_values(&self.value, &dims, params.vm)?;
+        let alloc = params.new_alloc(program, &self.ident, array_type(&dims));
+        for (i, v) in values.iter().enumerate() {
+            let ptr = params.elem_ptr(program, alloc, &dims, i);
+            let func_data = program.func_mut(params.func);
+            let v = func_data.dfg_mut().new_value().integer(*v);
+            let s = func_data.dfg_mut().new_value().store(v, ptr);
+            params.push_insts(program, &[s]);
+        }
+        params
+            .vm
+            .insert_const_array(self.ident.as_str(), alloc, dims, values)
+            .map_err(|e| e.at(self.span))?;
+        Ok(())
+    }
+
+    fn build_global(&self, program: &mut Program, vm: &mut ValueManager) -> Result<(), BuildError> {
+        let dims = eval_dims(&self.dims, vm)?;
+        if dims.is_empty() {
+            let v = self.value.exp()?.calc(vm)?;
+            return vm.insert_const(self.ident.as_str(), v).map_err(|e| e.at(self.span));
+        }
+
+        let values = const_values(&self.value, &dims, vm)?;
+        let init = global_init(program, &dims, &values);
+        let alloc = program.new_value().global_alloc(init);
+        program.set_value_name(alloc, Some(format!("@{}", self.ident)));
+        vm.insert_const_array(self.ident.as_str(), alloc, dims, values)
+            .map_err(|e| e.at(self.span))
     }
 }
 
 impl VarDef {
-    fn build(&self, program: &mut Program, params: &mut BuildParams) {
-        match self {
-            VarDef::Ident(ident) => {
-                let func_data = program.func_mut(params.func);
-                let v = func_data.dfg_mut().new_value().alloc(Type::get_i32());
-                func_data.layout_mut().bb_mut(params.bb).insts_mut().extend([v]);
-                params.vm.insert_var(ident.as_str(), v);
+    fn build(&self, program: &mut Program, params: &mut BuildParams) -> Result<(), BuildError> {
+        let dims = eval_dims(&self.dims, params.vm)?;
+        let alloc = params.new_alloc(program, &self.ident, array_type(&dims));
+        match &self.init {
+            None => {}
+            Some(init) if dims.is_empty() => {
+                let exp = init.exp()?;
+                exp.build(program, params)?;
+                let v = params.take_value(program, exp.span())?;
+                let s = program.func_mut(params.func).dfg_mut().new_value().store(v, alloc);
+                params.push_insts(program, &[s]);
             }
-            VarDef::InitVal(ident,exp ) => {
-                exp.build(program, params);
-
-                let func_data = program.func_mut(params.func);
-                let v = func_data.dfg_mut().new_value().alloc(Type::get_i32());
-                params.vm.insert_var(ident.as_str(), v);
-                let exp_v = params.v.take().unwrap();
-                let s = func_data.dfg_mut().new_value().store(exp_v, v);
-                func_data.layout_mut().bb_mut(params.bb).insts_mut().extend([v, s]);
+            Some(init) => {
+                // local memory is not zeroed, so every element is stored
+                for (i, exp) in init.flatten(&dims)?.into_iter().enumerate() {
+                    let v = match exp {
+                        Some(exp) => {
+                            exp.build(program, params)?;
+                            params.take_value(program, exp.span())?
+                        }
+                        None => program.func_mut(params.func).dfg_mut().new_value().integer(0),
+                    };
+                    let ptr = params.elem_ptr(program, alloc, &dims, i);
+                    let s = program.func_mut(params.func).dfg_mut().new_value().store(v, ptr);
+                    params.push_insts(program, &[s]);
+                }
             }
         }
+        params.vm.insert_var(self.ident.as_str(), alloc).map_err(|e| e.at(self.span))?;
+        Ok(())
+    }
+
+    fn build_global(&self, program: &mut Program, vm: &mut ValueManager) -> Result<(), BuildError> {
+        let dims = eval_dims(&self.dims, vm)?;
+        let init = match &self.init {
+            None => program.new_value().zero_init(array_type(&dims)),
+            Some(init) if dims.is_empty() => program.new_value().integer(init.exp()?.calc(vm)?),
+            Some(init) => {
+                let values = const_values(init, &dims, vm)?;
+                global_init(program, &dims, &values)
+            }
+        };
+        let alloc = program.new_value().global_alloc(init);
+        program.set_value_name(alloc, Some(format!("@{}", self.ident)));
+        vm.insert_var(self.ident.as_str(), alloc).map_err(|e| e.at(self.span))
     }
 }
-impl Exp {
-    /// build exp
-    fn build(&self, program: &mut Program, params: &mut BuildParams) {
-        match self {
-            Exp::Exp(exp) => exp.build(program, params),
+
+impl LVal {
+    /// Looks up the declaration of the variable or constant.
+    fn decl<'a>(&self, vm: &'a ValueManager) -> Result<&'a vm::Decl, BuildError> {
+        vm.get(&self.ident)
+            .ok_or_else(|| ErrorKind::UndeclaredVariable(self.ident.clone()).at(self.span))
+    }
+
+    /// Builds the pointer to the element (or sub-array) designated by the
+    /// indices, each index steps into one dimension with `getelemptr`, or
+    /// with `getptr` for the first index of an array parameter.
+    fn build_ptr(&self, program: &mut Program, params: &mut BuildParams) -> Result<Value, BuildError> {
+        let mut ptr = match self.decl(params.vm)? {
+            vm::Decl::Var(v) | vm::Decl::ConstArray(v, ..) => *v,
+            vm::Decl::Const(_) => return Err(ErrorKind::IndexMismatch(self.ident.clone()).at(self.span)),
+        };
+        for index in self.indices.iter() {
+            index.build(program, params)?;
+            let index = params.take_value(program, index.span())?;
+            ptr = match pointee(&params.value_ty(program, ptr)).kind() {
+                TypeKind::Array(..) => {
+                    let elem = program
+                        .func_mut(params.func)
+                        .dfg_mut()
+                        .new_value()
+                        .get_elem_ptr(ptr, index);
+                    params.push_insts(program, &[elem]);
+                    elem
+                }
+                // array parameter, the pointer is stored in its alloc
+                TypeKind::Pointer(_) => {
+                    let dfg = program.func_mut(params.func).dfg_mut();
+                    let base = dfg.new_value().load(ptr);
+                    let elem = dfg.new_value().get_ptr(base, index);
+                    params.push_insts(program, &[base, elem]);
+                    elem
+                }
+                _ => return Err(ErrorKind::IndexMismatch(self.ident.clone()).at(self.span)),
+            };
         }
+        Ok(ptr)
     }
+}
 
-    fn calc(&self, params: &mut BuildParams) -> i32 {
+/// Base type of a pointer type.
+fn pointee(ty: &Type) -> Type {
+    match ty.kind() {
+        TypeKind::Pointer(base) => base.clone(),
+        _ => unreachable!("not a pointer"),
+    }
+}
+
+impl Exp {
+    /// build exp
+    fn build(&self, program: &mut Program, params: &mut BuildParams) -> Result<(), BuildError> {
         match self {
-            Exp::Exp(exp) => exp.calc(params),
+            Exp::Exp(exp) => exp.build(program, params)?,
         }
+        Ok(())
     }
 }
 
@@ -206,15 +810,15 @@ impl Exp {
 
 
 impl AddExp {
-    fn build(&self, program: &mut Program, params: &mut BuildParams) {
+    fn build(&self, program: &mut Program, params: &mut BuildParams) -> Result<(), BuildError> {
         match self {
-            AddExp::MulExp(exp) => exp.build(program, params),
-            AddExp::AddExp(add_exp, op, mul_exp) => {
-                add_exp.build(program, params);
-                let add_v = params.v.take().unwrap();
+            AddExp::MulExp(exp) => exp.build(program, params)?,
+            AddExp::AddExp(add_exp, op, mul_exp, _) => {
+                add_exp.build(program, params)?;
+                let add_v = params.take_value(program, add_exp.span())?;
 
-                mul_exp.build(program, params);
-                let mul_v = params.v.take().unwrap();
+                mul_exp.build(program, params)?;
+                let mul_v = params.take_value(program, mul_exp.span())?;
 
                 let op = match op {
                     AddOp::Add => BinaryOp::Add,
@@ -224,29 +828,20 @@ impl AddExp {
                 insert_op!(program, params, op, add_v, mul_v);
             }
         }
-    }
-
-    fn calc(&self, params: &mut BuildParams) -> i32 {
-        match self {
-            AddExp::MulExp(exp) => exp.calc(params),
-            AddExp::AddExp(add_exp, op, mul_exp) => match op {
-                AddOp::Add => add_exp.calc(params) + mul_exp.calc(params),
-                AddOp::Sub => add_exp.calc(params) - mul_exp.calc(params),
-            }
-        }
+        Ok(())
     }
 }
 
 impl MulExp {
-    fn build(&self, program: &mut Program, params: &mut BuildParams) {
+    fn build(&self, program: &mut Program, params: &mut BuildParams) -> Result<(), BuildError> {
         match self {
-            MulExp::UnaryExp(exp) => exp.build(program, params),
-            MulExp::MulExp(mul_exp, op, unary_exp) => {
-                mul_exp.build(program, params);
-                let mul_v = params.v.take().unwrap();
+            MulExp::UnaryExp(exp) => exp.build(program, params)?,
+            MulExp::MulExp(mul_exp, op, unary_exp, _) => {
+                mul_exp.build(program, params)?;
+                let mul_v = params.take_value(program, mul_exp.span())?;
 
-                unary_exp.build(program, params);
-                let unary_v = params.v.take().unwrap();
+                unary_exp.build(program, params)?;
+                let unary_v = params.take_value(program, unary_exp.span())?;
 
                 let op = match op {
                     MulOp::Mul => BinaryOp::Mul,
@@ -256,29 +851,46 @@ impl MulExp {
                 insert_op!(program, params, op, mul_v, unary_v);
             }
         }
-    }
-
-    fn calc(&self, params: &mut BuildParams) -> i32 {
-        match self {
-            MulExp::UnaryExp(exp) => exp.calc(params),
-            MulExp::MulExp(mul_exp, op, unary_exp) => match op {
-                MulOp::Mul => mul_exp.calc(params) * unary_exp.calc(params),
-                MulOp::Div => mul_exp.calc(params) / unary_exp.calc(params),
-                MulOp::Mod => mul_exp.calc(params) % unary_exp.calc(params),
-            }
-        }
+        Ok(())
     }
 }
 
 
 impl UnaryExp {
-    fn build(&self, program: &mut Program, params: &mut BuildParams) {
+    fn build(&self, program: &mut Program, params: &mut BuildParams) -> Result<(), BuildError> {
         match self {
-            UnaryExp::PrimaryExp(exp) => exp.build(program, params),
-            UnaryExp::UnaryOp(op, exp) => {
+            UnaryExp::PrimaryExp(exp) => exp.build(program, params)?,
+            UnaryExp::Call(ident, args, line, span) => {
+                let timer = match ident.as_str() {
+                    "starttime" if args.is_empty() => Some("_sysy_starttime"),
+                    "stoptime" if args.is_empty() => Some("_sysy_stoptime"),
+                    _ => None,
+                };
+                let callee = params
+                    .vm
+                    .get_func(timer.unwrap_or(ident))
+                    .ok_or_else(|| ErrorKind::UndeclaredFunction(ident.clone()).at(*span))?;
+                let mut arg_vs = Vec::new();
+                if timer.is_some() {
+                    let line = program.func_mut(params.func).dfg_mut().new_value().integer(*line as i32);
+                    arg_vs.push(line);
+                }
+                for arg in args.iter() {
+                    arg.build(program, params)?;
+                    arg_vs.push(params.take_arg(program, arg.span())?);
+                }
+                let call = program
+                    .func_mut(params.func)
+                    .dfg_mut()
+                    .new_value()
+                    .call(callee, arg_vs);
+                params.push_insts(program, &[call]);
+                params.v = Some(call);
+            }
+            UnaryExp::UnaryOp(op, exp, _) => {
                 // build next exp recursively
-                exp.build(program, params);
-                let unary_v = params.v.take().unwrap();
+                exp.build(program, params)?;
+                let unary_v = params.take_value(program, exp.span())?;
                 // op instruction
                 let op = match op {
                     UnaryOp::Plus => BinaryOp::Add,
@@ -291,133 +903,164 @@ impl UnaryExp {
                 insert_op!(program, params, op, zero, unary_v);
             }
         }
-    }
-
-    fn calc(&self, params: &mut BuildParams) -> i32 {
-        match self {
-            UnaryExp::PrimaryExp(exp) => exp.calc(params),
-            UnaryExp::UnaryOp(op, exp) => match op {
-                UnaryOp::Plus => exp.calc(params),
-                UnaryOp::Minus => -exp.calc(params),
-                UnaryOp::Not => !exp.calc(params),
-            }
-        }
+        Ok(())
     }
 }
 
 impl PrimaryExp {
-    fn build(&self, program: &mut Program, params: &mut BuildParams) {
+    fn build(&self, program: &mut Program, params: &mut BuildParams) -> Result<(), BuildError> {
         match self {
-            PrimaryExp::Exp(exp) => exp.build(program, params),
-            PrimaryExp::Number(num) => {
+            PrimaryExp::Exp(exp, _) => exp.build(program, params)?,
+            PrimaryExp::Number(num, _) => {
                 let func_data = program.func_mut(params.func);
                 let value = func_data.dfg_mut().new_value().integer(*num);
                 params.v = Some(value);
                 // just a number, don't need to create a instruction
                 // func_data.layout_mut().bb_mut(params.bb).insts_mut().extend([value]);
             }
-            PrimaryExp::LVal(lval) => {
-                let func_data = program.func_mut(params.func);
-                let v = params.vm.get(&lval).unwrap();
-                match *v {
-                    vm::Decl::Const(v) => {
-                        let value = func_data.dfg_mut().new_value().integer(v);
-                        params.v = Some(value);
-                    }
-                    vm::Decl::Var(v) => {
-                        let l = func_data.dfg_mut().new_value().load(v);
-                        func_data.layout_mut().bb_mut(params.bb).insts_mut().extend([l]);
-                        params.v = Some(l);
-                    }
+            PrimaryExp::LVal(lval) => match lval.decl(params.vm)? {
+                vm::Decl::Const(v) if lval.indices.is_empty() => {
+                    let value = program.func_mut(params.func).dfg_mut().new_value().integer(*v);
+                    params.v = Some(value);
                 }
-               
-            }
+                _ => {
+                    let ptr = lval.build_ptr(program, params)?;
+                    let ty = pointee(&params.value_ty(program, ptr));
+                    let dfg = program.func_mut(params.func).dfg_mut();
+                    let v = match ty.kind() {
+                        // partially indexed array decays to a pointer to its
+                        // first element, which can only be passed to a call
+                        TypeKind::Array(..) => {
+                            let zero = dfg.new_value().integer(0);
+                            dfg.new_value().get_elem_ptr(ptr, zero)
+                        }
+                        _ => dfg.new_value().load(ptr),
+                    };
+                    params.push_insts(program, &[v]);
+                    params.v = Some(v);
+                }
+            },
         }
+        Ok(())
     }
+}
 
-    fn calc(&self, params: &mut BuildParams) -> i32 {
-        match self {
-            PrimaryExp::Exp(exp) => exp.calc(params),
-            PrimaryExp::Number(num) => *num,
-            PrimaryExp::LVal(lval) => params.vm.get_const(lval).unwrap(),
+/// Builds `lhs && rhs` (`is_and`) or `lhs || rhs` with short-circuit
+/// evaluation: `rhs` is only built into a separate basic block, which is
+/// skipped when `lhs` already decides the result.
+fn build_logic<F>(
+    program: &mut Program,
+    params: &mut BuildParams,
+    is_and: bool,
+    lhs: Value,
+    rhs: F,
+) -> Result<(), BuildError>
+where
+    F: FnOnce(&mut Program, &mut BuildParams) -> Result<Value, BuildError>,
+{
+    // constant lhs: either the result is known, or it is just `rhs != 0`,
+    // no branch is needed in both cases
+    if let ValueKind::Integer(l) = program.func(params.func).dfg().value(lhs).kind() {
+        let l = l.value() != 0;
+        if l != is_and {
+            let v = program.func_mut(params.func).dfg_mut().new_value().integer(l as i32);
+            params.v = Some(v);
+            return Ok(());
+        }
+        let r = rhs(program, params)?;
+        let func_data = program.func_mut(params.func);
+        if let ValueKind::Integer(r) = func_data.dfg().value(r).kind() {
+            let v = (r.value() != 0) as i32;
+            params.v = Some(func_data.dfg_mut().new_value().integer(v));
+        } else {
+            let zero = func_data.dfg_mut().new_value().integer(0);
+            insert_op!(program, params, BinaryOp::NotEq, r, zero);
         }
+        return Ok(());
     }
+
+    let (rhs_bb, end_bb) = if is_and {
+        (params.new_bb(program, "%land_rhs"), params.new_bb(program, "%land_end"))
+    } else {
+        (params.new_bb(program, "%lor_rhs"), params.new_bb(program, "%lor_end"))
+    };
+
+    // result = is_and ? 0 : 1, then only overwritten in rhs_bb, its slot is
+    // allocated in the entry block like the variables
+    let result = program.func_mut(params.func).dfg_mut().new_value().alloc(Type::get_i32());
+    params.push_alloc(program, result);
+    let func_data = program.func_mut(params.func);
+    let init = func_data.dfg_mut().new_value().integer(!is_and as i32);
+    let store = func_data.dfg_mut().new_value().store(init, result);
+    let br = if is_and {
+        func_data.dfg_mut().new_value().branch(lhs, rhs_bb, end_bb)
+    } else {
+        func_data.dfg_mut().new_value().branch(lhs, end_bb, rhs_bb)
+    };
+    params.push_insts(program, &[store, br]);
+
+    params.enter_bb(program, rhs_bb);
+    let r = rhs(program, params)?;
+    let func_data = program.func_mut(params.func);
+    let zero = func_data.dfg_mut().new_value().integer(0);
+    let r = func_data.dfg_mut().new_value().binary(BinaryOp::NotEq, r, zero);
+    let store = func_data.dfg_mut().new_value().store(r, result);
+    params.push_insts(program, &[r, store]);
+    params.jump_to(program, end_bb);
+
+    params.enter_bb(program, end_bb);
+    let load = program.func_mut(params.func).dfg_mut().new_value().load(result);
+    params.push_insts(program, &[load]);
+    params.v = Some(load);
+    Ok(())
 }
 
 impl LOrExp {
-    fn build(&self, program: &mut Program, params: &mut BuildParams) {
+    fn build(&self, program: &mut Program, params: &mut BuildParams) -> Result<(), BuildError> {
         match self {
-            LOrExp::LAndExp(exp) => exp.build(program, params),
-            LOrExp::LOrExp(lor_exp, land_exp) => {
-                lor_exp.build(program, params);
-                let lor_v = params.v.take().unwrap();
-                
-                land_exp.build(program, params);
-                let land_v = params.v.take().unwrap();
-                
-
-                let func_data = program.func_mut(params.func);
-                let zero = func_data.dfg_mut().new_value().integer(0);
-                let or_v = func_data.dfg_mut().new_value().binary(BinaryOp::Or, lor_v, land_v);
-                let res = func_data.dfg_mut().new_value().binary(BinaryOp::NotEq, or_v, zero);
+            LOrExp::LAndExp(exp) => exp.build(program, params)?,
+            LOrExp::LOrExp(lor_exp, land_exp, _) => {
+                lor_exp.build(program, params)?;
+                let lor_v = params.take_value(program, lor_exp.span())?;
 
-                params.v = Some(res);
-                func_data.layout_mut().bb_mut(params.bb).insts_mut().extend([or_v, res]);
+                build_logic(program, params, false, lor_v, |program, params| {
+                    land_exp.build(program, params)?;
+                    params.take_value(program, land_exp.span())
+                })?;
             }
         }
-    }
-
-    fn calc(&self, params: &mut BuildParams) -> i32 {
-        match self {
-            LOrExp::LAndExp(exp) => exp.calc(params),
-            LOrExp::LOrExp(lor_exp, land_exp) => 
-                (lor_exp.calc(params) != 0 || land_exp.calc(params) != 0).into()
-        }
+        Ok(())
     }
 }
 
 impl LAndExp {
-    fn build(&self, program: &mut Program, params: &mut BuildParams) {
+    fn build(&self, program: &mut Program, params: &mut BuildParams) -> Result<(), BuildError> {
         match self {
-            LAndExp::EqExp(exp) => exp.build(program, params),
-            LAndExp::LAndExp(land_exp, eq_exp) => {
-                land_exp.build(program, params);
-                let land_v = params.v.take().unwrap();
-                
-                eq_exp.build(program, params);
-                let eq_v = params.v.take().unwrap();
+            LAndExp::EqExp(exp) => exp.build(program, params)?,
+            LAndExp::LAndExp(land_exp, eq_exp, _) => {
+                land_exp.build(program, params)?;
+                let land_v = params.take_value(program, land_exp.span())?;
 
-                let func_data = program.func_mut(params.func);
-                let zero = func_data.dfg_mut().new_value().integer(0);
-
-                let l_v = func_data.dfg_mut().new_value().binary(BinaryOp::NotEq, land_v, zero);
-                let r_v = func_data.dfg_mut().new_value().binary(BinaryOp::NotEq, eq_v, zero);
-                let res = func_data.dfg_mut().new_value().binary(BinaryOp::And, l_v, r_v);
-                params.v = Some(res);
-                func_data.layout_mut().bb_mut(params.bb).insts_mut().extend([l_v, r_v, res]);
+                build_logic(program, params, true, land_v, |program, params| {
+                    eq_exp.build(program, params)?;
+                    params.take_value(program, eq_exp.span())
+                })?;
             }
         }
-    }
-
-    fn calc(&self, params: &mut BuildParams) -> i32 {
-        match self {
-            LAndExp::EqExp(exp) => exp.calc(params),
-            LAndExp::LAndExp(land_exp, eq_exp) => 
-               (land_exp.calc(params) != 0 && eq_exp.calc(params) != 0).into()
-        }
+        Ok(())
     }
 }
 
 impl EqExp {
-    fn build(&self, program: &mut Program, params: &mut BuildParams) {
+    fn build(&self, program: &mut Program, params: &mut BuildParams) -> Result<(), BuildError> {
         match self {
-            EqExp::RelExp(exp) => exp.build(program, params),
-            EqExp::EqExp(eq_exp, eq_op, rel_exp) => {
-                eq_exp.build(program, params);
-                let eq_v = params.v.take().unwrap();
+            EqExp::RelExp(exp) => exp.build(program, params)?,
+            EqExp::EqExp(eq_exp, eq_op, rel_exp, _) => {
+                eq_exp.build(program, params)?;
+                let eq_v = params.take_value(program, eq_exp.span())?;
                 
-                rel_exp.build(program, params);
-                let rel_v = params.v.take().unwrap();
+                rel_exp.build(program, params)?;
+                let rel_v = params.take_value(program, rel_exp.span())?;
                 
                 let op = match eq_op {
                     EqOp::Eq => BinaryOp::Eq,
@@ -426,30 +1069,20 @@ impl EqExp {
                 insert_op!(program, params, op, eq_v, rel_v);
             }
         }
-    }
-
-    fn calc(&self, params: &mut BuildParams) -> i32 {
-        match self {
-            EqExp::RelExp(exp) => exp.calc(params),
-            EqExp::EqExp(eq_exp, eq_op, rel_exp) => match eq_op {
-                EqOp::Eq => eq_exp.calc(params) == rel_exp.calc(params) ,
-                EqOp::Ne => eq_exp.calc(params) != rel_exp.calc(params),
-            }.into()
-            
-        }
+        Ok(())
     }
 }
 
 impl RelExp {
-    fn build(&self, program: &mut Program, params: &mut BuildParams) {
+    fn build(&self, program: &mut Program, params: &mut BuildParams) -> Result<(), BuildError> {
         match self {
-            RelExp::AddExp(exp) => exp.build(program, params),
-            RelExp::RelExp(rel_exp, rel_op, add_exp) => {
-                rel_exp.build(program, params);
-                let rel_v = params.v.take().unwrap();
+            RelExp::AddExp(exp) => exp.build(program, params)?,
+            RelExp::RelExp(rel_exp, rel_op, add_exp, _) => {
+                rel_exp.build(program, params)?;
+                let rel_v = params.take_value(program, rel_exp.span())?;
                 
-                add_exp.build(program, params);
-                let add_v = params.v.take().unwrap();
+                add_exp.build(program, params)?;
+                let add_v = params.take_value(program, add_exp.span())?;
 
                 let op = match rel_op {
                     RelOp::Lt => BinaryOp::Lt,
@@ -460,17 +1093,6 @@ impl RelExp {
                 insert_op!(program, params, op, rel_v, add_v);
             }
         }
-    }
-
-    fn calc(&self, params: &mut BuildParams) -> i32 {
-        match self {
-            RelExp::AddExp(exp) => exp.calc(params),
-            RelExp::RelExp(rel_exp, rel_op, add_exp) => match rel_op {
-                RelOp::Lt => rel_exp.calc(params) < add_exp.calc(params),
-                RelOp::Le => rel_exp.calc(params) <= add_exp.calc(params),
-                RelOp::Gt => rel_exp.calc(params) > add_exp.calc(params),
-                RelOp::Ge => rel_exp.calc(params) >= add_exp.calc(params),
-            }.into()
-        }
+        Ok(())
     }
 }
\ No newline at end of file
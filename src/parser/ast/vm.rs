@@ -1,42 +1,145 @@
-use std::collections::HashMap;
+use std::collections::{HashMap, HashSet};
 
-use koopa::ir::Value;
+use koopa::ir::{Function, Value};
+
+use crate::parser::ast::error::ErrorKind;
+use crate::parser::ast::eval::{ConstValue, Consts};
 
 pub enum Decl {
     Const(i32),
-    Var(Value)
+    Var(Value),
+    /// memory, dimensions and flattened values of a constant array
+    ConstArray(Value, Vec<usize>, Vec<i32>),
 }
+
+/// Scoped symbol table, the innermost scope is the last one.
 pub struct ValueManager {
-    vm: HashMap<String, Decl>,
+    scopes: Vec<HashMap<String, Decl>>,
+    /// Koopa names already used in the current function
+    names: HashSet<String>,
+    /// Koopa names of global variables, reserved in every function
+    globals: HashSet<String>,
+    funcs: HashMap<String, Function>,
+}
+
+impl Default for ValueManager {
+    fn default() -> Self {
+        Self::new()
+    }
 }
 
 impl ValueManager {
     pub fn new() -> Self {
         ValueManager {
-            vm: HashMap::new(),
+            scopes: vec![HashMap::new()],
+            names: HashSet::new(),
+            globals: HashSet::new(),
+            funcs: HashMap::new(),
         }
     }
 
+    /// Enters a new block scope.
+    pub fn push_scope(&mut self) {
+        self.scopes.push(HashMap::new());
+    }
+
+    /// Leaves the current block scope, its names are no longer visible.
+    pub fn pop_scope(&mut self) {
+        self.scopes.pop();
+        assert!(!self.scopes.is_empty(), "global scope popped");
+    }
+
     pub fn exist(&self, name: &str) -> bool {
-        self.vm.contains_key(name)
+        self.get(name).is_some()
     }
 
-    pub fn insert_const(&mut self, name: &str, value: i32) {
-        self.vm.insert(name.to_string(), Decl::Const(value));
+    pub fn insert_const(&mut self, name: &str, value: i32) -> Result<(), ErrorKind> {
+        self.insert(name, Decl::Const(value))
+    }
+
+    pub fn insert_const_array(
+        &mut self,
+        name: &str,
+        value: Value,
+        dims: Vec<usize>,
+        values: Vec<i32>,
+    ) -> Result<(), ErrorKind> {
+        self.insert(name, Decl::ConstArray(value, dims, values))
     }
 
     pub fn get_const(&self, name: &str) -> Option<i32> {
-        match self.vm.get(name) {
+        match self.get(name) {
             Some(Decl::Const(value)) => Some(*value),
             _ => None,
         }
     }
 
-    pub fn insert_var(&mut self, name: &str, value: Value) {
-        self.vm.insert(name.to_string(), Decl::Var(value));
+    pub fn insert_var(&mut self, name: &str, value: Value) -> Result<(), ErrorKind> {
+        self.insert(name, Decl::Var(value))
     }
 
+    /// Looks up the name from the innermost scope outwards.
     pub fn get(&self, name: &str) -> Option<&Decl> {
-        self.vm.get(name)
+        self.scopes.iter().rev().find_map(|scope| scope.get(name))
+    }
+
+    pub fn insert_func(&mut self, name: &str, func: Function) -> Result<(), ErrorKind> {
+        if self.scopes[0].contains_key(name) || self.funcs.insert(name.to_string(), func).is_some() {
+            return Err(ErrorKind::Redeclared(name.to_string()));
+        }
+        Ok(())
+    }
+
+    pub fn get_func(&self, name: &str) -> Option<Function> {
+        self.funcs.get(name).copied()
     }
-}
\ No newline at end of file
+
+    /// Forgets the Koopa names of the previous function.
+    pub fn reset_names(&mut self) {
+        self.names = self.globals.clone();
+    }
+
+    /// Marks the Koopa name as used by a global variable.
+    pub fn reserve_global(&mut self, koopa_name: &str) {
+        self.globals.insert(koopa_name.to_string());
+    }
+
+    /// Marks the Koopa name as used in the current function.
+    pub fn reserve_name(&mut self, koopa_name: &str) {
+        self.names.insert(koopa_name.to_string());
+    }
+
+    /// Returns a Koopa name for the variable that is unique in the current
+    /// function, shadowed variables get a numeric suffix.
+    pub fn unique_name(&mut self, name: &str) -> String {
+        let mut koopa_name = format!("@{}", name);
+        let mut cnt = 0;
+        while self.names.contains(&koopa_name) {
+            cnt += 1;
+            koopa_name = format!("@{}_{}", name, cnt);
+        }
+        self.names.insert(koopa_name.clone());
+        koopa_name
+    }
+
+    fn insert(&mut self, name: &str, decl: Decl) -> Result<(), ErrorKind> {
+        // functions live in the global scope as well
+        let is_global = self.scopes.len() == 1;
+        let scope = self.scopes.last_mut().unwrap();
+        if scope.contains_key(name) || (is_global && self.funcs.contains_key(name)) {
+            return Err(ErrorKind::Redeclared(name.to_string()));
+        }
+        scope.insert(name.to_string(), decl);
+        Ok(())
+    }
+}
+
+impl Consts for ValueManager {
+    fn lookup_const(&self, name: &str) -> Option<ConstValue<'_>> {
+        match self.get(name)? {
+            Decl::Const(v) => Some(ConstValue::Int(*v)),
+            Decl::ConstArray(_, dims, values) => Some(ConstValue::Array(dims, values)),
+            Decl::Var(_) => None,
+        }
+    }
+}
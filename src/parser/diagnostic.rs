@@ -0,0 +1,155 @@
+use lalrpop_util::ParseError;
+use std::fmt::{self, Display};
+
+use crate::parser::ast::error::BuildError;
+
+/// Byte range `[start, end)` in the source file.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Span {
+    pub start: usize,
+    pub end: usize,
+}
+
+impl Span {
+    pub fn new(start: usize, end: usize) -> Span {
+        Span { start, end }
+    }
+}
+
+/// An error message at a location in the source, printed like rustc does:
+///
+/// ```text
+/// error: redeclaration of `a`
+///  --> main.c:3:7
+///   |
+/// 3 |   int a = 1;
+///   |       ^^^^^
+/// ```
+#[derive(Debug)]
+pub struct Diagnostic {
+    pub message: String,
+    /// `None` if the error is not caused by a location in the source
+    pub span: Option<Span>,
+}
+
+impl Diagnostic {
+    pub fn new(message: String, span: Span) -> Diagnostic {
+        Diagnostic { message, span: Some(span) }
+    }
+
+    /// A diagnostic for an error which has no location in the source, like
+    /// a bug found in the generated IR.
+    pub fn without_span(message: String) -> Diagnostic {
+        Diagnostic { message, span: None }
+    }
+
+    /// Renders the diagnostic against the source of the file at `path`.
+    /// A span over several lines is underlined to the end of its first line,
+    /// a span which splits a character is widened to the whole character.
+    pub fn render(&self, path: &str, src: &str) -> String {
+        let span = match self.span {
+            Some(span) => span,
+            None => return format!("error: {}\n", self.message),
+        };
+        let mut start = span.start.min(src.len());
+        while !src.is_char_boundary(start) {
+            start -= 1;
+        }
+        let line_start = src[..start].rfind('\n').map_or(0, |i| i + 1);
+        let line_end = src[start..].find('\n').map_or(src.len(), |i| start + i);
+        let line = src[line_start..line_end].trim_end_matches('\r');
+        let line_no = src[..start].matches('\n').count() + 1;
+        let col = src[line_start..start].chars().count() + 1;
+
+        // keep tabs in the padding, so the carets line up with the source
+        let pad: String = src[line_start..start]
+            .chars()
+            .map(|c| if c == '\t' { '\t' } else { ' ' })
+            .collect();
+        let mut end = span.end.clamp(start, line_start + line.len());
+        while !src.is_char_boundary(end) {
+            end += 1;
+        }
+        let carets = "^".repeat(src[start..end].chars().count().max(1));
+
+        let gutter = " ".repeat(line_no.to_string().len());
+        let mut out = String::new();
+        out += &format!("error: {}\n", self.message);
+        out += &format!("{}--> {}:{}:{}\n", gutter, path, line_no, col);
+        out += &format!("{} |\n", gutter);
+        out += &format!("{} | {}\n", line_no, line);
+        out += &format!("{} | {}{}\n", gutter, pad, carets);
+        out
+    }
+}
+
+/// Only the message, `render` shows it with its location in the source.
+impl Display for Diagnostic {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.message)
+    }
+}
+
+impl std::error::Error for Diagnostic {}
+
+impl From<BuildError> for Diagnostic {
+    fn from(err: BuildError) -> Diagnostic {
+        Diagnostic::new(err.kind.to_string(), err.span)
+    }
+}
+
+/// Syntax errors, user errors of the grammar are diagnostics already.
+impl<T: Display> From<ParseError<usize, T, Diagnostic>> for Diagnostic {
+    fn from(err: ParseError<usize, T, Diagnostic>) -> Diagnostic {
+        match err {
+            ParseError::InvalidToken { location } => {
+                Diagnostic::new("invalid token".to_string(), Span::new(location, location + 1))
+            }
+            ParseError::UnrecognizedEof { location, expected } => Diagnostic::new(
+                format!("unexpected end of file{}", Expected(&expected)),
+                Span::new(location, location),
+            ),
+            ParseError::UnrecognizedToken { token: (l, token, r), expected } => Diagnostic::new(
+                format!("unexpected token `{}`{}", token, Expected(&expected)),
+                Span::new(l, r),
+            ),
+            ParseError::ExtraToken { token: (l, token, r) } => {
+                Diagnostic::new(format!("extra token `{}`", token), Span::new(l, r))
+            }
+            ParseError::User { error } => error,
+        }
+    }
+}
+
+/// The `, expected ...` suffix of a parse error, empty if nothing is known.
+struct Expected<'a>(&'a [String]);
+
+impl Display for Expected<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let mut names: Vec<String> = Vec::new();
+        for name in self.0.iter().map(|terminal| describe(terminal)) {
+            if !names.contains(&name) {
+                names.push(name);
+            }
+        }
+        match names.as_slice() {
+            [] => Ok(()),
+            [one] => write!(f, ", expected {}", one),
+            many => write!(f, ", expected one of {}", many.join(", ")),
+        }
+    }
+}
+
+/// Human readable name of a terminal as lalrpop reports it, `"\";\""` is
+/// the literal `;`, and `r#"..."#` is one of the regexes of the grammar.
+fn describe(terminal: &str) -> String {
+    if let Some(regex) = terminal.strip_prefix("r#") {
+        if regex.contains("a-zA-Z") {
+            "identifier".to_string()
+        } else {
+            "integer literal".to_string()
+        }
+    } else {
+        format!("`{}`", terminal.trim_matches('"'))
+    }
+}
@@ -0,0 +1,51 @@
+//! Integer arithmetic with the semantics of Koopa IR and RV32IM, shared by
+//! the constant evaluation of the frontend and the constant folding of the
+//! backend, so both agree with what the generated code computes at runtime.
+
+use koopa::ir::BinaryOp;
+
+/// Computes `lhs op rhs` the way RV32IM does: arithmetic wraps around,
+/// `x / 0` is `-1`, `x % 0` is `x`, and shifts only use the low 5 bits of
+/// `rhs`. Comparisons give 0 or 1, `And`/`Or`/`Xor` are bitwise.
+pub fn binary(op: BinaryOp, lhs: i32, rhs: i32) -> i32 {
+    match op {
+        BinaryOp::NotEq => (lhs != rhs) as i32,
+        BinaryOp::Eq => (lhs == rhs) as i32,
+        BinaryOp::Gt => (lhs > rhs) as i32,
+        BinaryOp::Lt => (lhs < rhs) as i32,
+        BinaryOp::Ge => (lhs >= rhs) as i32,
+        BinaryOp::Le => (lhs <= rhs) as i32,
+        BinaryOp::Add => lhs.wrapping_add(rhs),
+        BinaryOp::Sub => lhs.wrapping_sub(rhs),
+        BinaryOp::Mul => lhs.wrapping_mul(rhs),
+        BinaryOp::Div if rhs == 0 => -1,
+        BinaryOp::Div => lhs.wrapping_div(rhs),
+        BinaryOp::Mod if rhs == 0 => lhs,
+        BinaryOp::Mod => lhs.wrapping_rem(rhs),
+        BinaryOp::And => lhs & rhs,
+        BinaryOp::Or => lhs | rhs,
+        BinaryOp::Xor => lhs ^ rhs,
+        BinaryOp::Shl => lhs.wrapping_shl(rhs as u32),
+        BinaryOp::Shr => (lhs as u32).wrapping_shr(rhs as u32) as i32,
+        BinaryOp::Sar => lhs.wrapping_shr(rhs as u32),
+    }
+}
+
+/// Like `binary`, but `None` for a division or remainder by zero, which is
+/// undefined in SysY and must not appear in a constant expression.
+pub fn checked_binary(op: BinaryOp, lhs: i32, rhs: i32) -> Option<i32> {
+    match op {
+        BinaryOp::Div | BinaryOp::Mod if rhs == 0 => None,
+        _ => Some(binary(op, lhs, rhs)),
+    }
+}
+
+/// Computes `-value`, `-i32::MIN` wraps around to itself.
+pub fn neg(value: i32) -> i32 {
+    value.wrapping_neg()
+}
+
+/// Computes the logical `!value` of C, which is 1 for 0 and 0 otherwise.
+pub fn not(value: i32) -> i32 {
+    (value == 0) as i32
+}
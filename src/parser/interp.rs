@@ -0,0 +1,432 @@
+//! Interpreter for Koopa IR programs, it runs `@main` in-process with the
+//! SysY runtime library, and serves as the reference for the backend.
+//!
+//! Memory is a list of allocations of words, pointers are an allocation and
+//! a word offset in it, so every access out of an allocation is caught.
+//! Every alloc gets its memory once per call, which is freed when the
+//! function returns.
+
+use crate::parser::fold;
+use crate::parser::runtime::{Outcome, RunError, Runtime};
+use koopa::ir::entities::{FunctionData, ValueData};
+use koopa::ir::{BasicBlock, Function, Program, Type, TypeKind, Value, ValueKind};
+use std::collections::HashMap;
+
+/// Runs `@main` of the program on the input, for at most `step_limit`
+/// instructions.
+pub fn run(program: &Program, input: &[u8], step_limit: u64) -> Result<Outcome, RunError> {
+    let mut interp = Interpreter::new(program, input, step_limit);
+    let exit_code = interp.run()?;
+    Ok(Outcome {
+        exit_code,
+        output: interp.runtime.into_output(),
+        steps: interp.steps,
+    })
+}
+
+/// A value computed by the program.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Val {
+    Int(i32),
+    Ptr(Ptr),
+}
+
+/// Address of a word in memory.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct Ptr {
+    /// index of the allocation
+    alloc: usize,
+    /// offset in words, may be out of the allocation until it is accessed
+    offset: i64,
+}
+
+/// Size of the type in words, pointers take one word like integers do.
+fn words(ty: &Type) -> usize {
+    match ty.kind() {
+        TypeKind::Int32 | TypeKind::Pointer(_) => 1,
+        TypeKind::Array(base, len) => words(base) * len,
+        TypeKind::Unit | TypeKind::Function(..) => 0,
+    }
+}
+
+/// Type pointed to by a pointer type.
+fn pointee(ty: &Type) -> Option<&Type> {
+    match ty.kind() {
+        TypeKind::Pointer(base) => Some(base),
+        _ => None,
+    }
+}
+
+/// A call of a function which has not returned yet.
+struct Frame {
+    func: Function,
+    bb: BasicBlock,
+    /// index of the next instruction in `bb`
+    pc: usize,
+    values: HashMap<Value, Val>,
+    /// number of allocations when the function was called, the ones after
+    /// it are freed on return
+    allocs: usize,
+    /// call instruction in the caller which receives the return value
+    dest: Option<Value>,
+}
+
+struct Interpreter<'a> {
+    program: &'a Program,
+    runtime: Runtime,
+    step_limit: u64,
+    steps: u64,
+    /// instructions of every basic block, in layout order
+    blocks: HashMap<(Function, BasicBlock), Vec<Value>>,
+    memory: Vec<Vec<Val>>,
+    globals: HashMap<Value, Ptr>,
+    frames: Vec<Frame>,
+}
+
+impl<'a> Interpreter<'a> {
+    fn new(program: &'a Program, input: &[u8], step_limit: u64) -> Self {
+        let mut blocks = HashMap::new();
+        for &func in program.func_layout() {
+            for (&bb, node) in program.func(func).layout().bbs() {
+                blocks.insert((func, bb), node.insts().keys().copied().collect());
+            }
+        }
+        Interpreter {
+            program,
+            runtime: Runtime::new(input),
+            step_limit,
+            steps: 0,
+            blocks,
+            memory: Vec::new(),
+            globals: HashMap::new(),
+            frames: Vec::new(),
+        }
+    }
+
+    fn func_data(&self) -> &'a FunctionData {
+        self.program.func(self.frames.last().unwrap().func)
+    }
+
+    fn func_name(&self) -> String {
+        self.func_data().name().to_string()
+    }
+
+    fn invalid<T>(&self, reason: &str) -> Result<T, RunError> {
+        Err(RunError::Invalid(format!("{} in `{}`", reason, self.func_name())))
+    }
+
+    /// Allocates the globals, then runs `@main` until it returns.
+    fn run(&mut self) -> Result<i32, RunError> {
+        for &value in self.program.inst_layout() {
+            let (ty, cells) = {
+                let data = self.program.borrow_value(value);
+                let init = match data.kind() {
+                    ValueKind::GlobalAlloc(alloc) => alloc.init(),
+                    _ => return Err(RunError::Invalid("global value is not an allocation".into())),
+                };
+                let ty = pointee(data.ty()).unwrap().clone();
+                (ty, self.global_cells(init))
+            };
+            let ptr = self.alloc(&ty);
+            self.memory[ptr.alloc] = cells;
+            self.globals.insert(value, ptr);
+        }
+
+        let main = self
+            .program
+            .func_layout()
+            .iter()
+            .copied()
+            .find(|&func| self.program.func(func).name() == "@main")
+            .ok_or_else(|| RunError::UndefinedFunction("@main".into()))?;
+        self.call(main, Vec::new(), None)?;
+        loop {
+            if let Some(exit_code) = self.step()? {
+                return Ok(exit_code);
+            }
+        }
+    }
+
+    /// Words of a global constant.
+    fn global_cells(&self, value: Value) -> Vec<Val> {
+        let data = self.program.borrow_value(value);
+        match data.kind() {
+            ValueKind::Integer(int) => vec![Val::Int(int.value())],
+            ValueKind::Aggregate(agg) => agg.elems().iter().flat_map(|&elem| self.global_cells(elem)).collect(),
+            _ => vec![Val::Int(0); words(data.ty())],
+        }
+    }
+
+    /// Words of a local constant.
+    fn local_cells(&self, data: &ValueData) -> Vec<Val> {
+        let dfg = self.func_data().dfg();
+        match data.kind() {
+            ValueKind::Aggregate(agg) => agg.elems().iter().flat_map(|&elem| self.local_cells(dfg.value(elem))).collect(),
+            ValueKind::Integer(int) => vec![Val::Int(int.value())],
+            _ => vec![Val::Int(0); words(data.ty())],
+        }
+    }
+
+    /// Allocates zeroed memory for a value of the type.
+    fn alloc(&mut self, ty: &Type) -> Ptr {
+        self.memory.push(vec![Val::Int(0); words(ty)]);
+        Ptr {
+            alloc: self.memory.len() - 1,
+            offset: 0,
+        }
+    }
+
+    /// Index of the word the pointer points to, if it is in its allocation.
+    fn cell(&self, ptr: Ptr) -> Result<(usize, usize), RunError> {
+        let len = self.memory.get(ptr.alloc).map_or(0, |cells| cells.len());
+        if ptr.offset < 0 || ptr.offset as usize >= len {
+            return Err(RunError::OutOfBounds(format!(
+                "in `{}`: word {} of an allocation of {} words",
+                self.func_name(),
+                ptr.offset,
+                len
+            )));
+        }
+        Ok((ptr.alloc, ptr.offset as usize))
+    }
+
+    fn load(&self, ptr: Ptr) -> Result<Val, RunError> {
+        let (alloc, offset) = self.cell(ptr)?;
+        Ok(self.memory[alloc][offset])
+    }
+
+    fn store(&mut self, ptr: Ptr, value: Val) -> Result<(), RunError> {
+        let (alloc, offset) = self.cell(ptr)?;
+        self.memory[alloc][offset] = value;
+        Ok(())
+    }
+
+    /// Value of an operand in the current function.
+    fn value(&self, value: Value) -> Result<Val, RunError> {
+        if value.is_global() {
+            return match self.globals.get(&value) {
+                Some(&ptr) => Ok(Val::Ptr(ptr)),
+                None => self.invalid("use of an undefined global value"),
+            };
+        }
+        if let Some(&val) = self.frames.last().unwrap().values.get(&value) {
+            return Ok(val);
+        }
+        match self.func_data().dfg().values().get(&value).map(|data| data.kind()) {
+            Some(ValueKind::Integer(int)) => Ok(Val::Int(int.value())),
+            Some(ValueKind::ZeroInit(_)) | Some(ValueKind::Undef(_)) => Ok(Val::Int(0)),
+            _ => self.invalid("use of a value before it is defined"),
+        }
+    }
+
+    fn int(&self, value: Value) -> Result<i32, RunError> {
+        match self.value(value)? {
+            Val::Int(int) => Ok(int),
+            Val::Ptr(_) => self.invalid("pointer used as an integer"),
+        }
+    }
+
+    fn ptr(&self, value: Value) -> Result<Ptr, RunError> {
+        match self.value(value)? {
+            Val::Ptr(ptr) => Ok(ptr),
+            Val::Int(_) => self.invalid("integer used as a pointer"),
+        }
+    }
+
+    fn set(&mut self, value: Value, val: Val) {
+        self.frames.last_mut().unwrap().values.insert(value, val);
+    }
+
+    /// Continues in the basic block, with its parameters set to the arguments.
+    fn jump(&mut self, bb: BasicBlock, args: &[Value]) -> Result<(), RunError> {
+        let args = args.iter().map(|&arg| self.value(arg)).collect::<Result<Vec<_>, _>>()?;
+        let params = self.func_data().dfg().bb(bb).params();
+        for (&param, arg) in params.iter().zip(args) {
+            self.set(param, arg);
+        }
+        let frame = self.frames.last_mut().unwrap();
+        frame.bb = bb;
+        frame.pc = 0;
+        Ok(())
+    }
+
+    /// Calls the function, defined ones get a new frame, the runtime
+    /// library is run right away.
+    fn call(&mut self, func: Function, args: Vec<Val>, dest: Option<Value>) -> Result<(), RunError> {
+        let data = self.program.func(func);
+        let entry = match data.layout().entry_bb() {
+            Some(entry) => entry,
+            None => {
+                let ret = self.call_runtime(data.name(), &args)?;
+                if let (Some(dest), Some(ret)) = (dest, ret) {
+                    self.set(dest, Val::Int(ret));
+                }
+                return Ok(());
+            }
+        };
+        let values = data.params().iter().copied().zip(args).collect();
+        self.frames.push(Frame {
+            func,
+            bb: entry,
+            pc: 0,
+            values,
+            allocs: self.memory.len(),
+            dest,
+        });
+        Ok(())
+    }
+
+    /// Runs a function of the SysY runtime library.
+    fn call_runtime(&mut self, name: &str, args: &[Val]) -> Result<Option<i32>, RunError> {
+        let int = |i: usize| match args.get(i) {
+            Some(Val::Int(int)) => Ok(*int),
+            _ => Err(RunError::Invalid(format!("bad arguments for `{}`", name))),
+        };
+        let ptr = |i: usize| match args.get(i) {
+            Some(Val::Ptr(ptr)) => Ok(*ptr),
+            _ => Err(RunError::Invalid(format!("bad arguments for `{}`", name))),
+        };
+        Ok(match name {
+            "@getint" => Some(self.runtime.getint()),
+            "@getch" => Some(self.runtime.getch()),
+            "@getarray" => {
+                let mut array = ptr(0)?;
+                let n = self.runtime.getint();
+                for _ in 0..n {
+                    let value = self.runtime.getint();
+                    self.store(array, Val::Int(value))?;
+                    array.offset += 1;
+                }
+                Some(n)
+            }
+            "@putint" => {
+                self.runtime.putint(int(0)?);
+                None
+            }
+            "@putch" => {
+                self.runtime.putch(int(0)?);
+                None
+            }
+            "@putarray" => {
+                let mut array = ptr(1)?;
+                let mut values = Vec::new();
+                for _ in 0..int(0)? {
+                    match self.load(array)? {
+                        Val::Int(value) => values.push(value),
+                        Val::Ptr(_) => return self.invalid("`putarray` of pointers"),
+                    }
+                    array.offset += 1;
+                }
+                self.runtime.putarray(&values);
+                None
+            }
+            "@_sysy_starttime" | "@_sysy_stoptime" => None,
+            _ => return Err(RunError::UndefinedFunction(name.to_string())),
+        })
+    }
+
+    /// Executes one instruction, returns the exit code once `@main` returns.
+    fn step(&mut self) -> Result<Option<i32>, RunError> {
+        self.steps += 1;
+        if self.steps > self.step_limit {
+            return Err(RunError::StepLimit(self.step_limit));
+        }
+        let frame = self.frames.last().unwrap();
+        let inst = match self.blocks[&(frame.func, frame.bb)].get(frame.pc) {
+            Some(&inst) => inst,
+            None => return self.invalid("basic block without terminator"),
+        };
+        self.frames.last_mut().unwrap().pc += 1;
+        let data = self.func_data().dfg().value(inst);
+
+        match data.kind() {
+            // an alloc which runs again, in a loop, reuses its memory like a
+            // stack slot does
+            ValueKind::Alloc(_) if self.frames.last().unwrap().values.contains_key(&inst) => (),
+            ValueKind::Alloc(_) => {
+                let ptr = self.alloc(pointee(data.ty()).unwrap());
+                self.set(inst, Val::Ptr(ptr));
+            }
+            ValueKind::Load(load) => {
+                let val = self.load(self.ptr(load.src())?)?;
+                self.set(inst, val);
+            }
+            ValueKind::Store(store) => {
+                let mut dest = self.ptr(store.dest())?;
+                let value = store.value();
+                let value_data = match value.is_global() {
+                    true => None,
+                    false => self.func_data().dfg().values().get(&value),
+                };
+                match value_data {
+                    // aggregates are stored word by word
+                    Some(value_data) if value_data.kind().is_const() => {
+                        for cell in self.local_cells(value_data) {
+                            self.store(dest, cell)?;
+                            dest.offset += 1;
+                        }
+                    }
+                    _ => {
+                        let val = self.value(value)?;
+                        self.store(dest, val)?;
+                    }
+                }
+            }
+            ValueKind::GetPtr(gp) => {
+                let mut ptr = self.ptr(gp.src())?;
+                let stride = words(pointee(data.ty()).unwrap());
+                ptr.offset += self.int(gp.index())? as i64 * stride as i64;
+                self.set(inst, Val::Ptr(ptr));
+            }
+            ValueKind::GetElemPtr(gep) => {
+                let mut ptr = self.ptr(gep.src())?;
+                let stride = words(pointee(data.ty()).unwrap());
+                ptr.offset += self.int(gep.index())? as i64 * stride as i64;
+                self.set(inst, Val::Ptr(ptr));
+            }
+            ValueKind::Binary(binary) => {
+                let (lhs, rhs) = (self.int(binary.lhs())?, self.int(binary.rhs())?);
+                let val = match fold::checked_binary(binary.op(), lhs, rhs) {
+                    Some(val) => val,
+                    None => return Err(RunError::DivisionByZero(self.func_name())),
+                };
+                self.set(inst, Val::Int(val));
+            }
+            ValueKind::Branch(branch) => {
+                if self.int(branch.cond())? != 0 {
+                    self.jump(branch.true_bb(), branch.true_args())?;
+                } else {
+                    self.jump(branch.false_bb(), branch.false_args())?;
+                }
+            }
+            ValueKind::Jump(jump) => self.jump(jump.target(), jump.args())?,
+            ValueKind::Call(call) => {
+                let args = call.args().iter().map(|&arg| self.value(arg)).collect::<Result<Vec<_>, _>>()?;
+                let dest = match data.ty().is_unit() {
+                    true => None,
+                    false => Some(inst),
+                };
+                self.call(call.callee(), args, dest)?;
+            }
+            ValueKind::Return(ret) => {
+                let val = match ret.value() {
+                    Some(value) => Some(self.value(value)?),
+                    None => None,
+                };
+                let frame = self.frames.pop().unwrap();
+                self.memory.truncate(frame.allocs);
+                if self.frames.is_empty() {
+                    return match val {
+                        Some(Val::Int(exit_code)) => Ok(Some(exit_code)),
+                        _ => Ok(Some(0)),
+                    };
+                }
+                if let (Some(dest), Some(val)) = (frame.dest, val) {
+                    self.set(dest, val);
+                }
+            }
+            _ => return self.invalid("unsupported instruction"),
+        }
+        Ok(None)
+    }
+}
@@ -1,2 +1,7 @@
 pub mod asm;
 pub mod ast;
+pub mod diagnostic;
+pub mod fold;
+pub mod interp;
+pub mod runtime;
+pub mod verify;
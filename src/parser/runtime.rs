@@ -0,0 +1,137 @@
+//! The SysY runtime library for the execution engines in this crate, with
+//! the program input and output kept in memory.
+
+use std::fmt::{self, Display};
+
+/// Number of steps an engine runs a program for by default, before giving
+/// up on it as not terminating.
+pub const DEFAULT_STEP_LIMIT: u64 = 10_000_000;
+
+/// Result of running a program to the end.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Outcome {
+    /// return value of `main`
+    pub exit_code: i32,
+    /// everything the program wrote to stdout
+    pub output: Vec<u8>,
+    /// number of steps executed, what a step is depends on the engine
+    pub steps: u64,
+}
+
+/// Error which stops a running program.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum RunError {
+    /// the step limit was reached
+    StepLimit(u64),
+    /// calls nested too deep for the engine, with the number of calls
+    StackOverflow(u64),
+    /// division or remainder by zero, in the function
+    DivisionByZero(String),
+    /// memory access out of bounds, with where it happened
+    OutOfBounds(String),
+    /// call of a function which is neither defined nor in the runtime library
+    UndefinedFunction(String),
+    /// the program can not be run, with the reason
+    Invalid(String),
+    /// the engine itself panicked, with the message of the panic
+    Panic(String),
+}
+
+impl Display for RunError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            RunError::StepLimit(limit) => write!(f, "step limit of {} exceeded", limit),
+            RunError::StackOverflow(depth) => write!(f, "stack overflow after {} nested calls", depth),
+            RunError::DivisionByZero(func) => write!(f, "division by zero in `{}`", func),
+            RunError::OutOfBounds(at) => write!(f, "out-of-bounds memory access {}", at),
+            RunError::UndefinedFunction(name) => write!(f, "call of undefined function `{}`", name),
+            RunError::Invalid(reason) => write!(f, "invalid program: {}", reason),
+            RunError::Panic(message) => write!(f, "engine panicked: {}", message),
+        }
+    }
+}
+
+impl std::error::Error for RunError {}
+
+/// Input and output of a running program, the functions of the library
+/// which need memory (`getarray` and `putarray`) are built by the engines
+/// on top of `getint` and `putint`.
+pub struct Runtime {
+    input: Vec<u8>,
+    /// position of the next byte to read in `input`
+    pos: usize,
+    output: Vec<u8>,
+}
+
+impl Runtime {
+    pub fn new(input: &[u8]) -> Runtime {
+        Runtime {
+            input: input.to_vec(),
+            pos: 0,
+            output: Vec::new(),
+        }
+    }
+
+    /// Everything written by the program.
+    pub fn into_output(self) -> Vec<u8> {
+        self.output
+    }
+
+    /// Reads a decimal integer like `scanf("%d")` does, skipping leading
+    /// whitespace. Returns 0 at the end of the input.
+    pub fn getint(&mut self) -> i32 {
+        while self.pos < self.input.len() && self.input[self.pos].is_ascii_whitespace() {
+            self.pos += 1;
+        }
+        let negative = match self.input.get(self.pos) {
+            Some(b'-') => {
+                self.pos += 1;
+                true
+            }
+            Some(b'+') => {
+                self.pos += 1;
+                false
+            }
+            _ => false,
+        };
+        let mut value: i32 = 0;
+        while let Some(c) = self.input.get(self.pos).filter(|c| c.is_ascii_digit()) {
+            value = value.wrapping_mul(10).wrapping_add((c - b'0') as i32);
+            self.pos += 1;
+        }
+        if negative {
+            value.wrapping_neg()
+        } else {
+            value
+        }
+    }
+
+    /// Reads a character, -1 (`EOF`) at the end of the input.
+    pub fn getch(&mut self) -> i32 {
+        match self.input.get(self.pos) {
+            Some(&c) => {
+                self.pos += 1;
+                c as i32
+            }
+            None => -1,
+        }
+    }
+
+    pub fn putint(&mut self, value: i32) {
+        self.output.extend(value.to_string().bytes());
+    }
+
+    /// Writes the low byte of the character, like `putchar` does.
+    pub fn putch(&mut self, c: i32) {
+        self.output.push(c as u8);
+    }
+
+    /// Writes `n: a[0] a[1] ...` and a newline.
+    pub fn putarray(&mut self, values: &[i32]) {
+        self.output.extend(format!("{}:", values.len()).bytes());
+        for value in values.iter() {
+            self.output.extend(format!(" {}", value).bytes());
+        }
+        self.output.push(b'\n');
+    }
+}
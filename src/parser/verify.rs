@@ -0,0 +1,540 @@
+//! Verifier for Koopa IR programs, it catches bugs of the IR generator
+//! before the backend runs into them. It checks that:
+//!
+//! * every basic block ends with exactly one terminator,
+//! * the operands of every instruction have the types it requires,
+//! * every use of a value is dominated by its definition,
+//! * the arguments of jumps match the parameters of their targets,
+//! * every callee exists and is called with matching arguments.
+
+use koopa::ir::entities::{BasicBlockData, FunctionData, ValueData};
+use koopa::ir::{BasicBlock, Program, Type, TypeKind, Value, ValueKind};
+use std::collections::HashMap;
+use std::fmt::{self, Display};
+
+/// An error found in a function, or among the global values.
+#[derive(Debug)]
+pub struct VerifyError {
+    /// name of the function, `None` for global values
+    pub func: Option<String>,
+    pub message: String,
+}
+
+impl Display for VerifyError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match &self.func {
+            Some(func) => write!(f, "invalid Koopa IR in `{}`: {}", func, self.message),
+            None => write!(f, "invalid Koopa IR in global values: {}", self.message),
+        }
+    }
+}
+
+impl std::error::Error for VerifyError {}
+
+/// Verifies the program, returns all errors found.
+pub fn verify(program: &Program) -> Result<(), Vec<VerifyError>> {
+    let mut errors = Vec::new();
+    verify_globals(program, &mut errors);
+    for &func in program.func_layout() {
+        let data = program.func(func);
+        if data.layout().entry_bb().is_some() {
+            FuncVerifier::new(program, data, &mut errors).verify();
+        }
+    }
+    if errors.is_empty() {
+        Ok(())
+    } else {
+        Err(errors)
+    }
+}
+
+/// Initializers of global allocations must be constants of the allocated type.
+fn verify_globals(program: &Program, errors: &mut Vec<VerifyError>) {
+    let values = program.borrow_values();
+    for value in program.inst_layout() {
+        let data = &values[value];
+        let message = match data.kind() {
+            ValueKind::GlobalAlloc(alloc) => match values.get(&alloc.init()) {
+                Some(init) if !init.kind().is_const() => {
+                    Some("initializer of a global allocation is not a constant".to_string())
+                }
+                Some(init) if Some(init.ty()) != pointee(data.ty()) => Some(format!(
+                    "initializer of type {} does not match the global allocation of type {}",
+                    init.ty(),
+                    data.ty()
+                )),
+                Some(_) => None,
+                None => Some("initializer of a global allocation does not exist".to_string()),
+            },
+            _ => Some("global instruction is not an allocation".to_string()),
+        };
+        if let Some(message) = message {
+            errors.push(VerifyError { func: None, message });
+        }
+    }
+}
+
+/// Type pointed to by a pointer type.
+fn pointee(ty: &Type) -> Option<&Type> {
+    match ty.kind() {
+        TypeKind::Pointer(base) => Some(base),
+        _ => None,
+    }
+}
+
+/// Name of the instruction, used in messages.
+fn inst_name(kind: &ValueKind) -> &'static str {
+    match kind {
+        ValueKind::Integer(_) => "integer",
+        ValueKind::ZeroInit(_) => "zeroinit",
+        ValueKind::Undef(_) => "undef",
+        ValueKind::Aggregate(_) => "aggregate",
+        ValueKind::FuncArgRef(_) => "function argument",
+        ValueKind::BlockArgRef(_) => "block argument",
+        ValueKind::Alloc(_) => "alloc",
+        ValueKind::GlobalAlloc(_) => "global alloc",
+        ValueKind::Load(_) => "load",
+        ValueKind::Store(_) => "store",
+        ValueKind::GetPtr(_) => "getptr",
+        ValueKind::GetElemPtr(_) => "getelemptr",
+        ValueKind::Binary(_) => "binary",
+        ValueKind::Branch(_) => "br",
+        ValueKind::Jump(_) => "jump",
+        ValueKind::Call(_) => "call",
+        ValueKind::Return(_) => "ret",
+    }
+}
+
+/// Times of entering and leaving every node in a depth-first walk of the
+/// graph from node 0, `None` for nodes which can not be reached.
+fn walk(edges: &[Vec<usize>]) -> Vec<Option<(usize, usize)>> {
+    let mut times = vec![None; edges.len()];
+    let mut enter: Vec<Option<usize>> = vec![None; edges.len()];
+    let mut time = 0;
+    // nodes on the path from the root, with the next edge to follow
+    let mut stack = Vec::new();
+    if !edges.is_empty() {
+        stack.push((0, 0));
+        enter[0] = Some(time);
+        time += 1;
+    }
+    while let Some(&(node, next)) = stack.last() {
+        match edges[node].get(next) {
+            Some(&succ) => {
+                stack.last_mut().unwrap().1 += 1;
+                if enter[succ].is_none() {
+                    enter[succ] = Some(time);
+                    time += 1;
+                    stack.push((succ, 0));
+                }
+            }
+            None => {
+                times[node] = Some((enter[node].unwrap(), time));
+                time += 1;
+                stack.pop();
+            }
+        }
+    }
+    times
+}
+
+fn is_terminator(kind: &ValueKind) -> bool {
+    matches!(kind, ValueKind::Branch(_) | ValueKind::Jump(_) | ValueKind::Return(_))
+}
+
+struct FuncVerifier<'a> {
+    program: &'a Program,
+    func: &'a FunctionData,
+    errors: &'a mut Vec<VerifyError>,
+    /// basic blocks in layout order, the entry first
+    bbs: Vec<BasicBlock>,
+    /// index in `bbs` of every basic block
+    bb_index: HashMap<BasicBlock, usize>,
+    /// index in `bbs` of the block of every block parameter
+    bb_params: HashMap<Value, usize>,
+    /// index in `bbs` and position in the block of every instruction
+    insts: HashMap<Value, (usize, usize)>,
+    /// times of entering and leaving every block in a depth-first walk of
+    /// the dominator tree, `None` for unreachable blocks
+    dom_tree: Vec<Option<(usize, usize)>>,
+}
+
+impl<'a> FuncVerifier<'a> {
+    fn new(program: &'a Program, func: &'a FunctionData, errors: &'a mut Vec<VerifyError>) -> Self {
+        let bbs: Vec<BasicBlock> = func.layout().bbs().keys().copied().collect();
+        let bb_index = bbs.iter().enumerate().map(|(i, &bb)| (bb, i)).collect();
+        let mut bb_params = HashMap::new();
+        let mut insts = HashMap::new();
+        for (i, (bb, node)) in func.layout().bbs().iter().enumerate() {
+            if let Some(data) = func.dfg().bbs().get(bb) {
+                bb_params.extend(data.params().iter().map(|&param| (param, i)));
+            }
+            for (j, &inst) in node.insts().keys().enumerate() {
+                insts.insert(inst, (i, j));
+            }
+        }
+        FuncVerifier {
+            program,
+            func,
+            errors,
+            bbs,
+            bb_index,
+            bb_params,
+            insts,
+            dom_tree: Vec::new(),
+        }
+    }
+
+    fn error(&mut self, message: String) {
+        self.errors.push(VerifyError {
+            func: Some(self.func.name().to_string()),
+            message,
+        });
+    }
+
+    /// Name of the basic block, used in messages.
+    fn bb_name(&self, bb: BasicBlock) -> String {
+        match self.func.dfg().bbs().get(&bb).and_then(|data| data.name().clone()) {
+            Some(name) => format!("`{}`", name),
+            None => "unnamed basic block".to_string(),
+        }
+    }
+
+    fn bb_data(&self, bb: BasicBlock) -> Option<&'a BasicBlockData> {
+        self.func.dfg().bbs().get(&bb)
+    }
+
+    /// Data of a local or global value.
+    fn value_ty(&self, value: Value) -> Option<Type> {
+        if value.is_global() {
+            self.program.borrow_values().get(&value).map(|data| data.ty().clone())
+        } else {
+            self.func.dfg().values().get(&value).map(|data| data.ty().clone())
+        }
+    }
+
+    fn verify(&mut self) {
+        for i in 0..self.bbs.len() {
+            self.verify_terminator(i);
+        }
+        self.compute_dominators();
+        let insts: Vec<(Value, (usize, usize))> = self
+            .func
+            .layout()
+            .bbs()
+            .iter()
+            .enumerate()
+            .flat_map(|(i, (_, node))| node.insts().keys().enumerate().map(move |(j, &inst)| (inst, (i, j))))
+            .collect();
+        for (inst, (i, j)) in insts {
+            let data = match self.func.dfg().values().get(&inst) {
+                Some(data) => data,
+                None => {
+                    let message = format!("instruction in {} does not exist", self.bb_name(self.bbs[i]));
+                    self.error(message);
+                    continue;
+                }
+            };
+            if !data.kind().is_local_inst() {
+                let message = format!(
+                    "{} in {} is not an instruction",
+                    inst_name(data.kind()),
+                    self.bb_name(self.bbs[i])
+                );
+                self.error(message);
+                continue;
+            }
+            self.verify_types(data, i);
+            self.verify_uses(data, i, j);
+        }
+    }
+
+    /// The block is not empty, ends with a terminator and has no other one.
+    fn verify_terminator(&mut self, i: usize) {
+        let node = self.func.layout().bbs().node(&self.bbs[i]).unwrap();
+        let kinds: Vec<bool> = node
+            .insts()
+            .keys()
+            .map(|inst| match self.func.dfg().values().get(inst) {
+                Some(data) => is_terminator(data.kind()),
+                None => false,
+            })
+            .collect();
+        let bb = self.bb_name(self.bbs[i]);
+        match kinds.split_last() {
+            None => self.error(format!("basic block {} is empty", bb)),
+            Some((false, _)) => self.error(format!("basic block {} does not end with a terminator", bb)),
+            Some((true, rest)) if rest.contains(&true) => {
+                self.error(format!("basic block {} has instructions after its terminator", bb))
+            }
+            Some(_) => {}
+        }
+    }
+
+    /// Successors of the block, taken from its terminator.
+    fn successors(&self, i: usize) -> Vec<usize> {
+        let node = self.func.layout().bbs().node(&self.bbs[i]).unwrap();
+        let kind = match node.insts().back_key().and_then(|inst| self.func.dfg().values().get(inst)) {
+            Some(data) => data.kind(),
+            None => return Vec::new(),
+        };
+        kind.bb_uses().filter_map(|bb| self.bb_index.get(&bb).copied()).collect()
+    }
+
+    /// Computes the immediate dominators with the iterative algorithm of
+    /// Cooper, Harvey and Kennedy, and numbers the dominator tree so that
+    /// `dominates` takes constant time.
+    fn compute_dominators(&mut self) {
+        let n = self.bbs.len();
+        let succs: Vec<Vec<usize>> = (0..n).map(|i| self.successors(i)).collect();
+        let mut preds = vec![Vec::new(); n];
+        for (i, succs) in succs.iter().enumerate() {
+            for &s in succs.iter() {
+                preds[s].push(i);
+            }
+        }
+
+        // blocks which can not be reached from the entry have no dominators,
+        // the others are numbered in postorder by the time they are left
+        let times = walk(&succs);
+        let mut rpo: Vec<usize> = (0..n).filter(|&i| times[i].is_some()).collect();
+        rpo.sort_by_key(|&i| std::cmp::Reverse(times[i].unwrap().1));
+        let post = |i: usize| times[i].map_or(0, |(_, exit)| exit);
+
+        let mut idom: Vec<Option<usize>> = vec![None; n];
+        if n > 0 {
+            idom[0] = Some(0);
+        }
+        let mut changed = true;
+        while changed {
+            changed = false;
+            for &i in rpo.iter().skip(1) {
+                let mut new_idom = None;
+                for &p in preds[i].iter().filter(|&&p| idom[p].is_some()) {
+                    new_idom = Some(match new_idom {
+                        None => p,
+                        Some(mut q) => {
+                            // walk up from both to their common dominator
+                            let mut p = p;
+                            while p != q {
+                                while post(p) < post(q) {
+                                    p = idom[p].unwrap();
+                                }
+                                while post(q) < post(p) {
+                                    q = idom[q].unwrap();
+                                }
+                            }
+                            p
+                        }
+                    });
+                }
+                if new_idom != idom[i] {
+                    idom[i] = new_idom;
+                    changed = true;
+                }
+            }
+        }
+
+        let mut children = vec![Vec::new(); n];
+        for &i in rpo.iter().skip(1) {
+            children[idom[i].unwrap()].push(i);
+        }
+        self.dom_tree = walk(&children);
+    }
+
+    /// Whether the definition at `def` comes before the use at `at` on
+    /// every path from the entry, both are (block index, position).
+    fn dominates(&self, def: (usize, usize), at: (usize, usize)) -> bool {
+        match (self.dom_tree[def.0], self.dom_tree[at.0]) {
+            // uses in unreachable blocks are never executed
+            (_, None) => true,
+            _ if def.0 == at.0 => def.1 < at.1,
+            (Some(def), Some(at)) => def.0 <= at.0 && at.1 <= def.1,
+            (None, Some(_)) => false,
+        }
+    }
+
+    /// Every used value exists and is defined before the use.
+    fn verify_uses(&mut self, data: &ValueData, i: usize, j: usize) {
+        let name = inst_name(data.kind());
+        for value in data.kind().value_uses() {
+            if value.is_global() {
+                if self.program.borrow_values().get(&value).is_none() {
+                    self.error(format!("operand of {} is a global value which does not exist", name));
+                }
+                continue;
+            }
+            let used = match self.func.dfg().values().get(&value) {
+                Some(used) => used,
+                None => {
+                    self.error(format!("operand of {} does not exist", name));
+                    continue;
+                }
+            };
+            let dominated = match used.kind() {
+                kind if kind.is_const() => true,
+                ValueKind::FuncArgRef(_) => self.func.params().contains(&value),
+                ValueKind::BlockArgRef(_) => match self.bb_params.get(&value) {
+                    Some(&def) => self.dominates((def, 0), (i, j + 1)),
+                    None => false,
+                },
+                _ => match self.insts.get(&value) {
+                    Some(&def) => self.dominates(def, (i, j)),
+                    None => false,
+                },
+            };
+            if !dominated {
+                let message = format!(
+                    "operand of {} in {} is not defined before it is used",
+                    name,
+                    self.bb_name(self.bbs[i])
+                );
+                self.error(message);
+            }
+        }
+    }
+
+    /// Operand types of the instruction and the type of its result.
+    fn verify_types(&mut self, data: &ValueData, i: usize) {
+        let bb = self.bb_name(self.bbs[i]);
+        let i32_ty = Type::get_i32();
+        let name = inst_name(data.kind());
+        // every operand which does not exist is reported by `verify_uses`
+        let ty = |value: Value| self.value_ty(value);
+
+        let expect = |what: &str, found: Option<Type>, expected: &Type| match found {
+            Some(found) if found != *expected => Some(format!(
+                "{} of {} in {} has type {}, expected {}",
+                what, name, bb, found, expected
+            )),
+            _ => None,
+        };
+        let result = Some(data.ty().clone());
+        let not_pointer = |what: &str| Some(format!("{} of {} in {} is not a pointer", what, name, bb));
+
+        let messages: Vec<Option<String>> = match data.kind() {
+            ValueKind::Alloc(_) => match pointee(data.ty()) {
+                Some(_) => Vec::new(),
+                None => vec![not_pointer("result")],
+            },
+            ValueKind::Load(load) => match ty(load.src()) {
+                Some(src) => match pointee(&src) {
+                    Some(base) => vec![expect("result", result, base)],
+                    None => vec![not_pointer("source")],
+                },
+                None => Vec::new(),
+            },
+            ValueKind::Store(store) => match ty(store.dest()) {
+                Some(dest) => match pointee(&dest) {
+                    Some(base) => vec![expect("value", ty(store.value()), base)],
+                    None => vec![not_pointer("destination")],
+                },
+                None => Vec::new(),
+            },
+            ValueKind::GetPtr(gp) => {
+                let src = match ty(gp.src()) {
+                    Some(src) if pointee(&src).is_some() => expect("result", result, &src),
+                    Some(_) => not_pointer("source"),
+                    None => None,
+                };
+                vec![expect("index", ty(gp.index()), &i32_ty), src]
+            }
+            ValueKind::GetElemPtr(gep) => {
+                let src = ty(gep.src());
+                let src = match src.as_ref().map(|src| pointee(src).map(|base| base.kind())) {
+                    Some(Some(TypeKind::Array(elem, _))) => {
+                        expect("result", result, &Type::get_pointer(elem.clone()))
+                    }
+                    Some(_) => Some(format!("source of getelemptr in {} is not a pointer to an array", bb)),
+                    None => None,
+                };
+                vec![expect("index", ty(gep.index()), &i32_ty), src]
+            }
+            ValueKind::Binary(binary) => vec![
+                expect("left operand", ty(binary.lhs()), &i32_ty),
+                expect("right operand", ty(binary.rhs()), &i32_ty),
+                expect("result", result, &i32_ty),
+            ],
+            ValueKind::Branch(branch) => {
+                let mut messages = vec![expect("condition", ty(branch.cond()), &i32_ty)];
+                messages.extend(self.verify_args(branch.true_bb(), branch.true_args(), &bb));
+                messages.extend(self.verify_args(branch.false_bb(), branch.false_args(), &bb));
+                messages
+            }
+            ValueKind::Jump(jump) => self.verify_args(jump.target(), jump.args(), &bb),
+            ValueKind::Call(call) => match self.program.funcs().get(&call.callee()) {
+                Some(callee) => {
+                    let (params, ret) = match callee.ty().kind() {
+                        TypeKind::Function(params, ret) => (params, ret),
+                        _ => unreachable!(),
+                    };
+                    let mut messages = vec![expect("result", result, ret)];
+                    if params.len() != call.args().len() {
+                        messages.push(Some(format!(
+                            "call of `{}` in {} has {} arguments, expected {}",
+                            callee.name(),
+                            bb,
+                            call.args().len(),
+                            params.len()
+                        )));
+                    } else {
+                        for (&arg, param) in call.args().iter().zip(params) {
+                            messages.push(expect("argument", ty(arg), param));
+                        }
+                    }
+                    messages
+                }
+                None => vec![Some(format!("callee of call in {} does not exist", bb))],
+            },
+            ValueKind::Return(ret) => {
+                let expected = match self.func.ty().kind() {
+                    TypeKind::Function(_, ret) => ret.clone(),
+                    _ => unreachable!(),
+                };
+                match ret.value() {
+                    Some(value) => vec![expect("value", ty(value), &expected)],
+                    None if !expected.is_unit() => {
+                        vec![Some(format!("ret in {} has no value, expected {}", bb, expected))]
+                    }
+                    None => Vec::new(),
+                }
+            }
+            _ => Vec::new(),
+        };
+        for message in messages.into_iter().flatten() {
+            self.error(message);
+        }
+    }
+
+    /// The arguments of a jump to the block match its parameters.
+    fn verify_args(&self, target: BasicBlock, args: &[Value], bb: &str) -> Vec<Option<String>> {
+        let params = match self.bb_data(target) {
+            Some(data) if self.bbs.contains(&target) => data.params(),
+            _ => return vec![Some(format!("target of the terminator of {} does not exist", bb))],
+        };
+        if params.len() != args.len() {
+            return vec![Some(format!(
+                "jump from {} to {} has {} arguments, expected {}",
+                bb,
+                self.bb_name(target),
+                args.len(),
+                params.len()
+            ))];
+        }
+        let mut messages = Vec::new();
+        for (&arg, &param) in args.iter().zip(params) {
+            if let (Some(arg_ty), Some(param_ty)) = (self.value_ty(arg), self.value_ty(param)) {
+                if arg_ty != param_ty {
+                    messages.push(Some(format!(
+                        "argument of jump from {} to {} has type {}, expected {}",
+                        bb,
+                        self.bb_name(target),
+                        arg_ty,
+                        param_ty
+                    )));
+                }
+            }
+        }
+        messages
+    }
+}
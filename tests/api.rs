@@ -0,0 +1,46 @@
+//! The library API used by tools embedding the compiler.
+
+use compiler::{compile, Output, Stage};
+
+const SOURCE: &str = "int main() { return 1 + 2; }";
+
+#[test]
+fn every_stage_gives_its_output() {
+    match compile(SOURCE, Stage::Ast) {
+        Ok(Output::Ast(unit)) => assert_eq!(unit.unit().items.len(), 1),
+        _ => panic!("expected the syntax tree"),
+    }
+    match compile(SOURCE, Stage::Koopa) {
+        Ok(Output::Koopa(program)) => assert_eq!(program.func_layout().len(), 9),
+        _ => panic!("expected the program"),
+    }
+    match compile(SOURCE, Stage::KoopaText) {
+        Ok(Output::KoopaText(text)) => assert!(text.contains("fun @main(): i32 {")),
+        _ => panic!("expected Koopa IR text"),
+    }
+    match compile(SOURCE, Stage::Riscv) {
+        Ok(Output::Riscv(text)) => assert!(text.contains("main:")),
+        _ => panic!("expected RISC-V assembly"),
+    }
+}
+
+#[test]
+fn errors_are_returned_as_diagnostics() {
+    let source = "int main() { int a = 08; return b; }";
+    let diags = match compile(source, Stage::Ast) {
+        Err(diags) => diags,
+        Ok(_) => panic!("expected errors"),
+    };
+    assert_eq!(diags.len(), 1);
+    assert!(diags[0].message.starts_with("unexpected token `8`"));
+
+    let source = "int main() { return b; }";
+    let diags = match compile(source, Stage::Riscv) {
+        Err(diags) => diags,
+        Ok(_) => panic!("expected errors"),
+    };
+    assert_eq!(diags.len(), 1);
+    assert_eq!(diags[0].to_string(), "use of undeclared identifier `b`");
+    let span = diags[0].span.unwrap();
+    assert_eq!(&source[span.start..span.end], "b");
+}
@@ -0,0 +1,103 @@
+//! Arrays and their initializer lists, which are flattened and padded with
+//! zeros like in C.
+
+mod common;
+
+use common::{compile, koopa, riscv};
+
+#[test]
+fn global_initializers_are_padded_with_zeros() {
+    let ir = koopa("int g[2][3] = {1, 2, 3, {4}}; int z[4]; int main() { return 0; }");
+    assert!(ir.contains("global @g = alloc [[i32, 3], 2], {{1, 2, 3}, {4, 0, 0}}\n"), "{}", ir);
+    assert!(ir.contains("global @z = alloc [i32, 4], zeroinit\n"), "{}", ir);
+
+    let asm = riscv("int g[2][3] = {1, 2, 3, {4}}; int z[4]; int main() { return 0; }");
+    let words = [1, 2, 3, 4, 0, 0].map(|v| format!("  .word {}\n", v)).concat();
+    assert!(asm.contains(&format!("g:\n{}", words)), "{}", asm);
+    assert!(asm.contains("z:\n  .zero 16\n"), "{}", asm);
+}
+
+#[test]
+fn every_element_of_a_local_array_is_stored() {
+    let ir = koopa("int main() { int a[2][3] = {{1, 2}, 3, 4}; return 0; }");
+    let stored: Vec<_> = ir
+        .lines()
+        .filter_map(|line| Some(line.trim().strip_prefix("store ")?.split(", ").next()?.to_string()))
+        .collect();
+    assert_eq!(stored, ["1", "2", "0", "3", "4", "0"]);
+}
+
+#[test]
+fn dimensions_are_constant_expressions() {
+    let ir = koopa("const int n[2] = {2, 3}; int main() { int a[n[1] * 2][n[0]]; return 0; }");
+    assert!(ir.contains(" = alloc [[i32, 2], 6]\n"), "{}", ir);
+}
+
+#[test]
+fn elements_are_indexed_with_getelemptr() {
+    let ir = koopa("int main() { int a[2][3] = {}; int i = 1; a[i][2] = 5; return a[1][i]; }");
+    let geps: Vec<_> = ir.lines().filter(|line| line.contains(" = getelemptr ")).collect();
+    // 6 elements are initialized, then two are accessed
+    assert_eq!(geps.len(), 2 * 6 + 2 * 2, "{}", ir);
+    assert!(geps[12].contains(" = getelemptr @a, %"), "{}", ir);
+    assert!(geps[13].ends_with(", 2"), "{}", ir);
+    assert!(geps[14].ends_with(" = getelemptr @a, 1"), "{}", ir);
+    assert!(geps[15].contains(", %"), "{}", ir);
+}
+
+#[test]
+fn braces_must_be_aligned_with_a_dimension() {
+    let stderr = compile("-koopa", "int g[2][3] = {1, {4}}; int main() { return 0; }").unwrap_err();
+    assert!(stderr.contains("invalid initializer"), "{}", stderr);
+}
+
+/// Lines of the function in the Koopa IR.
+fn func<'a>(ir: &'a str, name: &str) -> Vec<&'a str> {
+    let start = format!("fun @{}(", name);
+    ir.lines()
+        .skip_while(|line| !line.starts_with(&start))
+        .take_while(|line| *line != "}")
+        .map(|line| line.trim())
+        .collect()
+}
+
+const PARAMS: &str = "
+    int sum(int a[], int n) { return a[n - 1]; }
+    int row(int m[][3], int i) { return sum(m[i], 3); }
+    int main() { int m[2][3] = {}; return sum(m[1], 3) + row(m, 1); }
+";
+
+#[test]
+fn array_parameters_are_pointers_indexed_with_getptr() {
+    let ir = koopa(PARAMS);
+    let sum = func(&ir, "sum");
+    assert_eq!(sum[0], "fun @sum(@a: *i32, @n: i32): i32 {");
+    assert!(sum.iter().any(|inst| inst.contains(" = getptr %")), "{}", ir);
+    let row = func(&ir, "row");
+    assert_eq!(row[0], "fun @row(@m: *[i32, 3], @i: i32): i32 {");
+    // `m[i]` is the pointer to the first element of the row
+    let getptr = row.iter().position(|inst| inst.contains(" = getptr %")).unwrap();
+    assert!(row[getptr + 1].ends_with(", 0"), "{}", ir);
+    assert!(row[getptr + 2].contains(" = call @sum(%"), "{}", ir);
+}
+
+#[test]
+fn arrays_and_rows_decay_to_pointers_to_their_first_element() {
+    let ir = koopa(PARAMS);
+    let main = func(&ir, "main");
+    let calls: Vec<_> = main.iter().enumerate().filter(|(_, inst)| inst.contains(" = call @")).collect();
+    let (sum, row) = (calls[0].0, calls[1].0);
+    assert!(main[sum - 2].ends_with(" = getelemptr @m, 1"), "{}", ir);
+    assert!(main[sum - 1].ends_with(", 0"), "{}", ir);
+    assert!(main[row - 1].ends_with(" = getelemptr @m, 0"), "{}", ir);
+}
+
+#[test]
+fn indexes_are_scaled_by_the_size_of_the_elements() {
+    let asm = riscv("int main() { int m[2][3] = {}; int i = 1; m[i][0] = 2; return m[0][i]; }");
+    let lines: Vec<_> = asm.lines().map(|line| line.trim()).collect();
+    for size in [12, 4] {
+        let li = lines.iter().position(|line| line.starts_with("li ") && line.ends_with(&format!(", {}", size)));
+        assert!(lines[li.unwrap() + 1].starts_with("mul "), "{}", asm);
+    }
+}
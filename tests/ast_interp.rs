@@ -0,0 +1,96 @@
+//! The AST interpreter runs checked programs without lowering them, and
+//! agrees with the Koopa IR interpreter.
+
+use compiler::parser::ast::check::CheckedUnit;
+use compiler::parser::ast::interp::run;
+use compiler::parser::interp;
+use compiler::parser::runtime::{RunError, DEFAULT_STEP_LIMIT};
+use compiler::{compile, Output, Stage};
+
+fn unit(source: &str) -> CheckedUnit {
+    match compile(source, Stage::Ast) {
+        Ok(Output::Ast(unit)) => unit,
+        _ => panic!("failed to check"),
+    }
+}
+
+const PROGRAM: &str = r#"
+    const int N = 3;
+    int g[2][N] = {{1, 2}, {3}};
+    int calls;
+    int fib(int n) { if (n < 2) return n; return fib(n - 1) + fib(n - 2); }
+    int sum(int a[], int n) {
+        int i = 0, s = 0;
+        while (i < n) { s = s + a[i]; i = i + 1; }
+        return s;
+    }
+    int side(int x) { calls = calls + 1; return x; }
+    void fill(int a[][N], int v) { a[1][2] = v; }
+    int main() {
+        int a[8];
+        int n = getarray(a);
+        putint(fib(getint())); putch(10);
+        putint(sum(a, n)); putch(10);
+        fill(g, 7);
+        putarray(N, g[1]);
+        int x = 1;
+        {
+            int x = 2;
+            x = x + 1;
+        }
+        if (side(0) && side(1) || side(1) || side(1)) x = x + 10;
+        putint(x); putch(32); putint(calls); putch(10);
+        int c = getch();
+        while (c != -1) { if (c > 32) putch(c); c = getch(); }
+        return -2147483648 / -1 + 300;
+    }
+"#;
+
+#[test]
+fn runs_with_scopes_arrays_and_short_circuit() {
+    let outcome = run(&unit(PROGRAM), b"3 1 2 3\n10 a b", DEFAULT_STEP_LIMIT).unwrap();
+    assert_eq!(String::from_utf8(outcome.output).unwrap(), "55\n6\n3: 3 0 7\n11 2\nab");
+    assert_eq!(outcome.exit_code, i32::MIN + 300);
+    assert!(outcome.steps > 0);
+}
+
+#[test]
+fn agrees_with_the_koopa_interpreter() {
+    let program = match compile(PROGRAM, Stage::Koopa) {
+        Ok(Output::Koopa(program)) => program,
+        _ => panic!("failed to compile"),
+    };
+    let input = b"3 1 2 3\n10 a b";
+    let expected = interp::run(&program, input, DEFAULT_STEP_LIMIT).unwrap();
+    let outcome = run(&unit(PROGRAM), input, DEFAULT_STEP_LIMIT).unwrap();
+    assert_eq!(outcome.output, expected.output);
+    assert_eq!(outcome.exit_code, expected.exit_code);
+}
+
+#[test]
+fn deep_recursion_runs() {
+    let unit = unit("int f(int n) { if (n == 0) return 0; return f(n - 1) + 1; } int main() { return f(50000); }");
+    assert_eq!(run(&unit, b"", DEFAULT_STEP_LIMIT).unwrap().exit_code, 50000);
+}
+
+#[test]
+fn unbounded_recursion_stops_before_the_stack_overflows() {
+    let unit = unit("int f(int n) { return f(n + 1) + 1; } int main() { return f(0); }");
+    match run(&unit, b"", DEFAULT_STEP_LIMIT) {
+        Err(RunError::StackOverflow(depth)) => assert!(depth > 1000, "{}", depth),
+        result => panic!("{:?}", result),
+    }
+}
+
+#[test]
+fn runtime_errors_are_reported() {
+    let unit1 = unit("int main() { int a[2][3]; a[1][3] = 1; return a[2][0]; }");
+    assert_eq!(
+        run(&unit1, b"", DEFAULT_STEP_LIMIT),
+        Err(RunError::OutOfBounds("in `main`: word 6 of an allocation of 6 words".into()))
+    );
+    let unit2 = unit("int f(int x) { return 1 % x; } int main() { return f(0); }");
+    assert_eq!(run(&unit2, b"", DEFAULT_STEP_LIMIT), Err(RunError::DivisionByZero("f".into())));
+    let unit3 = unit("int main() { while (1) {} return 0; }");
+    assert_eq!(run(&unit3, b"", 1000), Err(RunError::StepLimit(1000)));
+}
@@ -0,0 +1,45 @@
+//! `if` statements are lowered to `br` between fresh basic blocks, and to
+//! `bnez` and `j` between labels in the assembly.
+
+mod common;
+
+use common::{block, blocks, branches, koopa, riscv};
+
+#[test]
+fn if_without_else_falls_through_to_the_end() {
+    let ir = koopa("int main() { int a = 1; if (a) a = 2; return a; }");
+    let blocks = blocks(&ir);
+    let branches = branches(&ir);
+    assert_eq!(branches.len(), 1);
+    let (then, end) = &branches[0];
+    assert!(block(&blocks, then).last().unwrap().starts_with("jump "));
+    assert!(block(&blocks, end).last().unwrap().starts_with("ret "));
+}
+
+#[test]
+fn else_binds_to_the_nearest_if() {
+    let ir = koopa("int main() { int a = 1; int b = 0; if (a) if (b) return 1; else return 2; return 3; }");
+    let blocks = blocks(&ir);
+    let branches = branches(&ir);
+    assert_eq!(branches.len(), 2);
+    // the outer `if` has no `else`, it skips to `return 3`
+    assert_eq!(block(&blocks, &branches[0].1), ["ret 3"]);
+    assert_eq!(block(&blocks, &branches[1].0), ["ret 1"]);
+    assert_eq!(block(&blocks, &branches[1].1), ["ret 2"]);
+}
+
+#[test]
+fn both_arms_may_return() {
+    let ir = koopa("int main() { int a = 0; if (a) return 1; else return 2; }");
+    let blocks = blocks(&ir);
+    let (then, other) = &branches(&ir)[0];
+    assert_eq!(block(&blocks, then), ["ret 1"]);
+    assert_eq!(block(&blocks, other), ["ret 2"]);
+}
+
+#[test]
+fn branches_are_lowered_to_labels() {
+    let asm = riscv("int main() { int a = 1; if (a) a = 2; else a = 3; return a; }");
+    assert!(asm.lines().any(|line| line.trim().starts_with("bnez ")), "{}", asm);
+    assert!(asm.lines().any(|line| line.trim().starts_with("j ")), "{}", asm);
+}
@@ -0,0 +1,64 @@
+//! Calls follow the ILP32 calling convention: the first 8 arguments are
+//! passed in `a0` to `a7`, the others on the stack of the caller.
+
+mod common;
+
+use common::riscv;
+
+const SOURCE: &str = "
+    int f(int a, int b, int c, int d, int e, int g, int h, int i, int j, int k) { return a + j * 2 + k * 3; }
+    int main() { return f(1, 2, 3, 4, 5, 6, 7, 8, 9, 10); }
+";
+
+/// Instructions of the function in the assembly.
+fn func<'a>(asm: &'a str, name: &str) -> Vec<&'a str> {
+    asm.lines()
+        .skip_while(|line| *line != format!("{}:", name))
+        .skip(1)
+        .take_while(|line| !line.contains(".global") && !line.ends_with(':'))
+        .map(|line| line.trim())
+        .collect()
+}
+
+/// Size of the stack frame of the function.
+fn frame(insts: &[&str]) -> i32 {
+    insts[0].strip_prefix("addi sp, sp, -").unwrap().parse().unwrap()
+}
+
+#[test]
+fn arguments_beyond_a7_are_passed_on_the_stack() {
+    let asm = riscv(SOURCE);
+    let main = func(&asm, "main");
+    let call = main.iter().position(|inst| *inst == "call f").unwrap();
+    for i in 0..8 {
+        let reg = format!("a{},", i);
+        assert!(main[..call].iter().any(|inst| inst.split(' ').nth(1) == Some(&reg)), "{}", asm);
+    }
+    // the 9th and 10th arguments are at the bottom of the frame of the caller
+    for (value, offset) in [(9, 0), (10, 4)] {
+        let li = main.iter().position(|inst| inst.starts_with("li ") && inst.ends_with(&format!(", {}", value)));
+        let reg = main[li.unwrap()].split(' ').nth(1).unwrap();
+        assert_eq!(main[li.unwrap() + 1], format!("sw {} {}(sp)", reg, offset), "{}", asm);
+    }
+    // the result is in a0
+    assert!(main[call + 1].ends_with(", a0"), "{}", asm);
+
+    let f = func(&asm, "f");
+    let size = frame(&f);
+    for offset in [size, size + 4] {
+        let load = format!(", {}(sp)", offset);
+        assert!(f.iter().any(|inst| inst.starts_with("lw ") && inst.ends_with(&load)), "{}", asm);
+    }
+}
+
+#[test]
+fn frames_are_aligned_and_save_ra_if_they_call() {
+    let asm = riscv(SOURCE);
+    let (main, f) = (func(&asm, "main"), func(&asm, "f"));
+    assert_eq!(frame(&main) % 16, 0);
+    assert_eq!(frame(&f) % 16, 0);
+    let ra = main.iter().find(|inst| inst.starts_with("sw ra, ")).unwrap();
+    assert!(main.contains(&ra.replacen("sw", "lw", 1).as_str()), "{}", asm);
+    // `f` does not call, `ra` is left alone
+    assert!(!f.iter().any(|inst| inst.contains("ra,")), "{}", asm);
+}
@@ -0,0 +1,150 @@
+//! Programs which parse, but which the checker rejects.
+
+mod common;
+
+use common::compile;
+
+/// Messages of the errors of the checker, in the order they were reported.
+fn errors(source: &str) -> Vec<String> {
+    match compile("-koopa", source) {
+        Ok(_) => panic!("`{}` was accepted", source),
+        Err(stderr) => stderr
+            .lines()
+            .filter_map(|line| line.strip_prefix("error: "))
+            .filter(|message| !message.starts_with("aborting"))
+            .map(String::from)
+            .collect(),
+    }
+}
+
+/// Asserts that the checker accepts the program.
+fn accepted(source: &str) {
+    if let Err(stderr) = compile("-koopa", source) {
+        panic!("`{}` was rejected:\n{}", source, stderr);
+    }
+}
+
+#[test]
+fn undeclared_names() {
+    assert_eq!(
+        errors("int main() { return a; }"),
+        ["use of undeclared identifier `a`"]
+    );
+    assert_eq!(
+        errors("int main() { { int a; } a = 1; return 0; }"),
+        ["use of undeclared identifier `a`"]
+    );
+    assert_eq!(
+        errors("int main() { return f(); }"),
+        ["call to undeclared function `f`"]
+    );
+}
+
+#[test]
+fn globals_are_declared_in_source_order() {
+    assert_eq!(
+        errors("int f() { return g; } int g = 1; int main() { return f(); }"),
+        ["use of undeclared identifier `g`"]
+    );
+    assert_eq!(
+        errors("int f() { int g = 2; return g; } int g = 1; int main() { return g + h; } int h;"),
+        ["use of undeclared identifier `h`"]
+    );
+    let source = "int f(int n) { if (n) return f(n - 1); return 0; } int main() { return f(3); }";
+    accepted(source);
+}
+
+#[test]
+fn functions_can_be_called_before_their_definition() {
+    let source = "
+        int is_even(int n) { if (n == 0) return 1; return is_odd(n - 1); }
+        int is_odd(int n) { if (n == 0) return 0; return is_even(n - 1); }
+        int main() { return is_even(10) + later(); }
+        int later() { return 1; }
+    ";
+    accepted(source);
+}
+
+#[test]
+fn redeclarations() {
+    assert_eq!(
+        errors("int main() { int a; const int a = 1; return 0; }"),
+        ["redeclaration of `a`"]
+    );
+    assert_eq!(
+        errors("int f(int a, int a) { return a; } int main() { return 0; }"),
+        ["redeclaration of `a`"]
+    );
+    assert_eq!(
+        errors("int f; int f() { return 0; } int main() { return 0; }"),
+        ["redeclaration of `f`"]
+    );
+    // shadowing in an inner scope is fine
+    let source = "int a; int main() { int a = 1; { int a = 2; } return a; }";
+    accepted(source);
+}
+
+#[test]
+fn wrong_arity() {
+    assert_eq!(
+        errors("int f(int a) { return a; } int main() { return f(1, 2); }"),
+        ["function `f` takes 1 argument(s) but 2 were supplied"]
+    );
+    assert_eq!(
+        errors("int main() { putint(); return 0; }"),
+        ["function `putint` takes 1 argument(s) but 0 were supplied"]
+    );
+}
+
+#[test]
+fn assignment_to_a_constant() {
+    assert_eq!(
+        errors("const int c = 1; int main() { c = 2; return c; }"),
+        ["cannot assign to constant `c`"]
+    );
+    assert_eq!(
+        errors("int main() { const int a[2] = {1, 2}; a[0] = 3; return 0; }"),
+        ["cannot assign to constant `a`"]
+    );
+}
+
+#[test]
+fn non_constant_array_sizes() {
+    assert_eq!(
+        errors("int main() { int n = 2; int a[n]; return 0; }"),
+        ["expression is not a constant"]
+    );
+    assert_eq!(
+        errors("int main() { int a[0]; return 0; }"),
+        ["array size must be a positive constant"]
+    );
+    assert_eq!(
+        errors("int n = 2; int a[n]; int main() { return 0; }"),
+        ["expression is not a constant"]
+    );
+}
+
+#[test]
+fn misuse_of_runtime_names() {
+    assert_eq!(
+        errors("int getint; int main() { return 0; }"),
+        ["redeclaration of `getint`"]
+    );
+    assert_eq!(
+        errors("void putch(int c) {} int main() { return 0; }"),
+        ["redeclaration of `putch`"]
+    );
+    // the line number of the timer calls is passed by the compiler
+    assert_eq!(
+        errors("int main() { starttime(1); return 0; }"),
+        ["function `starttime` takes 0 argument(s) but 1 were supplied"]
+    );
+    assert_eq!(
+        errors("int main() { int a = putint(1); return a; }"),
+        ["void value not ignored as it ought to be"]
+    );
+    assert_eq!(
+        errors("int main() { int a; return getarray(a); }"),
+        ["mismatched type for argument 1 of `getarray`"]
+    );
+}
@@ -0,0 +1,106 @@
+//! Runs the compiler binary on SysY sources, for the tests which check the
+//! code it generates.
+
+#![allow(dead_code)]
+
+use koopa::front::Driver;
+use std::fs::{read_to_string, remove_file, write};
+use std::process::Command;
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+/// Compiles the source with `mode` (`-koopa` or `-riscv`), returns the
+/// generated code, or stderr if the compiler fails.
+pub fn compile(mode: &str, source: &str) -> Result<String, String> {
+    static COUNT: AtomicUsize = AtomicUsize::new(0);
+    let path = std::env::temp_dir().join(format!(
+        "compiler-{}-{}",
+        std::process::id(),
+        COUNT.fetch_add(1, Ordering::Relaxed)
+    ));
+    let input = path.with_extension("c");
+    let output = path.with_extension("out");
+    write(&input, source).unwrap();
+    let result = Command::new(env!("CARGO_BIN_EXE_compiler"))
+        .arg(mode)
+        .arg(&input)
+        .arg("-o")
+        .arg(&output)
+        .output()
+        .unwrap();
+    let code = read_to_string(&output);
+    let _ = remove_file(&input);
+    let _ = remove_file(&output);
+    match result.status.success() {
+        true => Ok(code.unwrap()),
+        false => Err(String::from_utf8_lossy(&result.stderr).into_owned()),
+    }
+}
+
+/// Koopa IR of the source, which must compile to IR the koopa crate accepts.
+pub fn koopa(source: &str) -> String {
+    let ir = compile("-koopa", source).unwrap_or_else(|stderr| panic!("`{}` failed:\n{}", source, stderr));
+    if let Err(err) = Driver::from(ir.as_str()).generate_program() {
+        panic!("invalid Koopa IR ({:?}):\n{}", err, ir);
+    }
+    ir
+}
+
+/// Assembly of the source, every label it jumps to must be defined.
+pub fn riscv(source: &str) -> String {
+    let asm = compile("-riscv", source).unwrap_or_else(|stderr| panic!("`{}` failed:\n{}", source, stderr));
+    check_labels(&asm);
+    asm
+}
+
+/// Asserts that every label the assembly jumps to is defined.
+pub fn check_labels(asm: &str) {
+    for line in asm.lines() {
+        let target = match line.split_whitespace().collect::<Vec<_>>()[..] {
+            ["j", target] => target,
+            [op, _, target] if op.starts_with('b') => target,
+            _ => continue,
+        };
+        assert!(
+            asm.lines().any(|line| line == format!("{}:", target)),
+            "undefined label `{}`:\n{}",
+            target,
+            asm
+        );
+    }
+}
+
+/// Basic blocks of the Koopa IR, as their label and instructions.
+pub fn blocks(ir: &str) -> Vec<(String, Vec<String>)> {
+    let mut blocks: Vec<(String, Vec<String>)> = Vec::new();
+    for line in ir.lines() {
+        let line = line.trim();
+        if line.starts_with('%') && line.ends_with(':') {
+            blocks.push((line.trim_end_matches(':').to_string(), Vec::new()));
+        } else if !line.is_empty() && !line.starts_with("fun ") && line != "}" {
+            if let Some((_, insts)) = blocks.last_mut() {
+                insts.push(line.to_string());
+            }
+        }
+    }
+    blocks
+}
+
+/// Instructions of the block with the label.
+pub fn block<'a>(blocks: &'a [(String, Vec<String>)], label: &str) -> &'a [String] {
+    match blocks.iter().find(|(name, _)| name == label) {
+        Some((_, insts)) => insts,
+        None => panic!("no block `{}`", label),
+    }
+}
+
+/// Targets of the `br` instructions, in order, as the labels if the
+/// condition is true and if it is false.
+pub fn branches(ir: &str) -> Vec<(String, String)> {
+    ir.lines()
+        .filter_map(|line| {
+            let args = line.trim().strip_prefix("br ")?;
+            let args: Vec<_> = args.split(", ").collect();
+            Some((args[1].to_string(), args[2].to_string()))
+        })
+        .collect()
+}
@@ -0,0 +1,58 @@
+//! Compiling the same source twice in one process gives identical output,
+//! no state is carried over between compilations.
+
+use compiler::{compile, Output, Stage};
+
+const SOURCE: &str = r#"
+int g[4] = {1, 2, 3, 4};
+
+int sum(int a[], int n) {
+    int i = 0, s = 0;
+    while (i < n) {
+        if (a[i] > 2 && a[i] != 3 || !a[i]) {
+            s = s + a[i] * (i + 1) - s / 7 % 3;
+        } else {
+            s = s - 1;
+        }
+        i = i + 1;
+    }
+    return s;
+}
+
+int main() {
+    int x = getint();
+    if (x || sum(g, 4)) {
+        putint(sum(g, x));
+    }
+    return 0;
+}
+"#;
+
+/// Compiles the source to Koopa IR text and RISC-V assembly.
+fn compile_both(source: &str) -> (String, String) {
+    let koopa = match compile(source, Stage::KoopaText) {
+        Ok(Output::KoopaText(text)) => text,
+        _ => panic!("failed to generate Koopa IR"),
+    };
+    let riscv = match compile(source, Stage::Riscv) {
+        Ok(Output::Riscv(text)) => text,
+        _ => panic!("failed to generate RISC-V"),
+    };
+    (koopa, riscv)
+}
+
+#[test]
+fn compiling_twice_gives_identical_output() {
+    let (koopa, riscv) = compile_both(SOURCE);
+    for _ in 0..3 {
+        let (koopa_again, riscv_again) = compile_both(SOURCE);
+        assert_eq!(koopa, koopa_again);
+        assert_eq!(riscv, riscv_again);
+    }
+}
+
+#[test]
+fn basic_block_names_restart_in_every_function() {
+    let (koopa, _) = compile_both(SOURCE);
+    assert_eq!(koopa.matches("%entry_0:").count(), 2);
+}
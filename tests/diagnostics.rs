@@ -0,0 +1,64 @@
+//! Rendering of diagnostics against the source.
+
+mod common;
+
+use common::compile;
+use compiler::parser::diagnostic::{Diagnostic, Span};
+
+fn render(src: &str, start: usize, end: usize) -> String {
+    Diagnostic::new("bad".to_string(), Span::new(start, end)).render("main.c", src)
+}
+
+#[test]
+fn multi_line_spans_are_underlined_to_the_end_of_their_first_line() {
+    let src = "int main() {\n  return 1 +\n    2;\n}\n";
+    let start = src.find("1 +").unwrap();
+    let end = src.find("2;").unwrap() + 1;
+    assert_eq!(
+        render(src, start, end),
+        "error: bad\n --> main.c:2:10\n  |\n2 |   return 1 +\n  |          ^^^\n"
+    );
+}
+
+#[test]
+fn tabs_are_kept_in_the_padding() {
+    let src = "int main() {\n\t\treturn x;\n}\n";
+    let start = src.find('x').unwrap();
+    assert_eq!(
+        render(src, start, start + 1),
+        "error: bad\n --> main.c:2:10\n  |\n2 | \t\treturn x;\n  | \t\t       ^\n"
+    );
+}
+
+#[test]
+fn carriage_returns_are_not_part_of_the_line() {
+    let src = "int main() {\r\n  return x;\r\n}\r\n";
+    let start = src.find("x;").unwrap();
+    assert_eq!(
+        render(src, start, start + 3),
+        "error: bad\n --> main.c:2:10\n  |\n2 |   return x;\n  |          ^^\n"
+    );
+}
+
+#[test]
+fn columns_and_carets_count_characters() {
+    let src = "// ééé\nint main() { return \"ü\"; }\n";
+    let start = src.find('"').unwrap();
+    assert_eq!(
+        render(src, start, start + "\"ü\"".len()),
+        "error: bad\n --> main.c:2:21\n  |\n2 | int main() { return \"ü\"; }\n  |                     ^^^\n"
+    );
+    // a span which splits `ü` covers the whole character
+    assert_eq!(
+        render(src, start + 2, start + 3),
+        "error: bad\n --> main.c:2:22\n  |\n2 | int main() { return \"ü\"; }\n  |                      ^\n"
+    );
+}
+
+#[test]
+fn invalid_non_ascii_tokens_are_reported() {
+    let src = "int main() {\n  return é;\n}\n";
+    let stderr = compile("-koopa", src).expect_err("`é` was accepted");
+    assert!(stderr.starts_with("error: invalid token\n --> "), "{}", stderr);
+    assert!(stderr.contains(":2:10\n  |\n2 |   return é;\n  |          ^\n"), "{}", stderr);
+}
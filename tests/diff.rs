@@ -0,0 +1,138 @@
+//! Differential testing runs a program with every engine and reports the
+//! first one which diverges.
+
+use compiler::diff::{diff, diff_koopa, Engine};
+use compiler::parser::runtime::{RunError, DEFAULT_STEP_LIMIT};
+use compiler::Options;
+use koopa::front::Driver;
+use std::fs::{read, read_dir, read_to_string};
+use std::path::Path;
+
+/// Every `tests/programs/<name>.c` is run by every engine with the input in
+/// `<name>.in`, if it exists. The engines must agree with each other and
+/// with `<name>.out`, which is the stdout followed by the exit code on a
+/// line of its own.
+#[test]
+fn programs() {
+    let mut cases: Vec<_> = read_dir("tests/programs")
+        .unwrap()
+        .map(|entry| entry.unwrap().path())
+        .filter(|path| path.extension().is_some_and(|ext| ext == "c"))
+        .collect();
+    cases.sort();
+    assert!(!cases.is_empty());
+
+    let mut failed = Vec::new();
+    for case in cases.iter() {
+        if !passes(case) {
+            failed.push(case.display().to_string());
+        }
+    }
+    assert!(failed.is_empty(), "failed cases: {}", failed.join(", "));
+}
+
+fn passes(case: &Path) -> bool {
+    let source = read_to_string(case).unwrap();
+    let input = read(case.with_extension("in")).unwrap_or_default();
+    let expected = read_to_string(case.with_extension("out")).unwrap();
+    let report = match diff(&source, &input, DEFAULT_STEP_LIMIT, &Options { verify: true }) {
+        Ok(report) => report,
+        Err(diags) => {
+            eprintln!("{}: {}", case.display(), diags[0].message);
+            return false;
+        }
+    };
+    if let Some(divergence) = report.divergence {
+        eprintln!("{}: {} diverges: {}", case.display(), divergence.engine, divergence.reason);
+        return false;
+    }
+    let outcome = match &report.runs[0].result {
+        Ok(outcome) => outcome,
+        Err(err) => {
+            eprintln!("{}: {}", case.display(), err);
+            return false;
+        }
+    };
+    let mut actual = String::from_utf8_lossy(&outcome.output).into_owned();
+    if !actual.is_empty() && !actual.ends_with('\n') {
+        actual.push('\n');
+    }
+    actual += &format!("{}\n", outcome.exit_code);
+    if actual != expected {
+        eprintln!("{}: expected\n{}\ngot\n{}", case.display(), expected, actual);
+        return false;
+    }
+    true
+}
+
+#[test]
+fn first_divergence_is_reported_with_its_code() {
+    // division by zero is caught by the interpreters, but not by RV32IM
+    let source = "int main() { int x = getint(); putint(1); return 1 / x; }";
+    let report = diff(source, b"0", DEFAULT_STEP_LIMIT, &Options::default()).unwrap();
+    let divergence = report.divergence.unwrap();
+    assert_eq!(divergence.engine, Engine::Riscv);
+    assert_eq!(divergence.reference, Engine::Ast);
+    assert_eq!(
+        divergence.reason,
+        "exits with -1 instead of failing with \"division by zero in `main`\""
+    );
+    assert!(divergence.code.unwrap().contains("div "));
+}
+
+#[test]
+fn running_out_of_steps_is_inconclusive() {
+    // a step of the AST interpreter is a whole statement, the other engines
+    // need several steps for it and run out of them
+    let source = "int main() { int i = 0; while (i < 1000) i = i + 1; return 7; }";
+    let report = diff(source, b"", 5000, &Options::default()).unwrap();
+    assert_eq!(report.runs[0].result.as_ref().unwrap().exit_code, 7);
+    assert_eq!(report.runs[1].result, Err(RunError::StepLimit(5000)));
+    assert_eq!(report.runs[2].result, Err(RunError::StepLimit(5000)));
+    assert!(report.divergence.is_none());
+}
+
+#[test]
+fn running_out_of_stack_is_inconclusive() {
+    // every call keeps the AST interpreter in the middle of 20 additions,
+    // which takes more of its stack than of the guest's
+    let mut call = "f(n + 1)".to_string();
+    for _ in 0..20 {
+        call = format!("n + ({})", call);
+    }
+    let source = format!("int f(int n) {{ return {}; }} int main() {{ return f(0); }}", call);
+    let report = diff(&source, b"", 1_000_000, &Options::default()).unwrap();
+    assert!(
+        matches!(report.runs[0].result, Err(RunError::StackOverflow(_))),
+        "{:?}",
+        report.runs[0].result
+    );
+    assert_eq!(report.runs[1].result, Err(RunError::StepLimit(1_000_000)));
+    assert!(report.divergence.is_none());
+    assert!(!report.conclusive());
+}
+
+#[test]
+fn backend_panics_are_divergences_of_the_emulator() {
+    // the backend only supports aggregates as initializers of globals
+    let koopa = r#"
+        fun @main(): i32 {
+        %entry:
+          %a = alloc [i32, 2]
+          store {1, 2}, %a
+          %p = getelemptr %a, 1
+          %v = load %p
+          ret %v
+        }
+    "#;
+    let program = Driver::from(koopa).generate_program().unwrap();
+    let report = diff_koopa(&program, b"", DEFAULT_STEP_LIMIT);
+    let engines: Vec<_> = report.runs.iter().map(|run| run.engine).collect();
+    assert_eq!(engines, [Engine::Koopa, Engine::Riscv]);
+    assert_eq!(report.runs[0].result.as_ref().unwrap().exit_code, 2);
+    let divergence = report.divergence.unwrap();
+    assert_eq!(divergence.engine, Engine::Riscv);
+    assert!(divergence.reason.contains("backend panicked: "), "{}", divergence.reason);
+    // the code is the Koopa IR the backend panicked on
+    assert!(divergence.code.unwrap().contains("store {1, 2}, %"));
+}
@@ -0,0 +1,164 @@
+//! The RISC-V emulator runs the assembly of the backend with the SysY
+//! runtime library.
+
+use compiler::parser::asm::emu::run;
+use compiler::parser::runtime::{RunError, DEFAULT_STEP_LIMIT};
+use compiler::{compile, Output, Stage};
+
+fn asm(source: &str) -> String {
+    match compile(source, Stage::Riscv) {
+        Ok(Output::Riscv(asm)) => asm,
+        _ => panic!("failed to compile"),
+    }
+}
+
+#[test]
+fn runtime_calls_clobber_caller_saved_registers() {
+    // `ret` jumps to the `ra` of the call, `s1` survives, `t0` and `a1` do not
+    let asm = r#"
+  .text
+main:
+  mv s0, ra
+  li t0, 5
+  li a1, 6
+  li s1, 7
+  li a0, 1
+  call putint
+.Lafter:
+  mv t1, ra
+  mv ra, s0
+  la t2, .Lafter
+  bne t1, t2, .Lfail
+  li t2, 5
+  beq t0, t2, .Lfail
+  li t2, 6
+  beq a1, t2, .Lfail
+  mv a0, s1
+  ret
+.Lfail:
+  li a0, -1
+  ret
+"#;
+    assert_eq!(run(asm, b"", DEFAULT_STEP_LIMIT).unwrap().exit_code, 7);
+}
+
+#[test]
+fn void_runtime_calls_clobber_a0() {
+    let asm = "  .text\nmain:\n  mv s0, ra\n  li a0, 65\n  call putch\n  mv ra, s0\n  ret\n";
+    let outcome = run(asm, b"", DEFAULT_STEP_LIMIT).unwrap();
+    assert_eq!(outcome.output, b"A");
+    assert_ne!(outcome.exit_code, 65);
+}
+
+#[test]
+fn values_live_across_calls_survive_in_the_frame() {
+    // every operand of `+` is computed before the call of the other one
+    let source = r#"
+        int id(int x) { return x; }
+        int main() {
+            int a = getint();
+            int b = a * 3 + getint();
+            putint(b); putch(10);
+            return id(a) + getint() * id(b) + id(getint());
+        }
+    "#;
+    let outcome = run(&asm(source), b"2 1 10 100", DEFAULT_STEP_LIMIT).unwrap();
+    assert_eq!(outcome.output, b"7\n");
+    assert_eq!(outcome.exit_code, 2 + 10 * 7 + 100);
+}
+
+#[test]
+fn frames_with_stack_arguments_and_large_arrays() {
+    // offsets beyond 12-bit immediates, and arguments past `a7` are read
+    // from the frame of the caller
+    let source = r#"
+        int many(int a, int b, int c, int d, int e, int f, int g, int h, int i, int j) {
+            int big[1000];
+            big[999] = i * 10 + j;
+            big[0] = a + b + c + d + e + f + g + h;
+            return big[0] * 100 + big[999];
+        }
+        int depth(int n, int a[]) {
+            int local[600];
+            local[599] = n;
+            if (n == 0) return a[0];
+            return depth(n - 1, local) + local[599];
+        }
+        int main() {
+            int a[1] = {1000};
+            putint(many(1, 2, 3, 4, 5, 6, 7, 8, 9, 10)); putch(10);
+            return depth(10, a);
+        }
+    "#;
+    let outcome = run(&asm(source), b"", DEFAULT_STEP_LIMIT).unwrap();
+    assert_eq!(outcome.output, b"3700\n");
+    // `depth(0, local)` reads `local[0]` of the caller, which is 0
+    assert_eq!(outcome.exit_code, 55);
+}
+
+#[test]
+fn runtime_library_reads_and_writes_memory() {
+    let source = r#"
+        int g[4];
+        int main() {
+            int n = getarray(g);
+            putarray(n, g);
+            starttime();
+            int c = getch();
+            while (c != -1) { putch(c); c = getch(); }
+            stoptime();
+            return getint();
+        }
+    "#;
+    // `getch` reads the newline after the array, as `scanf` leaves it
+    let outcome = run(&asm(source), b"3 7 -8 9\nxy", DEFAULT_STEP_LIMIT).unwrap();
+    assert_eq!(outcome.output, b"3: 7 -8 9\n\nxy");
+    // the end of the input reads as 0
+    assert_eq!(outcome.exit_code, 0);
+}
+
+#[test]
+fn pseudo_instructions_expand_like_an_assembler() {
+    let asm = r#"
+  .data
+x:
+  .word 1, 2
+  .text
+  .globl main
+main:
+  li a0, 0x12345678   # lui and addi
+  li t0, -4096        # lui only
+  not t1, t0
+  srai a0, a0, 24
+  la t1, x            # lui and addi
+  lw t2, 4(t1)
+  add a0, a0, t2
+  bgtz a0, .Lend
+  neg a0, a0
+.Lend:
+  ret
+"#;
+    let outcome = run(asm, b"", DEFAULT_STEP_LIMIT).unwrap();
+    assert_eq!(outcome.exit_code, 0x12 + 2);
+    assert_eq!(outcome.steps, 11);
+}
+
+#[test]
+fn errors_are_reported() {
+    assert_eq!(
+        run("  .text\nmain:\n  lw a0, 0(zero)\n  ret\n", b"", DEFAULT_STEP_LIMIT),
+        Err(RunError::OutOfBounds("at 0x10000: address 0x0".into()))
+    );
+    assert_eq!(
+        run("  .text\nmain:\n  j main\n", b"", 1000),
+        Err(RunError::StepLimit(1000))
+    );
+    assert_eq!(
+        run("  .text\nmain:\n  call f\n", b"", DEFAULT_STEP_LIMIT),
+        Err(RunError::Invalid("line 3: undefined symbol `f`".into()))
+    );
+    assert_eq!(
+        run("  .text\nf:\n  ret\n", b"", DEFAULT_STEP_LIMIT),
+        Err(RunError::UndefinedFunction("main".into()))
+    );
+}
@@ -0,0 +1,81 @@
+//! Constants are folded with the integer semantics of Koopa IR and RV32IM,
+//! in the frontend and in the backend alike.
+
+mod common;
+
+use common::{compile, koopa};
+use compiler::parser::asm::visitor::Visitor;
+use koopa::front::Driver;
+
+#[test]
+fn constant_expressions_wrap_around() {
+    let text = koopa(
+        r#"
+        const int min = -2147483647 - 1;
+        const int a[5] = {min / -1, min % -1, 2147483647 + 1, -min, 65536 * 65536};
+        int main() { return a[0]; }
+        "#,
+    );
+    assert!(
+        text.contains("{-2147483648, 0, -2147483648, -2147483648, 0}"),
+        "{}",
+        text
+    );
+}
+
+#[test]
+fn logical_not_of_a_constant_is_0_or_1() {
+    let text = koopa("const int a[3] = {!5, !0, !!-7}; int main() { return a[0]; }");
+    assert!(text.contains("{0, 1, 1}"), "{}", text);
+    // an array size of `!0` is a single element
+    assert!(koopa("int a[!0]; int main() { return 0; }").contains("alloc [i32, 1]"));
+}
+
+#[test]
+fn division_by_zero_in_a_constant_is_an_error() {
+    for source in [
+        "const int a = 1 / 0; int main() { return a; }",
+        "const int a = 1 % (2 - 2); int main() { return a; }",
+        "int a[3 / 0]; int main() { return 0; }",
+    ] {
+        match compile("-koopa", source) {
+            Err(stderr) => assert!(
+                stderr.contains("error: division by zero in a constant expression"),
+                "{}",
+                stderr
+            ),
+            Ok(_) => panic!("`{}` was accepted", source),
+        }
+    }
+}
+
+#[test]
+fn backend_folds_like_the_hardware() {
+    // constant operands are folded, x / 0 is -1 and x % 0 is x on RV32IM
+    for (inst, expected) in [
+        ("div -2147483648, -1", i32::MIN),
+        ("mod -2147483648, -1", 0),
+        ("div 7, 0", -1),
+        ("mod 7, 0", 7),
+        ("mul 65536, 65536", 0),
+        ("sub -2147483648, 1", i32::MAX),
+        ("shl 1, 33", 2),
+        ("sar -8, 1", -4),
+        ("shr -8, 28", 15),
+    ] {
+        let text = format!("fun @main(): i32 {{\n%entry:\n  %0 = {}\n  ret %0\n}}\n", inst);
+        let program = Driver::from(text).generate_program().unwrap();
+        let mut asm = Vec::new();
+        Visitor.visit(&mut asm, &program).unwrap();
+        let asm = String::from_utf8(asm).unwrap();
+        let op = match inst.split(' ').next().unwrap() {
+            "mod" => "rem",
+            "shl" => "sll",
+            "shr" => "srl",
+            "sar" => "sra",
+            op => op,
+        };
+        assert!(!asm.contains(&format!("  {} ", op)), "{} is not folded:\n{}", inst, asm);
+        assert!(asm.contains(&format!(", {}\n", expected)), "{}:\n{}", inst, asm);
+    }
+}
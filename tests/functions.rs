@@ -0,0 +1,41 @@
+//! `void` functions, `return` without a value, and the implicit return at
+//! the end of a function.
+
+mod common;
+
+use common::{block, blocks, branches, compile, koopa, riscv};
+
+#[test]
+fn void_functions_return_without_a_value() {
+    let ir = koopa("void f(int a) { if (a) return; a = 1; } int main() { f(1); return 0; }");
+    assert!(ir.contains("fun @f(@a: i32) {"), "{}", ir);
+    let blocks = blocks(&ir);
+    let (then, end) = &branches(&ir)[0];
+    assert_eq!(block(&blocks, then), ["ret"]);
+    // the end of the body returns implicitly
+    assert_eq!(block(&blocks, end).last().unwrap(), "ret");
+    assert!(ir.contains("  call @f(1)\n"), "{}", ir);
+}
+
+#[test]
+fn falling_off_the_end_of_an_int_function_returns_0() {
+    let ir = koopa("int main() { int a = 1; if (a) return a; }");
+    let blocks = blocks(&ir);
+    let (_, end) = &branches(&ir)[0];
+    assert_eq!(block(&blocks, end), ["ret 0"]);
+    let asm = riscv("int main() { int a = 1; if (a) return a; }");
+    assert_eq!(asm.lines().filter(|line| line.trim() == "ret").count(), 2, "{}", asm);
+}
+
+#[test]
+fn return_values_must_match_the_function() {
+    for source in [
+        "void f() { return 1; } int main() { return 0; }",
+        "int f() { return; } int main() { return 0; }",
+    ] {
+        let stderr = compile("-koopa", source).unwrap_err();
+        assert!(stderr.contains("return statement does not match the function return type"), "{}", stderr);
+    }
+    let stderr = compile("-koopa", "void f() { } int main() { return f(); }").unwrap_err();
+    assert!(stderr.contains("void value not ignored as it ought to be"), "{}", stderr);
+}
@@ -0,0 +1,37 @@
+//! Global variables and constants, initialized with constant expressions and
+//! emitted in the data sections.
+
+mod common;
+
+use common::{koopa, riscv};
+
+#[test]
+fn globals_are_initialized_with_constants() {
+    let ir = koopa("const int N = 3 * 2; int g = N + 1; int z; int main() { return g + z + N; }");
+    assert!(ir.starts_with("global @g = alloc i32, 7\nglobal @z = alloc i32, zeroinit\n"), "{}", ir);
+    // constants are folded, they take no memory
+    assert!(!ir.contains("@N"), "{}", ir);
+    assert!(ir.contains(" = load @g\n") && ir.contains(" = load @z\n"), "{}", ir);
+}
+
+#[test]
+fn locals_shadow_globals() {
+    let ir = koopa("int g = 1; int f() { g = g + 1; return g; } int main() { int g = 2; return g + f(); }");
+    assert!(ir.lines().any(|line| line.trim().starts_with("store %") && line.ends_with(", @g")), "{}", ir);
+    // the local gets a name of its own
+    let local = ir.lines().find_map(|line| line.trim().strip_suffix(" = alloc i32")).unwrap();
+    assert_ne!(local, "@g");
+    assert!(ir.contains(&format!("store 2, {}\n", local)), "{}", ir);
+}
+
+#[test]
+fn globals_are_emitted_in_data_sections() {
+    let asm = riscv("int g = 7; int z; int main() { z = g + 1; return z; }");
+    assert!(asm.contains("  .data\n  .global g\ng:\n  .word 7\n"), "{}", asm);
+    assert!(asm.contains("  .bss\n  .global z\nz:\n  .zero 4\n"), "{}", asm);
+    for global in ["g", "z"] {
+        let suffix = format!(", {}", global);
+        let la = asm.lines().find(|line| line.trim().starts_with("la ") && line.ends_with(&suffix));
+        assert!(la.is_some(), "{}", asm);
+    }
+}
@@ -0,0 +1,143 @@
+//! Memory and allocation semantics of the Koopa IR interpreter.
+
+use compiler::parser::interp::run;
+use compiler::parser::runtime::{RunError, DEFAULT_STEP_LIMIT};
+use koopa::front::Driver;
+use koopa::ir::Program;
+
+fn program(koopa: &str) -> Program {
+    Driver::from(koopa).generate_program().unwrap()
+}
+
+#[test]
+fn alloc_in_a_loop_reuses_its_memory() {
+    // a fresh allocation per iteration would make every load 0
+    let program = program(
+        r#"
+        fun @main(): i32 {
+        %entry:
+          jump %loop(0)
+
+        %loop(%i: i32):
+          %p = alloc i32
+          %v = load %p
+          %w = add %v, 1
+          store %w, %p
+          %n = add %i, 1
+          %c = lt %n, 1000
+          br %c, %loop(%n), %end(%w)
+
+        %end(%r: i32):
+          ret %r
+        }
+        "#,
+    );
+    assert_eq!(run(&program, b"", DEFAULT_STEP_LIMIT).unwrap().exit_code, 1000);
+}
+
+#[test]
+fn every_call_gets_its_own_allocations() {
+    let program = program(
+        r#"
+        fun @f(%n: i32): i32 {
+        %entry:
+          %p = alloc i32
+          store %n, %p
+          %c = gt %n, 0
+          br %c, %rec, %end
+
+        %rec:
+          %m = sub %n, 1
+          %x = call @f(%m)
+          jump %end
+
+        %end:
+          %r = load %p
+          ret %r
+        }
+
+        fun @main(): i32 {
+        %entry:
+          %r = call @f(10)
+          ret %r
+        }
+        "#,
+    );
+    assert_eq!(run(&program, b"", DEFAULT_STEP_LIMIT).unwrap().exit_code, 10);
+}
+
+#[test]
+fn arrays_are_passed_to_the_runtime_library_by_pointer() {
+    let program = program(
+        r#"
+        decl @getarray(*i32): i32
+        decl @putarray(i32, *i32)
+
+        global @g = alloc [i32, 4], {1, 2, 3, 4}
+
+        fun @main(): i32 {
+        %entry:
+          %a = alloc [i32, 4]
+          %p = getelemptr %a, 0
+          %n = call @getarray(%p)
+          %q = getelemptr @g, 1
+          call @putarray(3, %q)
+          call @putarray(%n, %p)
+          ret %n
+        }
+        "#,
+    );
+    let outcome = run(&program, b"2 7 8", DEFAULT_STEP_LIMIT).unwrap();
+    assert_eq!(outcome.output, b"3: 2 3 4\n2: 7 8\n");
+    assert_eq!(outcome.exit_code, 2);
+}
+
+#[test]
+fn access_out_of_an_allocation_is_an_error() {
+    // the allocations are adjacent in the address space of a real machine
+    let program = program(
+        r#"
+        fun @main(): i32 {
+        %entry:
+          %a = alloc [i32, 2]
+          %b = alloc i32
+          %p = getelemptr %a, 2
+          store 1, %p
+          ret 0
+        }
+        "#,
+    );
+    assert_eq!(
+        run(&program, b"", DEFAULT_STEP_LIMIT),
+        Err(RunError::OutOfBounds("in `@main`: word 2 of an allocation of 2 words".into()))
+    );
+}
+
+#[test]
+fn division_by_zero_and_step_limit_stop_the_program() {
+    let program1 = program(
+        r#"
+        fun @main(): i32 {
+        %entry:
+          %x = mod 1, 0
+          ret %x
+        }
+        "#,
+    );
+    assert_eq!(
+        run(&program1, b"", DEFAULT_STEP_LIMIT),
+        Err(RunError::DivisionByZero("@main".into()))
+    );
+    let program2 = program(
+        r#"
+        fun @main(): i32 {
+        %entry:
+          jump %loop
+
+        %loop:
+          jump %loop
+        }
+        "#,
+    );
+    assert_eq!(run(&program2, b"", 1000), Err(RunError::StepLimit(1000)));
+}
@@ -0,0 +1,130 @@
+//! Koopa IR text can be fed to the backend directly.
+
+mod common;
+
+use common::check_labels;
+use compiler::driver::{load_koopa, riscv_text};
+use compiler::parser::asm::emu;
+use compiler::parser::interp;
+use compiler::parser::runtime::DEFAULT_STEP_LIMIT;
+use koopa::front::Driver;
+use std::fs::write;
+
+const SOURCE: &str = r#"
+decl @putint(i32)
+
+fun @main(): i32 {
+%entry:
+  %0 = add 40, 2
+  call @putint(%0)
+  ret 0
+}
+"#;
+
+#[test]
+fn backend_runs_on_koopa_text() {
+    let path = std::env::temp_dir().join(format!("koopa-input-{}.koopa", std::process::id()));
+    write(&path, SOURCE).unwrap();
+    let program = load_koopa(path.to_str().unwrap()).unwrap().ok().unwrap();
+    let _ = std::fs::remove_file(&path);
+
+    let asm = riscv_text(&program);
+    assert!(asm.contains("main:"));
+    assert!(asm.contains("call putint"));
+    assert!(!asm.contains("putint:"));
+}
+
+#[test]
+fn invalid_koopa_text_is_rejected() {
+    let path = std::env::temp_dir().join(format!("koopa-invalid-{}.koopa", std::process::id()));
+    write(&path, "fun @main(): i32 {\n%entry:\n  ret %nope\n}\n").unwrap();
+    let result = load_koopa(path.to_str().unwrap()).unwrap();
+    let _ = std::fs::remove_file(&path);
+    assert_eq!(result.err(), Some(1));
+}
+
+/// Assembly of the IR, every label it jumps to must be defined.
+fn asm(koopa: &str) -> String {
+    let program = Driver::from(koopa).generate_program().unwrap();
+    let asm = riscv_text(&program);
+    check_labels(&asm);
+    asm
+}
+
+/// Runs the IR with the Koopa interpreter and its assembly with the emulator.
+fn run_both(koopa: &str, input: &[u8]) -> (i32, i32) {
+    let program = Driver::from(koopa).generate_program().unwrap();
+    let expected = interp::run(&program, input, DEFAULT_STEP_LIMIT).unwrap();
+    let outcome = emu::run(&asm(koopa), input, DEFAULT_STEP_LIMIT).unwrap();
+    (outcome.exit_code, expected.exit_code)
+}
+
+#[test]
+fn bitwise_operations_on_variables_are_lowered() {
+    let source = r#"
+        decl @getint(): i32
+
+        fun @main(): i32 {
+        %entry:
+          %x = call @getint()
+          %n = call @getint()
+          %a = xor %x, 255
+          %b = shl %x, %n
+          %c = shr %x, %n
+          %d = sar %x, %n
+          %e = add %a, %b
+          %f = add %c, %d
+          %g = xor %e, %f
+          ret %g
+        }
+    "#;
+    let asm = asm(source);
+    for op in ["xor", "sll", "srl", "sra"] {
+        assert!(asm.contains(&format!("  {} ", op)), "no `{}`:\n{}", op, asm);
+    }
+    let (actual, expected) = run_both(source, b"-100 3");
+    assert_eq!(actual, expected);
+    let x = -100i32;
+    assert_eq!(actual, ((x ^ 255) + (x << 3)) ^ (((x as u32) >> 3) as i32 + (x >> 3)));
+}
+
+#[test]
+fn block_parameters_are_lowered() {
+    // the arguments of the back edge swap the parameters, and the undefined
+    // argument of the first edge is never used
+    let source = r#"
+        decl @getint(): i32
+
+        fun @main(): i32 {
+        %entry:
+          %n = call @getint()
+          jump %loop(%n, 1, 0, undef)
+
+        %loop(%i: i32, %a: i32, %b: i32, %unused: i32):
+          %c = gt %i, 0
+          %i1 = sub %i, 1
+          %s = add %a, %b
+          br %c, %loop(%i1, %s, %a, %c), %end(%b)
+
+        %end(%r: i32):
+          ret %r
+        }
+    "#;
+    // the entry block and the back edge both jump to the loop
+    let asm = asm(source);
+    let jumps = asm.lines().filter(|line| line.starts_with("  j ") && line.ends_with(".loop"));
+    assert_eq!(jumps.count(), 2, "{}", asm);
+    for (n, fib) in [(0, 0), (1, 1), (10, 55), (20, 6765)] {
+        assert_eq!(run_both(source, n.to_string().as_bytes()), (fib, fib), "fib({})", n);
+    }
+}
+
+#[test]
+fn arrays_as_values_are_rejected() {
+    let path = std::env::temp_dir().join(format!("koopa-array-{}.koopa", std::process::id()));
+    let source = "fun @main(): i32 {\n%entry:\n  %a = alloc [i32, 2]\n  %v = load %a\n  ret 0\n}\n";
+    write(&path, source).unwrap();
+    let result = load_koopa(path.to_str().unwrap()).unwrap();
+    let _ = std::fs::remove_file(&path);
+    assert_eq!(result.err(), Some(1));
+}
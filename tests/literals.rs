@@ -0,0 +1,65 @@
+//! Integer literal edge cases, every `tests/literals/<name>.c` is compiled to
+//! Koopa IR and compared with `<name>.koopa`, or with the diagnostics in
+//! `<name>.err` if it is expected to be rejected.
+
+use std::fs::{read_dir, read_to_string};
+use std::path::Path;
+use std::process::Command;
+
+#[test]
+fn literals() {
+    let mut cases: Vec<_> = read_dir("tests/literals")
+        .unwrap()
+        .map(|entry| entry.unwrap().path())
+        .filter(|path| path.extension().is_some_and(|ext| ext == "c"))
+        .collect();
+    cases.sort();
+    assert!(!cases.is_empty());
+
+    let mut failed = Vec::new();
+    for case in cases.iter() {
+        if !passes(case) {
+            failed.push(case.display().to_string());
+        }
+    }
+    assert!(failed.is_empty(), "failed cases: {}", failed.join(", "));
+}
+
+fn passes(case: &Path) -> bool {
+    let output_path = std::env::temp_dir().join(format!(
+        "literals-{}-{}.koopa",
+        std::process::id(),
+        case.file_stem().unwrap().to_string_lossy()
+    ));
+    let output = Command::new(env!("CARGO_BIN_EXE_compiler"))
+        .arg("-koopa")
+        .arg(case)
+        .arg("-o")
+        .arg(&output_path)
+        .output()
+        .unwrap();
+    let stderr = String::from_utf8_lossy(&output.stderr);
+
+    let err_path = case.with_extension("err");
+    if err_path.exists() {
+        let expected = read_to_string(err_path).unwrap();
+        if output.status.success() || stderr != expected {
+            eprintln!("{}: expected errors\n{}\ngot\n{}", case.display(), expected, stderr);
+            return false;
+        }
+        return true;
+    }
+
+    if !output.status.success() {
+        eprintln!("{}: failed to compile\n{}", case.display(), stderr);
+        return false;
+    }
+    let expected = read_to_string(case.with_extension("koopa")).unwrap();
+    let actual = read_to_string(&output_path).unwrap();
+    let _ = std::fs::remove_file(&output_path);
+    if actual != expected {
+        eprintln!("{}: expected\n{}\ngot\n{}", case.display(), expected, actual);
+        return false;
+    }
+    true
+}
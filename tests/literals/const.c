@@ -0,0 +1,6 @@
+// literals in constant expressions evaluated by the compiler
+const int min = -2147483648;
+const int neg = -0x80000000;
+const int arr[2] = {0xFFFFFFFF, -2147483648};
+int a[3] = {min, neg, arr[0]};
+int main() { return arr[1]; }
@@ -0,0 +1,8 @@
+// decimal literals at the edges of the 32-bit range
+int zero = 0;
+int one = 1;
+int int_max = 2147483647;
+int int_min = -2147483648;
+int wrapped = 2147483648;
+int uint_max = 4294967295;
+int main() { return -2147483648; }
@@ -0,0 +1,9 @@
+// hex literals in either case, wrapping like unsigned 32-bit values
+int zero = 0x0;
+int upper = 0XFF;
+int mixed = 0xaBcD;
+int int_max = 0x7FFFFFFF;
+int int_min = 0x80000000;
+int minus_one = 0xFFFFFFFF;
+int padded = 0x00000000FFFFFFFF;
+int main() { return 0xffffffff; }
@@ -0,0 +1,9 @@
+// octal literals, a leading 0 alone is octal zero
+int zero = 0;
+int zeros = 000;
+int seven = 07;
+int eight = 010;
+int int_max = 017777777777;
+int int_min = -020000000000;
+int uint_max = 037777777777;
+int main() { return 0777; }
@@ -0,0 +1,5 @@
+// every literal which does not fit in 32 bits is reported
+int a = 4294967296;
+int b = 040000000000;
+int c = 0x100000000;
+int main() { return 99999999999999999999; }
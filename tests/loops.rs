@@ -0,0 +1,68 @@
+//! `while` loops re-evaluate their condition in a block of its own, which
+//! `continue` jumps back to, while `break` jumps past the loop.
+
+mod common;
+
+use common::{block, blocks, branches, compile, koopa, riscv};
+
+/// Label of the block which evaluates the condition of the first loop and
+/// the labels of its body and of the block after it.
+fn first_loop(ir: &str) -> (String, String, String) {
+    let blocks = blocks(ir);
+    let (cond, insts) = blocks
+        .iter()
+        .find(|(_, insts)| insts.last().unwrap().starts_with("br "))
+        .unwrap();
+    let (body, end) = branches(&insts.join("\n"))[0].clone();
+    (cond.clone(), body, end)
+}
+
+#[test]
+fn loop_bodies_jump_back_to_the_condition() {
+    let ir = koopa("int main() { int i = 0; while (i < 10) i = i + 1; return i; }");
+    let (cond, body, end) = first_loop(&ir);
+    let blocks = blocks(&ir);
+    assert_eq!(blocks[0].1.last().unwrap(), &format!("jump {}", cond));
+    assert_eq!(block(&blocks, &body).last().unwrap(), &format!("jump {}", cond));
+    assert!(block(&blocks, &end).last().unwrap().starts_with("ret "));
+}
+
+#[test]
+fn break_and_continue_jump_to_the_innermost_loop() {
+    let source = "int main() {
+        int i = 0;
+        while (i < 10)
+            while (i < 20)
+                if (i == 5) break; else if (i == 7) continue; else i = i + 1;
+        return i;
+    }";
+    let ir = koopa(source);
+    let blocks = blocks(&ir);
+    let branches = branches(&ir);
+    // the second branch is the condition of the inner loop
+    let (inner, _) = blocks
+        .iter()
+        .filter(|(_, insts)| insts.last().unwrap().starts_with("br "))
+        .nth(1)
+        .unwrap();
+    let end = &branches[1].1;
+    assert_eq!(block(&blocks, &branches[2].0), [format!("jump {}", end)]);
+    assert_eq!(block(&blocks, &branches[3].0), [format!("jump {}", inner)]);
+}
+
+#[test]
+fn break_and_continue_outside_of_a_loop_are_errors() {
+    for (source, message) in [
+        ("int main() { break; return 0; }", "`break` statement not within a loop"),
+        ("int main() { continue; return 0; }", "`continue` statement not within a loop"),
+    ] {
+        let stderr = compile("-koopa", source).unwrap_err();
+        assert!(stderr.contains(message), "{}", stderr);
+    }
+}
+
+#[test]
+fn loops_are_lowered_to_labels() {
+    let asm = riscv("int main() { int i = 0; while (i < 10) if (i == 5) break; else i = i + 1; return i; }");
+    assert!(asm.lines().any(|line| line.trim().starts_with("bnez ")), "{}", asm);
+}
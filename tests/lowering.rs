@@ -0,0 +1,178 @@
+//! Shape of the Koopa IR generated for SysY constructs.
+
+mod common;
+
+use common::{block, blocks, branches, compile, koopa};
+
+/// Names of the allocs in the entry block, and the number of allocs in the
+/// other blocks.
+fn allocs(ir: &str) -> (Vec<String>, usize) {
+    let blocks = blocks(ir);
+    let names = |insts: &[String]| -> Vec<String> {
+        insts
+            .iter()
+            .filter_map(|inst| Some(inst.split_once(" = alloc ")?.0.to_string()))
+            .collect()
+    };
+    let elsewhere = blocks[1..].iter().map(|(_, insts)| names(insts).len()).sum();
+    (names(&blocks[0].1), elsewhere)
+}
+
+#[test]
+fn locals_in_loops_are_allocated_in_the_entry_block() {
+    let ir = koopa(
+        r#"
+        int main() {
+            int i = 0;
+            while (i < 10) {
+                int t = i;
+                if (t > 5) { int u = t; i = i + u; }
+                i = i + 1;
+            }
+            return i;
+        }
+        "#,
+    );
+    assert_eq!(allocs(&ir).0.len(), 3);
+    assert_eq!(allocs(&ir).1, 0);
+}
+
+#[test]
+fn inner_blocks_shadow_outer_names() {
+    let ir = koopa(
+        r#"
+        int main() {
+            int a = 1;
+            {
+                int a = 2;
+                { int a = 3; a = a + 1; }
+                a = a + 1;
+            }
+            return a;
+        }
+        "#,
+    );
+    let (names, _) = allocs(&ir);
+    assert_eq!(names.len(), 3);
+    let stores: Vec<_> = ir.lines().filter_map(|line| line.trim().strip_prefix("store ")).collect();
+    assert_eq!(
+        stores[..3],
+        [
+            format!("1, {}", names[0]),
+            format!("2, {}", names[1]),
+            format!("3, {}", names[2])
+        ]
+    );
+    // `a = a + 1` updates the innermost `a`, the `return` reads the outer one
+    assert!(stores[3].ends_with(&format!(", {}", names[2])));
+    assert!(stores[4].ends_with(&format!(", {}", names[1])));
+    let ret = ir.lines().rev().find(|line| line.contains(" = load ")).unwrap();
+    assert!(ret.ends_with(&format!("load {}", names[0])), "{}", ir);
+}
+
+#[test]
+fn names_can_not_be_redeclared_in_the_same_block() {
+    let stderr = compile("-koopa", "int main() { int b = 1; { int b = 2; } int b = 3; return b; }").unwrap_err();
+    assert!(stderr.contains("redeclaration of `b`"), "{}", stderr);
+}
+
+#[test]
+fn functions_can_be_called_before_their_definition() {
+    // not checked with the parser of the koopa crate, which only resolves
+    // calls of functions defined before
+    let ir = compile(
+        "-koopa",
+        r#"
+        int is_even(int n) { if (n == 0) return 1; return is_odd(n - 1); }
+        int is_odd(int n) { if (n == 0) return 0; return is_even(n - 1); }
+        int main() { return is_even(10); }
+        "#,
+    )
+    .unwrap();
+    assert!(ir.contains("= call @is_odd(%"), "{}", ir);
+    assert!(ir.contains("= call @is_even(%"), "{}", ir);
+    assert!(ir.contains("= call @is_even(10)"), "{}", ir);
+}
+
+#[test]
+fn parameters_are_spilled_so_they_can_be_assigned() {
+    let ir = koopa("int add(int a, int b) { a = a + b; return a; } int main() { return add(1, 2); }");
+    let blocks = blocks(&ir);
+    assert_eq!(blocks[0].1[..4], ["%a = alloc i32", "%b = alloc i32", "store @a, %a", "store @b, %b"]);
+}
+
+#[test]
+fn calls_of_undeclared_functions_are_errors() {
+    let stderr = compile("-koopa", "int main() { return g(); }").unwrap_err();
+    assert!(stderr.contains("call to undeclared function `g`"), "{}", stderr);
+}
+
+#[test]
+fn short_circuit_results_are_allocated_in_the_entry_block() {
+    let ir = koopa(
+        r#"
+        int main() {
+            int i = 0;
+            int n = 0;
+            while (i < 10) {
+                if (i > 2 && i < 8 || i == 9) n = n + 1;
+                i = i + 1;
+            }
+            return n;
+        }
+        "#,
+    );
+    assert_eq!(allocs(&ir).0.len(), 4);
+    assert_eq!(allocs(&ir).1, 0);
+}
+
+/// Whether the instructions call `@f`.
+fn calls_f(insts: &[String]) -> bool {
+    insts.iter().any(|inst| inst.contains("call @f()"))
+}
+
+#[test]
+fn and_only_evaluates_its_right_operand_if_the_left_one_is_true() {
+    let ir = koopa("int f() { return 1; } int main() { int a = 0; return a && f(); }");
+    let blocks = blocks(&ir);
+    let (rhs, end) = &branches(&ir)[0];
+    assert_eq!(blocks.iter().filter(|(_, insts)| calls_f(insts)).count(), 1);
+    assert!(calls_f(block(&blocks, rhs)));
+    assert!(block(&blocks, end).last().unwrap().starts_with("ret "));
+}
+
+#[test]
+fn or_only_evaluates_its_right_operand_if_the_left_one_is_false() {
+    let ir = koopa("int f() { return 1; } int main() { int a = 1; return a || f(); }");
+    let blocks = blocks(&ir);
+    let (end, rhs) = &branches(&ir)[0];
+    assert_eq!(blocks.iter().filter(|(_, insts)| calls_f(insts)).count(), 1);
+    assert!(calls_f(block(&blocks, rhs)));
+    assert!(block(&blocks, end).last().unwrap().starts_with("ret "));
+}
+
+#[test]
+fn the_runtime_library_is_declared() {
+    let ir = koopa("int main() { int a[2]; putarray(getarray(a), a); putch(getch()); putint(getint()); return 0; }");
+    for decl in [
+        "decl @getint(): i32",
+        "decl @getch(): i32",
+        "decl @getarray(*i32): i32",
+        "decl @putint(i32)",
+        "decl @putch(i32)",
+        "decl @putarray(i32, *i32)",
+        "decl @_sysy_starttime(i32)",
+        "decl @_sysy_stoptime(i32)",
+    ] {
+        assert!(ir.lines().any(|line| line == decl), "{}", ir);
+    }
+    assert!(ir.contains("  call @putarray(%1, %2)\n"), "{}", ir);
+}
+
+#[test]
+fn timer_calls_pass_their_line_number() {
+    let ir = koopa("int f() { return 1; }\nint main() {\n  starttime();\n  f();\n\n  stoptime();\n  return 0;\n}\n");
+    assert!(ir.contains("call @_sysy_starttime(3)"), "{}", ir);
+    assert!(ir.contains("call @f()"), "{}", ir);
+    assert!(ir.contains("call @_sysy_stoptime(6)"), "{}", ir);
+}
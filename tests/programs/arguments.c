@@ -0,0 +1,14 @@
+int many(int a, int b, int c, int d, int e, int f, int g, int h, int i, int j) {
+    return a - b + c - d + e - f + g - h + i * j;
+}
+
+int pick(int a[][3], int i, int j) {
+    return a[i][j];
+}
+
+int main() {
+    int m[2][3] = {1, 2, 3, {4}};
+    putint(many(1, 2, 3, 4, 5, 6, 7, 8, 9, 10));
+    putch(10);
+    return pick(m, 1, 0) * 10 + pick(m, 0, 2);
+}
@@ -0,0 +1,24 @@
+int a[10];
+
+int fib(int n) {
+    if (n < 2) return n;
+    return fib(n - 1) + fib(n - 2);
+}
+
+int f(int x) {
+    if (x > 0 && a[x - 1] == 0) {
+        a[x - 1] = x;
+        return f(x - 1) + x;
+    }
+    return 0;
+}
+
+int main() {
+    int n = getint();
+    putint(fib(n));
+    putch(10);
+    putint(f(n));
+    putch(10);
+    putarray(10, a);
+    return a[4];
+}
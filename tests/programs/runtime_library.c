@@ -0,0 +1,33 @@
+const int N = 3;
+int g[2][N] = {{1, 2}, {3}};
+int zero[100];
+
+int sum(int a[], int n) {
+    int i = 0, s = 0;
+    while (i < n) {
+        s = s + a[i];
+        i = i + 1;
+    }
+    return s;
+}
+
+void fill(int a[][N], int v) {
+    a[1][2] = v;
+}
+
+int main() {
+    int a[8];
+    int n = getarray(a);
+    putint(sum(a, n));
+    putch(10);
+    fill(g, 7);
+    putarray(N, g[1]);
+    putint(getint() + zero[99]);
+    putch(10);
+    int c = getch();
+    while (c != -1) {
+        if (c > 32) putch(c);
+        c = getch();
+    }
+    return -2147483648 / -1 + 300 % 7;
+}
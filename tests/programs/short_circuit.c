@@ -0,0 +1,28 @@
+int calls;
+
+int side(int x) {
+    calls = calls + 1;
+    return x;
+}
+
+int main() {
+    int x = 1;
+    {
+        int x = 2;
+        x = x + 1;
+    }
+    if (side(0) && side(1) || side(1) || side(1)) x = x + 10;
+    int i = 0;
+    while (i < 5) {
+        i = i + 1;
+        if (i == 2) continue;
+        if (!(i < 4) || side(0)) break;
+    }
+    putint(x);
+    putch(32);
+    putint(calls);
+    putch(32);
+    putint(i);
+    putch(10);
+    return !5 + !0 * 2;
+}
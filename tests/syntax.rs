@@ -0,0 +1,31 @@
+//! Programs which the parser accepts or rejects.
+
+mod common;
+
+use common::{compile, koopa};
+
+#[test]
+fn comma_separated_lists_may_be_empty() {
+    koopa("int f() { return 0; } int main() { return f(); }");
+}
+
+#[test]
+fn trailing_commas_are_syntax_errors() {
+    for source in [
+        "int f(int a,) { return a; } int main() { return 0; }",
+        "int f(int a) { return a; } int main() { return f(1,); }",
+        "int f(,) { return 0; } int main() { return 0; }",
+    ] {
+        assert!(compile("-koopa", source).is_err(), "`{}` was accepted", source);
+    }
+}
+
+#[test]
+fn errors_are_reported_in_source_order() {
+    // the error in the parameter list is only recovered from after the body
+    let err = compile("-koopa", "int f(int a,) { return a +; }\nint main() { return 0; }")
+        .unwrap_err();
+    let first = err.find(":1:13").expect("missing error in the parameters");
+    let second = err.find(":1:27").expect("missing error in the body");
+    assert!(first < second, "{}", err);
+}
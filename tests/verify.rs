@@ -0,0 +1,200 @@
+//! The Koopa IR verifier accepts what the frontend generates and rejects
+//! broken IR built by hand.
+
+use compiler::parser::verify::verify;
+use compiler::{compile_with, Options, Output, Stage};
+use koopa::front::Driver;
+use koopa::ir::builder_traits::*;
+use koopa::ir::{BasicBlock, BinaryOp, Function, FunctionData, Program, Type, Value};
+
+/// A program with `fun @main(): i32` and its entry block.
+fn program() -> (Program, Function, BasicBlock) {
+    let mut program = Program::new();
+    let main = program.new_func(FunctionData::new("@main".into(), Vec::new(), Type::get_i32()));
+    let entry = new_bb(&mut program, main, "%entry");
+    (program, main, entry)
+}
+
+fn new_bb(program: &mut Program, func: Function, name: &str) -> BasicBlock {
+    let data = program.func_mut(func);
+    let bb = data.dfg_mut().new_bb().basic_block(Some(name.into()));
+    data.layout_mut().bbs_mut().push_key_back(bb).unwrap();
+    bb
+}
+
+fn push(program: &mut Program, func: Function, bb: BasicBlock, inst: Value) {
+    let data = program.func_mut(func);
+    data.layout_mut().bb_mut(bb).insts_mut().push_key_back(inst).unwrap();
+}
+
+fn ret_zero(program: &mut Program, func: Function, bb: BasicBlock) {
+    let dfg = program.func_mut(func).dfg_mut();
+    let zero = dfg.new_value().integer(0);
+    let ret = dfg.new_value().ret(Some(zero));
+    push(program, func, bb, ret);
+}
+
+/// Verifies the program and returns the single error message.
+fn error(program: &Program) -> String {
+    let errors = verify(program).unwrap_err();
+    assert_eq!(errors.len(), 1, "{:?}", errors);
+    errors[0].to_string()
+}
+
+#[test]
+fn generated_ir_is_valid() {
+    let source = r#"
+        int g[2][3] = {{1}, {2, 3}};
+        int f(int a[][3], int n) {
+            int s = 0;
+            while (n > 0) {
+                n = n - 1;
+                if (a[n][0] || n && !s) s = s + a[n][1];
+                else continue;
+                if (s > 100) break;
+            }
+            return s;
+        }
+        void p() { putint(f(g, 2)); return; }
+        int main() { p(); return f(g, 1); }
+    "#;
+    match compile_with(source, Stage::Koopa, &Options { verify: true }) {
+        Ok(Output::Koopa(program)) => assert!(verify(&program).is_ok()),
+        _ => panic!("expected a valid program"),
+    }
+}
+
+#[test]
+fn missing_terminator() {
+    let (mut program, main, entry) = program();
+    let dfg = program.func_mut(main).dfg_mut();
+    let one = dfg.new_value().integer(1);
+    let add = dfg.new_value().binary(BinaryOp::Add, one, one);
+    push(&mut program, main, entry, add);
+    assert_eq!(
+        error(&program),
+        "invalid Koopa IR in `@main`: basic block `%entry` does not end with a terminator"
+    );
+}
+
+#[test]
+fn instruction_after_terminator() {
+    let (mut program, main, entry) = program();
+    ret_zero(&mut program, main, entry);
+    ret_zero(&mut program, main, entry);
+    assert!(error(&program).contains("has instructions after its terminator"));
+}
+
+#[test]
+fn store_type_mismatch() {
+    let (mut program, main, entry) = program();
+    let dfg = program.func_mut(main).dfg_mut();
+    let ptr = dfg.new_value().alloc(Type::get_i32());
+    let value = dfg.new_value().load(ptr);
+    let store = dfg.new_value().store(value, ptr);
+    // turn the stored value into a pointer behind the builder's back
+    dfg.replace_value_with(value).alloc(Type::get_i32());
+    for inst in [ptr, value, store] {
+        push(&mut program, main, entry, inst);
+    }
+    ret_zero(&mut program, main, entry);
+    assert_eq!(
+        error(&program),
+        "invalid Koopa IR in `@main`: value of store in `%entry` has type *i32, expected i32"
+    );
+}
+
+#[test]
+fn use_not_dominated_by_definition() {
+    let (mut program, main, entry) = program();
+    let then_bb = new_bb(&mut program, main, "%then");
+    let end_bb = new_bb(&mut program, main, "%end");
+    let dfg = program.func_mut(main).dfg_mut();
+    let one = dfg.new_value().integer(1);
+    let br = dfg.new_value().branch(one, then_bb, end_bb);
+    let sum = dfg.new_value().binary(BinaryOp::Add, one, one);
+    let jump = dfg.new_value().jump(end_bb);
+    let ret = dfg.new_value().ret(Some(sum));
+    push(&mut program, main, entry, br);
+    push(&mut program, main, then_bb, sum);
+    push(&mut program, main, then_bb, jump);
+    push(&mut program, main, end_bb, ret);
+    assert_eq!(
+        error(&program),
+        "invalid Koopa IR in `@main`: operand of ret in `%end` is not defined before it is used"
+    );
+}
+
+#[test]
+fn dominance_in_loops() {
+    // `%i` of the loop header dominates the exit, `%j` of the body does not
+    let source = |ret: &str| {
+        format!(
+            "fun @main(): i32 {{\n%entry:\n  jump %head\n%head:\n  %i = add 1, 2\n  br %i, %body, %end\n\
+             %body:\n  %j = add %i, 1\n  br %j, %head, %end\n%end:\n  ret {}\n}}\n",
+            ret
+        )
+    };
+    let program = Driver::from(source("%i")).generate_program().unwrap();
+    assert!(verify(&program).is_ok());
+    let program = Driver::from(source("%j")).generate_program().unwrap();
+    assert_eq!(
+        error(&program),
+        "invalid Koopa IR in `@main`: operand of ret in `%end` is not defined before it is used"
+    );
+}
+
+#[test]
+fn thousands_of_blocks_in_reverse_order() {
+    // the blocks run from the last in the layout to the first, which uses
+    // the value defined in the entry
+    let (mut program, main, entry) = program();
+    let bbs: Vec<BasicBlock> = (0..20000).map(|i| new_bb(&mut program, main, &format!("%b{}", i))).collect();
+    let dfg = program.func_mut(main).dfg_mut();
+    let one = dfg.new_value().integer(1);
+    let sum = dfg.new_value().binary(BinaryOp::Add, one, one);
+    let jump = dfg.new_value().jump(*bbs.last().unwrap());
+    let ret = dfg.new_value().ret(Some(sum));
+    push(&mut program, main, entry, sum);
+    push(&mut program, main, entry, jump);
+    push(&mut program, main, bbs[0], ret);
+    for i in 1..bbs.len() {
+        let jump = program.func_mut(main).dfg_mut().new_value().jump(bbs[i - 1]);
+        push(&mut program, main, bbs[i], jump);
+    }
+    assert!(verify(&program).is_ok());
+}
+
+#[test]
+fn jump_argument_mismatch() {
+    let (mut program, main, entry) = program();
+    let data = program.func_mut(main);
+    let end_bb = data
+        .dfg_mut()
+        .new_bb()
+        .basic_block_with_params(Some("%end".into()), vec![Type::get_i32()]);
+    data.layout_mut().bbs_mut().push_key_back(end_bb).unwrap();
+    let dfg = program.func_mut(main).dfg_mut();
+    let one = dfg.new_value().integer(1);
+    let arg = dfg.new_value().binary(BinaryOp::Add, one, one);
+    let jump = dfg.new_value().jump_with_args(end_bb, vec![arg]);
+    dfg.replace_value_with(arg).alloc(Type::get_i32());
+    push(&mut program, main, entry, arg);
+    push(&mut program, main, entry, jump);
+    ret_zero(&mut program, main, end_bb);
+    assert_eq!(
+        error(&program),
+        "invalid Koopa IR in `@main`: argument of jump from `%entry` to `%end` has type *i32, expected i32"
+    );
+}
+
+#[test]
+fn missing_callee() {
+    let (mut program, main, entry) = program();
+    let callee = program.new_func(FunctionData::new_decl("@f".into(), Vec::new(), Type::get_unit()));
+    let call = program.func_mut(main).dfg_mut().new_value().call(callee, Vec::new());
+    push(&mut program, main, entry, call);
+    ret_zero(&mut program, main, entry);
+    program.remove_func(callee);
+    assert!(error(&program).contains("callee of call in `%entry` does not exist"));
+}